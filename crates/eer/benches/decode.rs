@@ -0,0 +1,132 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use eer::synthetic::SyntheticEer;
+use eer::{decode_eer_frame, decode_frames, get_compression_params, SumOptions};
+use ndarray::Array2;
+use tiff::decoder::Decoder;
+use tiff::tags::Tag;
+
+const WIDTH: u32 = 1024;
+const HEIGHT: u32 = 1024;
+const FRAMES: usize = 20;
+const EVENTS_PER_FRAME: usize = 50_000;
+
+/// Writes the synthetic fixture once per bench run
+fn fixture(name: &str, rows_per_strip: u32) -> PathBuf {
+    let mut eer = SyntheticEer::new(WIDTH, HEIGHT);
+    eer.rows_per_strip = rows_per_strip;
+    eer.fill_random(FRAMES, EVENTS_PER_FRAME, 42);
+    let path = std::env::temp_dir().join(name);
+    eer.write(&path).expect("failed to write synthetic EER fixture");
+    path
+}
+
+/// Byte ranges of the strips of the decoder's current page
+fn strip_ranges(decoder: &mut Decoder<File>) -> Vec<(u64, u64)> {
+    let offsets = decoder.get_tag_u64_vec(Tag::StripOffsets).unwrap();
+    let sizes = decoder.get_tag_u64_vec(Tag::StripByteCounts).unwrap();
+    offsets.into_iter().zip(sizes).collect()
+}
+
+/// Reads the strips with one seek and read each, as `decode_eer_frame` does
+fn read_per_strip(file: &mut File, strips: &[(u64, u64)]) -> Vec<u8> {
+    let mut data = vec![0u8; strips.iter().map(|&(_, size)| size as usize).sum()];
+    let mut start = 0;
+    for &(offset, size) in strips {
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.read_exact(&mut data[start..start + size as usize]).unwrap();
+        start += size as usize;
+    }
+    data
+}
+
+/// Reads the span covering all strips with a single seek and read
+fn read_coalesced(file: &mut File, strips: &[(u64, u64)]) -> Vec<u8> {
+    let base = strips.iter().map(|&(offset, _)| offset).min().unwrap_or(0);
+    let end = strips.iter().map(|&(offset, size)| offset + size).max().unwrap_or(0);
+    let mut data = vec![0u8; (end - base) as usize];
+    file.seek(SeekFrom::Start(base)).unwrap();
+    file.read_exact(&mut data).unwrap();
+    data
+}
+
+/// Every `step`-th frame summed by `threads` workers, each with its own decoder and
+/// file handle and taking every `threads`-th of those frames
+fn parallel_sum(path: &Path, step: usize, threads: usize) -> Array2<u32> {
+    let shape = (HEIGHT as usize, WIDTH as usize);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|t| scope.spawn(move || {
+                let mut decoder = Decoder::new(File::open(path).unwrap()).unwrap();
+                let params = get_compression_params(&mut decoder).unwrap().params();
+                let mut file = File::open(path).unwrap();
+                let mut sum = Array2::<u32>::zeros(shape);
+                for frame in (t * step..FRAMES).step_by(threads * step) {
+                    decoder.seek_to_image(frame).unwrap();
+                    sum += &decode_eer_frame(&mut decoder, &params, &mut file, 1, 1).unwrap().mapv(u32::from);
+                }
+                sum
+            }))
+            .collect();
+        workers.into_iter().fold(Array2::zeros(shape), |sum, worker| sum + worker.join().unwrap())
+    })
+}
+
+fn bench_frame(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode_eer_frame");
+    // Many small strips stress the per-strip seek/read path, one strip reads the frame in one go
+    for (label, rows_per_strip) in [("strips_16_rows", 16), ("single_strip", HEIGHT)] {
+        let path = fixture(&format!("emfir_bench_frame_{}.eer", label), rows_per_strip);
+        for upsampling in [1, 4] {
+            group.bench_function(format!("{}/upsampling_{}", label, upsampling), |b| {
+                let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+                let params = get_compression_params(&mut decoder).unwrap().params();
+                let mut file = File::open(&path).unwrap();
+                b.iter(|| decode_eer_frame(&mut decoder, &params, &mut file, upsampling, 1).unwrap());
+            });
+        }
+        std::fs::remove_file(&path).ok();
+    }
+    group.finish();
+}
+
+fn bench_strip_reads(c: &mut Criterion) {
+    let path = fixture("emfir_bench_strip_reads.eer", 16);
+    let mut group = c.benchmark_group("strip_reads");
+    let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+    let strips = strip_ranges(&mut decoder);
+    let mut file = File::open(&path).unwrap();
+    group.bench_function("per_strip", |b| b.iter(|| read_per_strip(&mut file, &strips)));
+    group.bench_function("coalesced", |b| b.iter(|| read_coalesced(&mut file, &strips)));
+    group.finish();
+    std::fs::remove_file(&path).ok();
+}
+
+fn bench_sum(c: &mut Criterion) {
+    let path = fixture("emfir_bench_sum.eer", 16);
+    let mut group = c.benchmark_group("decode_frames");
+    group.sample_size(10);
+    for step in [1, 5] {
+        group.bench_function(format!("serial/skip_{}", step), |b| {
+            b.iter(|| {
+                let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+                let params = get_compression_params(&mut decoder).unwrap().params();
+                let mut file = File::open(&path).unwrap();
+                let options = SumOptions::default();
+                decode_frames(&mut decoder, &params, &mut file, FRAMES as u32, Some(step), 1, options).unwrap().0
+            });
+        });
+        group.bench_function(format!("parallel/skip_{}", step), |b| {
+            let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+            b.iter(|| parallel_sum(&path, step as usize, threads));
+        });
+    }
+    group.finish();
+    std::fs::remove_file(&path).ok();
+}
+
+criterion_group!(benches, bench_frame, bench_strip_reads, bench_sum);
+criterion_main!(benches);
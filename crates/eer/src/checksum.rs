@@ -0,0 +1,61 @@
+//! Whole-file BLAKE3 digests taken from the reads a decode makes anyway.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Wraps a reader and hashes the underlying stream from the start, in order, as it is
+/// read. A read that jumps ahead first hashes the bytes it skipped; reads behind the
+/// hashed prefix are passed through. [`HashingReader::finish`] hashes the rest, so the
+/// digest always covers the whole stream while each byte is read at most twice, and
+/// only once when the reads already go front to back.
+pub struct HashingReader<R> {
+    inner: R,
+    pos: u64,
+    hashed_to: u64,
+    hasher: blake3::Hasher,
+}
+
+impl<R: Read + Seek> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        HashingReader { inner, pos: 0, hashed_to: 0, hasher: blake3::Hasher::new() }
+    }
+
+    /// Hashes the bytes between the hashed prefix and the current position
+    fn fill_gap(&mut self) -> io::Result<()> {
+        self.inner.seek(SeekFrom::Start(self.hashed_to))?;
+        let mut gap = (&mut self.inner).take(self.pos - self.hashed_to);
+        self.hashed_to += io::copy(&mut gap, &mut self.hasher)?;
+        self.inner.seek(SeekFrom::Start(self.pos))?;
+        Ok(())
+    }
+
+    /// Hashes whatever the reads did not reach and returns the digest of the whole stream
+    pub fn finish(mut self) -> io::Result<blake3::Hash> {
+        self.inner.seek(SeekFrom::Start(self.hashed_to))?;
+        io::copy(&mut self.inner, &mut self.hasher)?;
+        Ok(self.hasher.finalize())
+    }
+}
+
+impl<R: Read + Seek> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos > self.hashed_to {
+            self.fill_gap()?;
+        }
+        let n = self.inner.read(buf)?;
+        let end = self.pos + n as u64;
+        // The gap is filled, so the read starts inside or at the end of the hashed prefix
+        if end > self.hashed_to {
+            self.hasher.update(&buf[(self.hashed_to - self.pos) as usize..n]);
+            self.hashed_to = end;
+        }
+        self.pos = end;
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for HashingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+}
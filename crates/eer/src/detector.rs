@@ -0,0 +1,77 @@
+//! Known detectors, to sanity-check files against the hardware that wrote them.
+
+use crate::{recommended_upsampling, CompressionParams, Upsampling};
+
+/// What a detector model is expected to produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectorPreset {
+    pub name: &'static str,
+    /// Sensor size in pixels
+    pub width: u32,
+    pub height: u32,
+    /// Highest super-resolution factor the detector records
+    pub max_upsampling: u32,
+}
+
+/// Presets by model; more specific names come first so "Falcon 4i" doesn't match
+/// "Falcon 4"
+const PRESETS: [DetectorPreset; 2] = [
+    DetectorPreset { name: "Falcon 4i", width: 4096, height: 4096, max_upsampling: 4 },
+    DetectorPreset { name: "Falcon 4", width: 4096, height: 4096, max_upsampling: 4 },
+];
+
+impl DetectorPreset {
+    /// Finds the preset for a model name as written in the metadata, ignoring case,
+    /// spaces and punctuation ("Falcon 4i", "FALCON-4I", "TFS Falcon4i"). None for
+    /// unknown detectors.
+    pub fn lookup(model: &str) -> Option<&'static DetectorPreset> {
+        // Letters and digits of the model, each marked if a separator followed it
+        let mut chars: Vec<(char, bool)> = Vec::new();
+        for c in model.chars() {
+            if c.is_ascii_alphanumeric() {
+                chars.push((c.to_ascii_lowercase(), false));
+            } else if let Some(last) = chars.last_mut() {
+                last.1 = true;
+            }
+        }
+        let compact: String = chars.iter().map(|&(c, _)| c).collect();
+        PRESETS.iter().find(|preset| {
+            let name: String = preset.name.chars().filter(char::is_ascii_alphanumeric).collect::<String>().to_ascii_lowercase();
+            // "falcon4" must not match "falcon4i", but does match "Falcon 4 EC"
+            compact.match_indices(&name).any(|(start, _)| {
+                let end = start + name.len();
+                end == chars.len() || chars[end - 1].1 || !chars[end].0.is_ascii_alphabetic()
+            })
+        })
+    }
+
+    /// Differences between a file and what this detector writes, as warnings; empty
+    /// when the file matches. `dims` is the (height, width) of the frames.
+    pub fn check(&self, dims: (usize, usize), params: &CompressionParams) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if dims != (self.height as usize, self.width as usize) {
+            warnings.push(format!(
+                "{} has a {}x{} sensor but the frames are {}x{}",
+                self.name, self.width, self.height, dims.1, dims.0
+            ));
+        }
+        let encoded = recommended_upsampling(params) as u32;
+        if encoded > self.max_upsampling {
+            warnings.push(format!(
+                "The sub-pixel bits allow {}x upsampling but {} records at most {}x",
+                encoded, self.name, self.max_upsampling
+            ));
+        }
+        warnings
+    }
+}
+
+impl Upsampling {
+    /// Like [`Upsampling::resolve`], also capping `Auto` at the detector's super-resolution
+    pub fn resolve_for(self, params: &CompressionParams, preset: Option<&DetectorPreset>) -> u32 {
+        match (self, preset) {
+            (Upsampling::Auto, Some(preset)) => self.resolve(params).min(preset.max_upsampling),
+            _ => self.resolve(params),
+        }
+    }
+}
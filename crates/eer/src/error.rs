@@ -0,0 +1,57 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Problems opening an input file, reported before any parsing starts
+#[derive(Debug)]
+pub enum EerError {
+    NotFound(PathBuf),
+    PermissionDenied(PathBuf),
+    EmptyFile(PathBuf),
+}
+
+impl fmt::Display for EerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EerError::NotFound(path) => write!(f, "File not found: {}", path.display()),
+            EerError::PermissionDenied(path) => write!(f, "Permission denied reading {}", path.display()),
+            EerError::EmptyFile(path) => write!(f, "File is empty: {}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for EerError {}
+
+/// A decode stopped because its cancellation token was set, see
+/// [`DecodeOptions::cancel`](crate::DecodeOptions::cancel)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Decoding was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl EerError {
+    /// Maps an error from opening `path` to a friendly variant, if there is one
+    pub(crate) fn from_open(path: &Path, err: io::Error) -> anyhow::Error {
+        match err.kind() {
+            io::ErrorKind::NotFound => EerError::NotFound(path.to_path_buf()).into(),
+            io::ErrorKind::PermissionDenied => EerError::PermissionDenied(path.to_path_buf()).into(),
+            _ => err.into(),
+        }
+    }
+}
+
+/// Opens an input file, turning missing, unreadable and empty files into [`EerError`]s
+pub(crate) fn open_input(path: &Path) -> anyhow::Result<File> {
+    let file = File::open(path).map_err(|e| EerError::from_open(path, e))?;
+    if file.metadata()?.len() == 0 {
+        return Err(EerError::EmptyFile(path.to_path_buf()).into());
+    }
+    Ok(file)
+}
@@ -0,0 +1,98 @@
+//! Raw array exports: NumPy `.npy` files and headerless binary blobs.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use anyhow::Result;
+use ndarray::Array2;
+
+/// Byte order of exported samples
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endianness {
+    /// NumPy byte-order character for the dtype descriptor
+    fn npy_char(self) -> char {
+        match self {
+            Endianness::Little => '<',
+            Endianness::Big => '>',
+        }
+    }
+}
+
+/// Sample types that can be exported
+pub trait Sample: Copy {
+    /// NumPy dtype without the byte-order character, e.g. `u2`
+    const NPY_TYPE: &'static str;
+    fn write_to(self, out: &mut Vec<u8>, endianness: Endianness);
+}
+
+impl Sample for u16 {
+    const NPY_TYPE: &'static str = "u2";
+    fn write_to(self, out: &mut Vec<u8>, endianness: Endianness) {
+        out.extend_from_slice(&match endianness {
+            Endianness::Little => self.to_le_bytes(),
+            Endianness::Big => self.to_be_bytes(),
+        });
+    }
+}
+
+impl Sample for f32 {
+    const NPY_TYPE: &'static str = "f4";
+    fn write_to(self, out: &mut Vec<u8>, endianness: Endianness) {
+        out.extend_from_slice(&match endianness {
+            Endianness::Little => self.to_le_bytes(),
+            Endianness::Big => self.to_be_bytes(),
+        });
+    }
+}
+
+/// Writes the samples in row-major order with no header
+pub fn write_raw<T: Sample, W: Write>(image: &Array2<T>, writer: &mut W, endianness: Endianness) -> Result<()> {
+    let mut bytes = Vec::with_capacity(image.len() * std::mem::size_of::<T>());
+    image.iter().for_each(|&v| v.write_to(&mut bytes, endianness));
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Writes a version 1.0 `.npy` file, with the dtype descriptor (e.g. `<u2` or `>u2`)
+/// matching the byte order of the data
+pub fn write_npy<T: Sample, W: Write>(image: &Array2<T>, writer: &mut W, endianness: Endianness) -> Result<()> {
+    let (rows, cols) = image.dim();
+    let mut header = format!(
+        "{{'descr': '{}{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        endianness.npy_char(),
+        T::NPY_TYPE,
+        rows,
+        cols
+    );
+    // Magic, version and length take 10 bytes; the whole preamble is padded to 64 bytes
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+    header.push('\n');
+
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    write_raw(image, writer, endianness)
+}
+
+/// Saves `image` as an `.npy` file
+pub fn save_npy<T: Sample>(image: &Array2<T>, path: &Path, endianness: Endianness) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_npy(image, &mut writer, endianness)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Saves `image` as a headerless binary blob
+pub fn save_raw<T: Sample>(image: &Array2<T>, path: &Path, endianness: Endianness) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_raw(image, &mut writer, endianness)?;
+    writer.flush()?;
+    Ok(())
+}
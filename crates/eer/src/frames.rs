@@ -0,0 +1,152 @@
+//! Lazy iteration over the decoded frames of a file, for selections the bespoke
+//! decode functions don't cover.
+
+use std::fs::File;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use ndarray::{Array2, Zip};
+use tiff::decoder::Decoder;
+
+use crate::{
+    decode_page_frames, first_frame_page, frames_in_page, get_compression_params, next_frame_page,
+    open_input, read_metadata_or_default, CompressionParams, Upsampling,
+};
+
+/// Yields every EER frame of a file in order, decoding each only when it is returned.
+/// Frames of packed pages are yielded one by one and reference pages are skipped.
+///
+/// [`Iterator::nth`] steps over frames without decoding them, so `skip` and `step_by`
+/// only pay for the frames they return:
+///
+/// ```no_run
+/// # use eer::frames::{FrameIterator, SumFrames};
+/// # use eer::Upsampling;
+/// let frames = FrameIterator::open("movie.eer".as_ref(), Upsampling::Factor(1))?;
+/// let shape = frames.shape();
+/// let sum = frames.skip(5).step_by(2).take(10).sum_into(shape)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct FrameIterator {
+    decoder: Decoder<File>,
+    file: File,
+    /// Read from the first frame page and used for every page, as in `decode_frames`
+    params: CompressionParams,
+    upsampling: u32,
+    shape: (usize, usize),
+    page: usize,
+    page_frames: u32,
+    sub_frame: u32,
+    /// The current page's frames not yet returned, decoded together on first use
+    page_cache: Vec<Option<Array2<u16>>>,
+    /// Set past the last frame, or after an error
+    done: bool,
+}
+
+impl FrameIterator {
+    /// Opens `path` positioned on its first frame, decoding at `upsampling`
+    pub fn open(path: &Path, upsampling: Upsampling) -> Result<Self> {
+        let mut decoder = Decoder::new(open_input(path)?)?;
+        let file = File::open(path)?;
+        let (page, (height, width)) = first_frame_page(&mut decoder)?;
+        let params = get_compression_params(&mut decoder)?.params();
+        let preset = read_metadata_or_default(path)?.detector_preset();
+        let upsampling = upsampling.resolve_for(&params, preset);
+        let page_frames = frames_in_page(&mut decoder)?;
+        Ok(FrameIterator {
+            decoder,
+            file,
+            params,
+            upsampling,
+            shape: (height * upsampling as usize, width * upsampling as usize),
+            page,
+            page_frames,
+            sub_frame: 0,
+            page_cache: Vec::new(),
+            done: false,
+        })
+    }
+
+    /// (height, width) of the decoded frames, upsampling included
+    pub fn shape(&self) -> (usize, usize) {
+        self.shape
+    }
+
+    /// Moves to the next frame without decoding the current one
+    fn advance(&mut self) -> Result<()> {
+        self.sub_frame += 1;
+        if self.sub_frame < self.page_frames {
+            return Ok(());
+        }
+        self.sub_frame = 0;
+        self.page_cache.clear();
+        if next_frame_page(&mut self.decoder, &mut self.page)? {
+            self.page_frames = frames_in_page(&mut self.decoder)?;
+        } else {
+            self.done = true;
+        }
+        Ok(())
+    }
+
+    fn decode_current(&mut self) -> Result<Array2<u16>> {
+        if self.page_cache.is_empty() {
+            let frames = decode_page_frames(&mut self.decoder, &self.params, &mut self.file, self.upsampling)?;
+            self.page_cache = frames.into_iter().map(Some).collect();
+        }
+        let frame = self.page_cache[self.sub_frame as usize].take()
+            .ok_or_else(|| anyhow!("Frame {} of the page was already returned", self.sub_frame))?;
+        self.advance()?;
+        Ok(frame)
+    }
+
+    /// Ends the iteration after an error, so callers that keep going don't loop on it
+    fn fuse_error<T>(&mut self, result: Result<T>) -> Option<Result<T>> {
+        if result.is_err() {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+impl Iterator for FrameIterator {
+    type Item = Result<Array2<u16>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let frame = self.decode_current();
+        self.fuse_error(frame)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        for _ in 0..n {
+            if self.done {
+                return None;
+            }
+            if let Err(e) = self.advance() {
+                return self.fuse_error(Err(e));
+            }
+        }
+        self.next()
+    }
+}
+
+/// Sums a sequence of decoded frames, e.g. a selection made with iterator adaptors
+/// over a [`FrameIterator`]
+pub trait SumFrames: Iterator<Item = Result<Array2<u16>>> + Sized {
+    /// Adds every frame into a `shape` (height, width) image, stopping at the first
+    /// error or at a frame of another shape. No frames give an all-zero image.
+    fn sum_into(self, shape: (usize, usize)) -> Result<Array2<u32>> {
+        let mut sum = Array2::<u32>::zeros(shape);
+        for frame in self {
+            let frame = frame?;
+            if frame.dim() != shape {
+                return Err(anyhow!("Frame of shape {:?} summed into an image of {:?}", frame.dim(), shape));
+            }
+            Zip::from(&mut sum).and(&frame).for_each(|s, &v| *s += v as u32);
+        }
+        Ok(sum)
+    }
+}
+
+impl<I: Iterator<Item = Result<Array2<u16>>>> SumFrames for I {}
@@ -1,20 +1,43 @@
 use std::fs::File;
 use std::path::Path;
-use std::io::{Read, Seek, SeekFrom};
-use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use quick_xml::Reader;
 use quick_xml::events::Event;
-use tiff::decoder::Decoder;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::{TiffError, TiffFormatError};
 use tiff::tags::Tag;
 use tiff::decoder::ifd::Value;
 use anyhow::{Result, anyhow};
 use ndarray::Array2;
 use serde_derive::Serialize;
 
+#[cfg(feature = "checksum")]
+pub mod checksum;
+pub mod detector;
+mod error;
+pub mod export;
+pub mod frames;
+pub mod gain;
+pub mod montage;
+pub mod rolling;
+#[cfg(any(test, feature = "synthetic"))]
+pub mod synthetic;
+pub mod tags;
+pub use error::{Cancelled, EerError};
+use error::open_input;
+use detector::DetectorPreset;
+use gain::GainReference;
+use mrc::AUTO_CONTRAST_TAIL;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use frames::SumFrames;
+    use gain::DEFAULT_DEFECT_THRESHOLD;
 
     #[test]
     fn test_bitstream_basic() {
@@ -27,363 +50,3713 @@ mod tests {
         assert_eq!(bs.get_bits(2), 0b10);      // Last 2 bits: 10
         assert!(bs.no_bits_left());            // Should be at end now
     }
+
+    #[test]
+    fn test_bitstream_msb_first() {
+        let data = vec![0b10110001];
+        let mut bs = BitStream::with_order(&data, BitOrder::MsbFirst);
+        assert_eq!(bs.get_bits(3), 0b101);
+        assert_eq!(bs.get_bits(3), 0b100);
+        assert_eq!(bs.get_bits(2), 0b01);
+        assert!(bs.no_bits_left());
+
+        // The same byte split LSB-first, the order `BitStream::new` uses
+        let mut bs = BitStream::with_order(&data, BitOrder::LsbFirst);
+        assert_eq!(bs.get_bits(3), 0b001);
+        assert_eq!(bs.get_bits(3), 0b110);
+        assert_eq!(bs.get_bits(2), 0b10);
+        assert!(bs.no_bits_left());
+
+        // Across bytes, and from an offset into a partial window at the end
+        let data = [0xA5, 0x3C, 0x81];
+        let mut bs = BitStream::with_order(&data, BitOrder::MsbFirst);
+        assert_eq!(bs.get_bits(12), 0xA53);
+        assert_eq!(bs.get_bits(8), 0xC8);
+        assert_eq!(bs.get_bits(8), 0x10);
+    }
+
+    /// Bit-by-bit reference for `BitStream::get_bits`, reading zeros past the end
+    fn reference_bits(data: &[u8], pos: usize, n: u32) -> u32 {
+        (0..n as usize)
+            .map(|i| {
+                let bit = pos + i;
+                let byte = data.get(bit / 8).copied().unwrap_or(0);
+                ((byte >> (bit % 8)) & 1) as u32
+            })
+            .enumerate()
+            .fold(0, |acc, (i, bit)| acc | (bit << i))
+    }
+
+    #[test]
+    fn test_bitstream_reads_at_strip_end() {
+        let data = [0xA5, 0x3C, 0xF0, 0x0F, 0x81, 0x7E];
+        // Reads starting inside each of the last three bytes, for 7- and 8-bit codes
+        for n in [7, 8] {
+            for pos in (data.len() - 3) * 8..data.len() * 8 {
+                let mut bs = BitStream::new(&data);
+                bs.bit_pos = pos;
+                assert_eq!(bs.get_bits(n), reference_bits(&data, pos, n), "n={} pos={}", n, pos);
+            }
+        }
+
+        // A 7-bit code stream that straddles the final byte
+        let mut bs = BitStream::new(&data);
+        for i in 0..6 {
+            assert_eq!(bs.get_bits(7), reference_bits(&data, i * 7, 7));
+        }
+        assert_eq!(bs.bits_left(), 6);
+        assert_eq!(bs.get_bits(7), 0x7E >> 2);
+    }
+
+    #[test]
+    fn test_bitstream_wide_reads() {
+        let data = [0b1011_0001, 0b0110_1100, 0b1111_0000, 0xA5, 0x3C, 0x81];
+        let mut bs = BitStream::new(&data);
+        assert_eq!(bs.get_bits(3), 0b001);
+        // Bits 3..11: the top five of byte 0, then the low three of byte 1
+        assert_eq!(bs.get_bits(8), 0b100_10110);
+        assert_eq!(bs.get_bits(8), 0b000_01101);
+
+        // 32 bits from every offset in the first byte spill into a fifth byte
+        for pos in 0..8 {
+            bs.seek_bits(pos);
+            assert_eq!(bs.get_bits(32), reference_bits(&data, pos, 32), "pos={}", pos);
+        }
+    }
+
+    #[test]
+    fn test_bitstream_reads_past_end() {
+        let data = [0xA5];
+        let mut bs = BitStream::new(&data);
+        assert_eq!(bs.get_bits(7), 0x25);
+        assert_eq!(bs.get_bits(7), 1);
+        for _ in 0..10 {
+            assert_eq!(bs.get_bits(7), 0);
+            assert!(bs.no_bits_left());
+        }
+    }
+
+    #[test]
+    fn test_bitstream_reset_and_seek() {
+        let data = [0xA5, 0x3C, 0xF0, 0x0F];
+        let mut bs = BitStream::new(&data);
+        let first: Vec<u32> = (0..4).map(|_| bs.get_bits(7)).collect();
+        assert_eq!(bs.bits_left(), 4);
+
+        bs.reset();
+        assert_eq!(bs.bits_left(), 32);
+        let again: Vec<u32> = (0..4).map(|_| bs.get_bits(7)).collect();
+        assert_eq!(first, again);
+
+        for pos in [0, 5, 13, 25] {
+            bs.seek_bits(pos);
+            assert_eq!(bs.get_bits(7), reference_bits(&data, pos, 7), "pos={}", pos);
+        }
+        bs.seek_bits(32);
+        assert!(bs.no_bits_left());
+    }
+
+    #[test]
+    fn test_npy_export_roundtrip() {
+        use export::{write_npy, Endianness};
+
+        let image = Array2::from_shape_vec((2, 3), vec![0u16, 1, 258, 0xABCD, 7, 65535]).unwrap();
+        for (endianness, descr) in [(Endianness::Little, "'<u2'"), (Endianness::Big, "'>u2'")] {
+            let mut bytes = Vec::new();
+            write_npy(&image, &mut bytes, endianness).unwrap();
+
+            assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+            let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+            assert_eq!((10 + header_len) % 64, 0);
+            let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+            assert!(header.contains(descr));
+            assert!(header.contains("'shape': (2, 3)"));
+
+            let values: Vec<u16> = bytes[10 + header_len..]
+                .chunks_exact(2)
+                .map(|b| match endianness {
+                    Endianness::Little => u16::from_le_bytes([b[0], b[1]]),
+                    Endianness::Big => u16::from_be_bytes([b[0], b[1]]),
+                })
+                .collect();
+            assert_eq!(Array2::from_shape_vec((2, 3), values).unwrap(), image);
+        }
+    }
+
+    #[test]
+    fn test_auto_contrast_clips_hot_pixels() {
+        let mut image = Array2::<u32>::from_shape_fn((100, 100), |(y, x)| ((x + y) % 20) as u32 + 10);
+        image[[0, 0]] = 60000;
+        image[[5, 5]] = 0;
+        assert_eq!(auto_contrast(&image), (10.0, 29.0));
+
+        let flat = Array2::<u32>::from_elem((4, 4), 7);
+        assert_eq!(auto_contrast(&flat), (7.0, 7.0));
+
+        // A saturated pixel used to size the histogram at one bin per count
+        image[[0, 0]] = u32::MAX;
+        assert_eq!(auto_contrast(&image), (10.0, 29.0));
+        let mut hot = Array2::<u32>::from_elem((2, 2), u32::MAX);
+        hot[[0, 0]] = 1;
+        assert_eq!(auto_contrast(&hot), (1.0, u32::MAX as f32));
+        assert_eq!(count_at_rank(&hot, 1), u32::MAX);
+        assert_eq!(auto_contrast(&Array2::<u32>::zeros((0, 0))), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_recommended_upsampling() {
+        let params = CompressionParams { code_len: 7, horz_sub_bits: 2, vert_sub_bits: 2, pos_skip_max: None };
+        assert_eq!(recommended_upsampling(&params), 4);
+        assert_eq!("auto".parse::<Upsampling>().unwrap().resolve(&params), 4);
+        assert_eq!("1".parse::<Upsampling>().unwrap().resolve(&params), 1);
+        assert!("3".parse::<Upsampling>().is_err());
+        assert!(upsampling_shift(&params, 8).is_err());
+    }
+
+    #[test]
+    fn test_eer_compression() {
+        assert_eq!(EerCompression::try_from(65000).unwrap(), EerCompression::Count8);
+        assert_eq!(EerCompression::try_from(65001).unwrap().params().code_len, 7);
+        assert!(EerCompression::try_from(65002).is_err());
+        assert!(EerCompression::try_from(5).is_err());
+
+        let mut eer = synthetic::SyntheticEer::new(8, 8);
+        eer.compression = EerCompression::CUSTOM_CODE;
+        eer.params = CompressionParams { code_len: 6, horz_sub_bits: 1, vert_sub_bits: 3, pos_skip_max: None };
+        eer.frames.push(vec![(1, 1, 1, 5)]);
+        let path = std::env::temp_dir().join("emfir_custom_compression.eer");
+        eer.write(&path).unwrap();
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let compression = EerCompression::read(&mut decoder).unwrap();
+        assert_eq!(
+            compression,
+            EerCompression::Custom { code_len: 6, horz_sub_bits: 1, vert_sub_bits: 3 }
+        );
+        assert_eq!(get_compression_params(&mut decoder).unwrap(), compression);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_pos_skip_max_override() {
+        // A 100 sentinel in 7-bit codes; the gap of 250 pixels needs two of them
+        let mut eer = synthetic::SyntheticEer::new(300, 1);
+        eer.params.pos_skip_max = Some(100);
+        eer.frames.push(vec![(250, 0, 0, 0), (260, 0, 0, 0)]);
+        let path = std::env::temp_dir().join("emfir_pos_skip_max.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let mut file = File::open(&path).unwrap();
+        let mut params = get_compression_params(&mut decoder).unwrap().params();
+        assert_eq!(params.pos_skip_max(), 127);
+        params.pos_skip_max = Some(100);
+        let frame = decode_eer_frame(&mut decoder, &params, &mut file, 1, 1).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let hits: Vec<usize> = (0..300).filter(|&x| frame[[0, x]] > 0).collect();
+        assert_eq!(hits, vec![250, 260]);
+    }
+
+    #[test]
+    fn test_pos_skip_max_range() {
+        let params = EerCompression::Count7.params();
+        assert_eq!(params.clone().with_pos_skip_max(127).unwrap().pos_skip_max(), 127);
+        assert!(params.clone().with_pos_skip_max(0).is_err());
+        assert!(params.clone().with_pos_skip_max(128).is_err());
+
+        // Set directly, out-of-range sentinels are rejected instead of looping forever
+        let mut eer = synthetic::SyntheticEer::new(8, 1);
+        eer.frames.push(vec![(3, 0, 0, 0)]);
+        let path = std::env::temp_dir().join("emfir_pos_skip_max_range.eer");
+        eer.write(&path).unwrap();
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let mut file = File::open(&path).unwrap();
+        for pos_skip_max in [0, 128] {
+            let bad = CompressionParams { pos_skip_max: Some(pos_skip_max), ..params.clone() };
+            assert!(decode_eer_frame(&mut decoder, &bad, &mut file, 1, 1).is_err());
+            eer.params = bad;
+            assert!(eer.to_bytes().is_err());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_dark_reference_page() {
+        let mut eer = synthetic::SyntheticEer::new(4, 2);
+        eer.fill_random(3, 4, 5);
+        let path = std::env::temp_dir().join("emfir_dark_reference.eer");
+        eer.write(&path).unwrap();
+        assert!(dark_reference(&path).unwrap().is_none());
+
+        let dark: Vec<f32> = (0..8).map(|v| v as f32 * 0.25).collect();
+        eer.dark_reference = Some(dark.clone());
+        eer.write(&path).unwrap();
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(count_frames(&mut decoder).unwrap(), 3);
+        let read = dark_reference(&path).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read, Array2::from_shape_vec((2, 4), dark).unwrap());
+
+        eer.dark_reference = Some(vec![0.0; 3]);
+        assert!(eer.to_bytes().is_err());
+    }
+
+    #[test]
+    fn test_subtract_dark_reference_upsampled() {
+        let sum = Array2::<u32>::from_elem((4, 4), 10);
+        let dark = Array2::<f32>::from_elem((2, 2), 2.0);
+        // 8 frames * 2.0 dark counts, spread over 2x2 super-resolution pixels
+        let corrected = subtract_dark_reference(&sum, &dark, 8).unwrap();
+        assert!(corrected.iter().all(|&v| v == 6));
+        assert!(subtract_dark_reference(&sum, &Array2::zeros((3, 3)), 1).is_err());
+    }
+
+    #[test]
+    fn test_gain_defects() {
+        // Zero and negative gains are defects by default
+        let gain = GainReference::new(Array2::from_shape_vec((2, 2), vec![2.0, 0.0, -1.0, 0.5]).unwrap(), 0.0);
+        assert_eq!(gain.defect_count(), 2);
+        let corrected = gain.apply(&Array2::from_elem((4, 4), 10)).unwrap();
+        assert_eq!(corrected.row(0).to_vec(), vec![20, 20, 0, 0]);
+        assert_eq!(corrected.row(3).to_vec(), vec![0, 0, 5, 5]);
+        assert!(gain.apply(&Array2::zeros((3, 3))).is_err());
+        assert_eq!(GainReference::new(gain.gain().clone(), 0.6).defect_count(), 3);
+
+        let mut eer = synthetic::SyntheticEer::new(4, 2);
+        eer.frames = vec![vec![(0, 0, 0, 0), (1, 0, 0, 0)], vec![(0, 0, 0, 0), (2, 1, 0, 0)]];
+        let path = std::env::temp_dir().join("emfir_gain.eer");
+        eer.write(&path).unwrap();
+        let gain = Array2::from_shape_vec((2, 4), vec![1.5, 0.0, 1.0, 1.0, 1.0, 1.0, 3.0, 1.0]).unwrap();
+        let options = DecodeOptions { gain: Some(GainReference::new(gain, DEFAULT_DEFECT_THRESHOLD)), ..Default::default() };
+        let sum = sum_frames(&path, &options).unwrap();
+        let options = DecodeOptions { gain: Some(GainReference::new(Array2::ones((3, 3)), 0.0)), ..options };
+        let mismatched = sum_frames(&path, &options);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(sum, Array2::from_shape_vec((2, 4), vec![3, 0, 0, 0, 0, 0, 3, 0]).unwrap());
+        assert!(mismatched.is_err());
+    }
+
+    #[test]
+    fn test_synthetic_frame_roundtrip() {
+        let mut eer = synthetic::SyntheticEer::new(300, 7);
+        eer.rows_per_strip = 3;
+        eer.frames.push(vec![(0, 0, 0, 0), (299, 6, 3, 3), (150, 4, 1, 2), (151, 4, 0, 0)]);
+        let path = std::env::temp_dir().join("emfir_synthetic_roundtrip.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let mut file = File::open(&path).unwrap();
+        let frame = decode_eer_frame(&mut decoder, &params, &mut file, 1, 1).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(frame.dim(), (7, 300));
+        assert_eq!(frame[[0, 0]], 1);
+        assert_eq!(frame[[6, 299]], 1);
+        assert_eq!(frame[[4, 150]], 1);
+        assert_eq!(frame[[4, 151]], 1);
+        assert_eq!(frame.iter().map(|&v| v as u32).sum::<u32>(), 4);
+    }
+
+    #[test]
+    fn test_short_last_strip() {
+        // 4096 rows in strips of 500, as on Falcon sensors: eight full strips and a last
+        // strip of 96 rows. An event on the first and last row of every strip, the very
+        // last pixel included.
+        let (width, height) = (8, 4096);
+        let mut eer = synthetic::SyntheticEer::new(width, height);
+        eer.rows_per_strip = 500;
+        let mut events: Vec<_> = (0..height.div_ceil(500))
+            .flat_map(|strip| [(strip % width, strip * 500, 0, 0), (width - 1, (strip * 500 + 499).min(height - 1), 3, 3)])
+            .collect();
+        events.push((0, height - 1, 0, 0));
+        eer.frames = vec![events.clone()];
+        let path = std::env::temp_dir().join("emfir_short_last_strip.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(detect_code_len(&mut decoder, &params, &mut file).unwrap(), params.code_len);
+        let frame = decode_eer_frame(&mut decoder, &params, &mut file, 1, 1).unwrap();
+        let upsampled = decode_eer_frame(&mut decoder, &params, &mut file, 2, 1).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(frame.dim(), (4096, 8));
+        for &(x, y, _, _) in &events {
+            assert_eq!(frame[[y as usize, x as usize]], 1, "event at row {}", y);
+        }
+        assert_eq!(frame.iter().map(|&v| v as usize).sum::<usize>(), events.len());
+        assert_eq!(upsampled[[8191, 15]], 1);
+        assert_eq!(upsampled.iter().map(|&v| v as usize).sum::<usize>(), events.len());
+    }
+
+    #[test]
+    fn test_events_to_image_matches_dense_decode() {
+        let mut eer = synthetic::SyntheticEer::new(64, 48);
+        eer.rows_per_strip = 10;
+        eer.fill_random(1, 400, 3);
+        let path = std::env::temp_dir().join("emfir_events_roundtrip.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let mut file = File::open(&path).unwrap();
+        let events = decode_frame_events(&mut decoder, &params, &mut file).unwrap();
+        assert!(!events.is_empty());
+        for upsampling in [1, 2, 4] {
+            let dense = decode_eer_frame(&mut decoder, &params, &mut file, upsampling, 1).unwrap();
+            assert_eq!(events_to_image(&events, 64, 48, upsampling).unwrap(), dense);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_frame_event_tuples() {
+        let mut eer = synthetic::SyntheticEer::new(8, 6);
+        let expected = vec![(0, 0, 1, 2), (5, 0, 3, 3), (4, 2, 0, 1), (4, 3, 2, 0), (7, 5, 1, 1)];
+        eer.frames = vec![expected.clone()];
+        let path = std::env::temp_dir().join("emfir_event_tuples.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let mut file = File::open(&path).unwrap();
+        let events = decode_eer_frame_events(&mut decoder, &params, &mut file).unwrap();
+        let dense = decode_eer_frame(&mut decoder, &params, &mut file, 1, 1).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(events, expected);
+        let mut histogram = Array2::<u16>::zeros((6, 8));
+        for &(x, y, _, _) in &events {
+            histogram[[y as usize, x as usize]] += 1;
+        }
+        assert_eq!(histogram, dense);
+    }
+
+    #[test]
+    fn test_upsampled_event_placement() {
+        // Count7 has 2+2 sub-pixel bits; sub-pixel offsets are (h, v) = (3, 1)
+        let mut eer = synthetic::SyntheticEer::new(8, 6);
+        eer.frames = vec![vec![(3, 2, 3, 1), (0, 0, 0, 0), (7, 5, 2, 3)]];
+        let path = std::env::temp_dir().join("emfir_upsampled_placement.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let mut file = File::open(&path).unwrap();
+        let frames: Vec<Array2<u16>> = [1, 2, 4]
+            .iter()
+            .map(|&up| decode_eer_frame(&mut decoder, &params, &mut file, up, 1).unwrap())
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+
+        for (frame, up) in frames.iter().zip([1, 2, 4]) {
+            assert_eq!(frame.dim(), (6 * up, 8 * up));
+            assert_eq!(frame.iter().map(|&v| v as u32).sum::<u32>(), 3, "{}x", up);
+        }
+        assert_eq!(frames[0][[2, 3]], 1);
+        // 2x keeps the top sub-pixel bit: row 2 * 2 + (1 >> 1), column 3 * 2 + (3 >> 1)
+        assert_eq!(frames[1][[4, 7]], 1);
+        assert_eq!(frames[2][[9, 15]], 1);
+        assert_eq!(frames[2][[23, 30]], 1);
+    }
+
+    #[test]
+    fn test_decode_eer_frame_binned() {
+        let mut eer = synthetic::SyntheticEer::new(50, 30);
+        eer.rows_per_strip = 7;
+        eer.fill_random(1, 300, 5);
+        let path = std::env::temp_dir().join("emfir_binned.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let mut file = File::open(&path).unwrap();
+        let full = decode_eer_frame(&mut decoder, &params, &mut file, 1, 1).unwrap();
+        // 50x30 does not divide by 4, so the last row and column of bins are partial
+        let binned = decode_eer_frame(&mut decoder, &params, &mut file, 1, 4).unwrap();
+        assert!(decode_eer_frame(&mut decoder, &params, &mut file, 2, 4).is_err());
+        assert!(decode_eer_frame(&mut decoder, &params, &mut file, 1, MAX_FRAME_BIN_FACTOR).is_ok());
+        assert!(decode_eer_frame(&mut decoder, &params, &mut file, 1, MAX_FRAME_BIN_FACTOR + 1).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(binned.dim(), (8, 13));
+        let expected = Array2::from_shape_fn((8, 13), |(y, x)| {
+            full.slice(ndarray::s![y * 4..(y * 4 + 4).min(30), x * 4..(x * 4 + 4).min(50)]).sum()
+        });
+        assert_eq!(binned, expected);
+    }
+
+    #[test]
+    fn test_read_ifd_tags() {
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.compression = EerCompression::CUSTOM_CODE;
+        eer.xml = Some("<metadata/>".to_string());
+        eer.frames = vec![vec![(1, 1, 0, 0)]; 2];
+        let path = std::env::temp_dir().join("emfir_ifd_tags.eer");
+        eer.write(&path).unwrap();
+        let ifds = tags::read_ifd_tags(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(ifds.len(), 2);
+        let first: Vec<(&str, String)> = ifds[0]
+            .iter()
+            .map(|t| (t.name.as_str(), tags::format_value(&t.value)))
+            .collect();
+        assert!(first.contains(&("ImageWidth", "16".to_string())));
+        assert!(first.contains(&("Compression", "65002".to_string())));
+        assert!(first.contains(&("EerPosSkipBits", "7".to_string())));
+        assert!(first.contains(&("EerXmlData", "11 bytes: \"<metadata/>\"".to_string())));
+        assert!(ifds[1].iter().all(|t| t.code != TAG_XML_DATA));
+    }
+
+    #[test]
+    fn test_read_raw_xml() {
+        // Formatting that a parse and re-serialise would not keep
+        let xml = "<metadata>\r\n  <item name=\"a\" >1</item><!-- note -->\n</metadata>\n";
+        let mut eer = synthetic::SyntheticEer::new(8, 8);
+        eer.frames = vec![Vec::new()];
+        eer.xml = Some(xml.to_string());
+        let path = std::env::temp_dir().join("emfir_raw_xml.eer");
+        eer.write(&path).unwrap();
+        assert_eq!(read_raw_xml(&path).unwrap().as_deref(), Some(xml.as_bytes()));
+
+        eer.xml = None;
+        eer.write(&path).unwrap();
+        assert_eq!(read_raw_xml(&path).unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_xml_from_value_variants() {
+        let xml = "<metadata><item name=\"exposureTime\">2</item></metadata>";
+        let chars = || xml.bytes();
+
+        let variants = [
+            Value::Ascii(format!("{}\0", xml)),
+            Value::List(chars().map(Value::Byte).collect()),
+            Value::List(chars().map(|b| Value::UnsignedBig(b as u64)).collect()),
+            Value::List(chars().map(|b| Value::Short(b as u16)).collect()),
+            Value::List(vec![Value::Ascii(xml[..10].to_string()), Value::Ascii(xml[10..].to_string())]),
+        ];
+        for value in variants {
+            let text = xml_from_value(value.clone()).unwrap();
+            assert_eq!(text, xml, "{:?}", value);
+            assert_eq!(parse_xml_metadata(&text)["exposureTime"], "2");
+        }
+        assert_eq!(xml_from_value(Value::Byte(b'<')).unwrap(), "<");
+        assert!(xml_from_value(Value::List(vec![Value::Short(300)])).is_err());
+        assert!(xml_from_value(Value::Float(1.0)).is_err());
+
+        // Compressed blobs
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(xml.as_bytes()).unwrap();
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(xml.as_bytes()).unwrap();
+        let mut deflate = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        deflate.write_all(xml.as_bytes()).unwrap();
+        let blobs = [
+            ("zlib", zlib.finish().unwrap()),
+            ("gzip", gzip.finish().unwrap()),
+            ("deflate", deflate.finish().unwrap()),
+        ];
+        for (format, blob) in blobs {
+            assert_eq!(decompress_xml(&blob).unwrap().unwrap().0, format);
+            let value = Value::List(blob.into_iter().map(Value::Byte).collect());
+            assert_eq!(xml_from_value(value).unwrap(), xml);
+        }
+        // A blob inflating past the cap is an error, not truncated XML
+        let mut bomb = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        bomb.write_all(b"<").unwrap();
+        bomb.write_all(&vec![b' '; MAX_XML_BYTES as usize]).unwrap();
+        let bomb = bomb.finish().unwrap();
+        assert!(decompress_xml(&bomb).is_err());
+        assert!(xml_from_value(Value::List(bomb.into_iter().map(Value::Byte).collect())).is_err());
+
+        // BYTE-typed tag as written by the synthetic writer
+        let mut eer = synthetic::SyntheticEer::new(8, 8);
+        eer.frames = vec![Vec::new()];
+        eer.xml = Some(xml.to_string());
+        let path = std::env::temp_dir().join("emfir_xml_variants.eer");
+        eer.write(&path).unwrap();
+        let report = header_report(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.metadata["exposureTime"], "2");
+        assert_eq!((report.image_data.size_x, report.page_count, report.frame_count), (8, 1, 1));
+        assert_eq!(report.compression, Some(EerCompression::Count7.params()));
+    }
+
+    #[test]
+    fn test_header_report_for_output() {
+        let mut eer = synthetic::SyntheticEer::new(10, 6);
+        eer.frames = vec![Vec::new()];
+        eer.xml = Some(
+            "<metadata><item name=\"sensorPixelSize.width\">1.2</item>\
+             <item name=\"sensorPixelSize.height\">1.2</item></metadata>".to_string(),
+        );
+        let path = std::env::temp_dir().join("emfir_header_for_output.eer");
+        eer.write(&path).unwrap();
+        let data_for = |options: DecodeOptions| {
+            let data = header_report(&path).unwrap().for_output(&options).image_data;
+            (data.size_x, data.size_y, data.voxel_spacing_x, data.voxel_spacing_y)
+        };
+
+        assert_eq!(data_for(DecodeOptions::default()), (10, 6, 1.2, 1.2));
+        let upsampled = |factor| DecodeOptions { upsampling: Upsampling::Factor(factor), ..Default::default() };
+        assert_eq!(data_for(upsampled(2)), (20, 12, 0.6, 0.6));
+        assert_eq!(data_for(upsampled(4)), (40, 24, 0.3, 0.3));
+        // Count7 files have 2+2 sub-pixel bits, so auto is 4x
+        assert_eq!(data_for(DecodeOptions { upsampling: Upsampling::Auto, ..Default::default() }).0, 40);
+        let binned = data_for(DecodeOptions { bin_factor: 4, ..Default::default() });
+        // The summed image has the reported (height, width)
+        let sum = sum_frames(&path, &upsampled(2)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((binned.0, binned.1), (3, 2));
+        assert!((binned.2 - 4.8).abs() < 1e-5);
+        assert_eq!(sum.dim(), (12, 20));
+    }
+
+    #[test]
+    fn test_decode_params() {
+        let mut eer = synthetic::SyntheticEer::new(8, 8);
+        eer.frames = vec![Vec::new(); 10];
+        let path = std::env::temp_dir().join("emfir_decode_params.eer");
+        eer.write(&path).unwrap();
+        assert!(header_report(&path).unwrap().decode_params.is_none());
+
+        // The gain multiplies the sum at the decoded (2x upsampled) size
+        let gain = GainReference::new(Array2::ones((16, 16)), 0.5).with_source("gain.mrc");
+        let options = DecodeOptions {
+            skip_frames: Some(3),
+            upsampling: Upsampling::Factor(2),
+            exclude: HashSet::from([6, 7]),
+            bin_factor: 2,
+            crop: Some("0,0,8,4".parse().unwrap()),
+            auto_contrast: true,
+            gain: Some(gain),
+            ..Default::default()
+        };
+        // Frames 0, 3, 6 and 9, less the excluded 6
+        let (_, params) = sum_frames_with_params(&path, &options).unwrap();
+        assert_eq!((params.frames_summed, params.skip_frames, params.upsampling), (3, 3, 2));
+        assert_eq!(params.excluded, vec![6, 7]);
+        assert_eq!((params.gain_file.as_deref(), params.defect_threshold), (Some("gain.mrc"), Some(0.5)));
+        let json = serde_json::to_value(&params).unwrap();
+        assert_eq!(json["crop"], serde_json::json!({"x": 0, "y": 0, "width": 8, "height": 4}));
+        assert_eq!(json["contrast"], "auto");
+
+        let options = DecodeOptions { max_frames: Some(2), ..Default::default() };
+        let (_, params) = sum_frames_with_params(&path, &options).unwrap();
+        assert_eq!((params.frames_summed, params.upsampling, params.gain_file), (2, 1, None));
+        // No record for settings the decode rejects, and the cap reports what it summed
+        let options = DecodeOptions { skip_frames: Some(0), ..Default::default() };
+        assert!(sum_frames_with_params(&path, &options).is_err());
+        let options = DecodeOptions { max_frames: Some(50), skip_frames: Some(4), ..Default::default() };
+        let (_, params) = sum_frames_with_params(&path, &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((params.frames_summed, params.skip_frames), (3, 4));
+    }
+
+    #[test]
+    fn test_crop() {
+        let mut eer = synthetic::SyntheticEer::new(8, 6);
+        eer.frames = vec![vec![(2, 1, 0, 0), (7, 5, 0, 0)]];
+        let path = std::env::temp_dir().join("emfir_crop.eer");
+        eer.write(&path).unwrap();
+        let crop: Crop = "2,1,4,3".parse().unwrap();
+        let cropped = sum_frames(&path, &DecodeOptions { crop: Some(crop), ..Default::default() }).unwrap();
+        let upsampled = DecodeOptions { crop: Some(crop), upsampling: Upsampling::Factor(2), ..Default::default() };
+        let cropped_2x = sum_frames(&path, &upsampled).unwrap();
+        let outside = DecodeOptions { crop: Some("6,0,4,2".parse().unwrap()), ..Default::default() };
+        assert!(sum_frames(&path, &outside).is_err());
+        let overflowing = DecodeOptions { crop: Some(Crop { x: u32::MAX, y: 0, width: 2, height: 2 }), ..Default::default() };
+        assert!(sum_frames(&path, &overflowing).is_err());
+        let report = header_report(&path).unwrap().for_output(&DecodeOptions { crop: Some(crop), ..Default::default() });
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((cropped.dim(), cropped[[0, 0]], cropped.sum()), ((3, 4), 1, 1));
+        // At 2x the crop is in upsampled pixels; the event at sensor (2, 1) lands on (4, 2)
+        assert_eq!((cropped_2x.dim(), cropped_2x[[1, 2]]), ((3, 4), 1));
+        assert_eq!((report.image_data.size_x, report.image_data.size_y), (4, 3));
+        assert!("1,2,3".parse::<Crop>().is_err());
+    }
+
+    #[test]
+    fn test_sum_frame_series() {
+        let dir = std::env::temp_dir();
+        let paths = ["emfir_series_1.eer", "emfir_series_2.eer", "emfir_series_3.eer"].map(|name| dir.join(name));
+        let mut eer = synthetic::SyntheticEer::new(8, 8);
+        eer.frames = vec![vec![(1, 1, 0, 0)]; 3];
+        eer.write(&paths[0]).unwrap();
+        eer.frames = vec![vec![(1, 1, 0, 0), (2, 2, 0, 0)]; 2];
+        eer.write(&paths[1]).unwrap();
+        let parts = [paths[0].as_path(), paths[1].as_path()];
+        let sum = sum_frame_series(&parts, &DecodeOptions::default()).unwrap();
+
+        // A part with other dimensions or compression is rejected
+        synthetic::SyntheticEer { frames: vec![Vec::new()], ..synthetic::SyntheticEer::new(8, 4) }.write(&paths[2]).unwrap();
+        let resized = sum_frame_series(&[paths[0].as_path(), paths[2].as_path()], &DecodeOptions::default());
+        eer.compression = EerCompression::COUNT8_CODE;
+        eer.write(&paths[2]).unwrap();
+        let recoded = sum_frame_series(&[paths[0].as_path(), paths[2].as_path()], &DecodeOptions::default());
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert_eq!((sum[[1, 1]], sum[[2, 2]], sum.sum()), (5, 2, 7));
+        assert!(resized.unwrap_err().to_string().contains("8x4"));
+        assert!(recoded.unwrap_err().to_string().contains("compressed"));
+        assert!(sum_frame_series(&[], &DecodeOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_detector_preset() {
+        use detector::DetectorPreset;
+        assert_eq!(DetectorPreset::lookup("Falcon 4i").unwrap().name, "Falcon 4i");
+        assert_eq!(DetectorPreset::lookup("TFS FALCON-4").unwrap().name, "Falcon 4");
+        assert_eq!(DetectorPreset::lookup("Falcon 4 EC").unwrap().name, "Falcon 4");
+        assert!(DetectorPreset::lookup("K3").is_none());
+        assert!(DetectorPreset::lookup("Falcon4x").is_none());
+
+        let preset = DetectorPreset::lookup("Falcon 4i").unwrap();
+        let params = EerCompression::Count7.params();
+        assert!(preset.check((4096, 4096), &params).is_empty());
+        let wide = CompressionParams { horz_sub_bits: 3, vert_sub_bits: 3, ..params.clone() };
+        assert_eq!(preset.check((2048, 4096), &wide).len(), 2);
+        let wide_auto = Upsampling::Auto.resolve_for(&wide, Some(preset));
+        assert_eq!((wide_auto, Upsampling::Auto.resolve_for(&wide, None)), (4, 8));
+
+        let metadata = EerMetadata::from_xml("<metadata><item name=\"detectorName\"> Falcon 4i </item></metadata>");
+        assert_eq!(metadata.detector_model(), Some("Falcon 4i"));
+        assert_eq!(metadata.detector_preset(), Some(preset));
+        assert!(EerMetadata::default().detector_preset().is_none());
+
+        // A 16x16 "Falcon 4i" gets the size mismatch in its report
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.frames = vec![Vec::new()];
+        eer.xml = Some("<metadata><item name=\"detectorName\">Falcon 4i</item></metadata>".to_string());
+        let path = std::env::temp_dir().join("emfir_detector_preset.eer");
+        eer.write(&path).unwrap();
+        let report = header_report(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.warnings, vec!["Falcon 4i has a 4096x4096 sensor but the frames are 16x16".to_string()]);
+    }
+
+    #[test]
+    fn test_dose_stats() {
+        let mut eer = synthetic::SyntheticEer::new(10, 10);
+        eer.frames = vec![(0..10).map(|x| (x, x, 0, 0)).collect(); 4];
+        // 40 electrons over 100 pixels is 0.4 e/pixel; report 0.5
+        eer.xml = Some(
+            "<metadata><item name=\"meanDoseRate\">0.25</item><item name=\"exposureTime\">2</item></metadata>"
+                .to_string(),
+        );
+        let path = std::env::temp_dir().join("emfir_dose_stats.eer");
+        eer.write(&path).unwrap();
+
+        let stats = dose_stats(&path).unwrap();
+        assert_eq!((stats.frames, stats.total_counts), (4, 40));
+        assert!((stats.measured_dose - 0.4).abs() < 1e-12);
+        assert_eq!(stats.reported_dose, Some(0.5));
+        assert!((stats.relative_difference.unwrap() + 0.2).abs() < 1e-12);
+        assert!(stats.dose_mismatch());
+
+        eer.xml = None;
+        eer.write(&path).unwrap();
+        let stats = dose_stats(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(stats.reported_dose, None);
+        assert!(!stats.dose_mismatch());
+    }
+
+    #[test]
+    fn test_decode_uses_decoder_page_not_file_cursor() {
+        let mut eer = synthetic::SyntheticEer::new(24, 24);
+        eer.rows_per_strip = 5;
+        eer.fill_random(3, 80, 9);
+        let path = std::env::temp_dir().join("emfir_dual_handle.eer");
+        eer.write(&path).unwrap();
+
+        // Reference: frame 2 decoded from fresh handles
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        decoder.seek_to_image(2).unwrap();
+        let expected = decode_eer_frame(&mut decoder, &params, &mut File::open(&path).unwrap(), 1, 1).unwrap();
+
+        // The strip handle's cursor is irrelevant; only the decoder's page matters
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let mut file = File::open(&path).unwrap();
+        decode_eer_frame(&mut decoder, &params, &mut file, 1, 1).unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+        decoder.next_image().unwrap();
+        decoder.next_image().unwrap();
+        assert_eq!(decode_eer_frame(&mut decoder, &params, &mut file, 1, 1).unwrap(), expected);
+
+        // A handle on a different, shorter file is caught rather than decoded
+        let other = std::env::temp_dir().join("emfir_dual_handle_other.eer");
+        std::fs::write(&other, [0u8; 16]).unwrap();
+        let err = decode_eer_frame(&mut decoder, &params, &mut File::open(&other).unwrap(), 1, 1);
+        assert!(err.unwrap_err().to_string().contains("past the end"));
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&other).unwrap();
+    }
+
+    #[test]
+    fn test_strip_tag_length_mismatch() {
+        let err = pair_strips(vec![8, 100, 200], vec![92, 100]).unwrap_err();
+        assert_eq!(err.to_string(), "StripOffsets has 3 entries but StripByteCounts has 2");
+        assert_eq!(pair_strips(vec![8, 100], vec![92, 100]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_read_frame_strips() {
+        let mut eer = synthetic::SyntheticEer::new(32, 32);
+        eer.rows_per_strip = 8;
+        eer.fill_random(3, 50, 11);
+        let path = std::env::temp_dir().join("emfir_dump_strips.eer");
+        eer.write(&path).unwrap();
+
+        let (strips, data) = read_frame_strips(&path, 1).unwrap();
+        assert_eq!(strips.len(), 4);
+        let bytes = std::fs::read(&path).unwrap();
+        let expected: Vec<u8> = strips
+            .iter()
+            .flat_map(|s| bytes[s.offset as usize..(s.offset + s.size) as usize].to_vec())
+            .collect();
+        assert_eq!(data, expected);
+        assert!(read_frame_strips(&path, 3).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_decode_frames_reports_frames_summed() {
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.frames = vec![vec![(3, 3, 0, 0)]; 5];
+        let path = std::env::temp_dir().join("emfir_frames_summed.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let (sum, frames_summed) =
+            decode_frames(&mut decoder, &params, &mut File::open(&path).unwrap(), 5, Some(2), 1, SumOptions::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Frames 0, 2 and 4
+        assert_eq!(frames_summed, 3);
+        assert_eq!(sum[[3, 3]], 3);
+    }
+
+    #[test]
+    fn test_decode_after_count_frames() {
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.fill_random(6, 20, 11);
+        eer.overview_first = true;
+        let path = std::env::temp_dir().join("emfir_decode_after_count.eer");
+        eer.write(&path).unwrap();
+
+        let decode = |decoder: &mut Decoder<File>| {
+            let params = get_compression_params(decoder).unwrap().params();
+            decode_frames(decoder, &params, &mut File::open(&path).unwrap(), 6, None, 1, SumOptions::default()).unwrap()
+        };
+        let fresh = decode(&mut Decoder::new(File::open(&path).unwrap()).unwrap());
+
+        // Counting walks to the last page; decoding on the same decoder must start over
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(count_frames(&mut decoder).unwrap(), 6);
+        assert_eq!(count_frames(&mut decoder).unwrap(), 6);
+        let reused = decode(&mut decoder);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(reused, fresh);
+        assert_eq!(fresh.1, 6);
+    }
+
+    #[test]
+    fn test_decode_frames_excluding() {
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.frames = (0..6).map(|i| vec![(i, 0, 0, 0), (8, 8, 0, 0)]).collect();
+        let path = std::env::temp_dir().join("emfir_frames_excluding.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let exclude = HashSet::from([1, 4]);
+        let (sum, frames_summed) =
+            decode_frames(&mut decoder, &params, &mut File::open(&path).unwrap(), 6, None, 1, SumOptions {
+                exclude: Some(&exclude),
+                ..Default::default()
+            })
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(frames_summed, 4);
+        assert_eq!(sum[[8, 8]], 4);
+        let row: Vec<u32> = (0..6).map(|x| sum[[0, x]]).collect();
+        assert_eq!(row, vec![1, 0, 1, 1, 0, 1]);
+    }
+
+    #[test]
+    fn test_decode_frames_on_frame() {
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.frames = (0..5).map(|i| vec![(i, 2, 0, 0)]).collect();
+        let path = std::env::temp_dir().join("emfir_frames_observed.eer");
+        eer.write(&path).unwrap();
+
+        let mut seen = Vec::new();
+        let mut on_frame = |frame_idx: u32, frame: &Array2<u16>| seen.push((frame_idx, frame.sum()));
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let mut file = File::open(&path).unwrap();
+        let options = SumOptions { on_frame: Some(&mut on_frame), ..Default::default() };
+        let observed = decode_frames(&mut decoder, &params, &mut file, 5, Some(2), 1, options).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let plain =
+            decode_frames(&mut decoder, &params, &mut File::open(&path).unwrap(), 5, Some(2), 1, SumOptions::default())
+                .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(seen, vec![(0, 1), (2, 1), (4, 1)]);
+        assert_eq!(observed, plain);
+    }
+
+    #[test]
+    fn test_decode_frames_max_frames() {
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.frames = (0..10).map(|i| vec![(i, 1, 0, 0)]).collect();
+        let path = std::env::temp_dir().join("emfir_decode_max_frames.eer");
+        eer.write(&path).unwrap();
+
+        let decode = |skip_frames, max_frames| {
+            let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+            let params = get_compression_params(&mut decoder).unwrap().params();
+            let options = SumOptions { max_frames, ..Default::default() };
+            let (sum, frames_summed) =
+                decode_frames(&mut decoder, &params, &mut File::open(&path).unwrap(), 10, skip_frames, 1, options)?;
+            Ok::<_, anyhow::Error>(((0..10).filter(|&x| sum[[1, x]] == 1).collect::<Vec<_>>(), frames_summed))
+        };
+        // The first three frames, then the first three of every third
+        assert_eq!(decode(None, Some(3)).unwrap(), (vec![0, 1, 2], 3));
+        assert_eq!(decode(Some(3), Some(3)).unwrap(), (vec![0, 3, 6], 3));
+        // A cap past the end sums everything
+        assert_eq!(decode(Some(3), Some(50)).unwrap().1, 4);
+        assert!(decode(None, Some(0)).is_err());
+
+        let options = DecodeOptions { max_frames: Some(4), skip_frames: Some(2), ..Default::default() };
+        let sum = sum_frames(&path, &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((0..10).map(|x| sum[[1, x]]).collect::<Vec<_>>(), vec![1, 0, 1, 0, 1, 0, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_decode_frame_stack() {
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.frames = (0..6).map(|i| vec![(i, 3, 0, 0)]).collect();
+        eer.frames_per_page = 2;
+        let path = std::env::temp_dir().join("emfir_frame_stack.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let mut file = File::open(&path).unwrap();
+        let stack = decode_frame_stack(&mut decoder, &params, &mut file, 1..5, 1).unwrap();
+        let err = decode_frame_stack(&mut decoder, &params, &mut file, 4..7, 1).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(stack.len(), 4);
+        for (i, frame) in stack.iter().enumerate() {
+            assert_eq!((frame.dim(), frame[[3, i + 1]], frame.sum()), ((16, 16), 1, 1));
+        }
+        assert!(err.to_string().contains("outside the 6 frames"), "{}", err);
+    }
+
+    /// Sets a cancellation token on its first read, i.e. while the first frame decodes
+    struct CancelOnRead<R> {
+        inner: R,
+        cancel: Arc<AtomicBool>,
+        reads: usize,
+    }
+
+    impl<R: Read> Read for CancelOnRead<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.cancel.store(true, Ordering::Relaxed);
+            self.reads += 1;
+            self.inner.read(buf)
+        }
+    }
+
+    impl<R: Seek> Seek for CancelOnRead<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_cancel_decode() {
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.frames = vec![vec![(3, 3, 0, 0)]; 6];
+        let path = std::env::temp_dir().join("emfir_cancel_decode.eer");
+        eer.write(&path).unwrap();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut file = CancelOnRead { inner: File::open(&path).unwrap(), cancel: cancel.clone(), reads: 0 };
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let options = SumOptions { cancel: Some(&cancel), ..Default::default() };
+        let err = decode_frames(&mut decoder, &params, &mut file, 6, None, 1, options).unwrap_err();
+        // Only the first frame's single strip was read
+        assert!(err.is::<Cancelled>());
+        assert_eq!(file.reads, 1);
+
+        let mut file = CancelOnRead { inner: File::open(&path).unwrap(), cancel: cancel.clone(), reads: 0 };
+        cancel.store(false, Ordering::Relaxed);
+        let options = DecodeOptions { cancel: Some(cancel.clone()), partial_on_cancel: true, ..Default::default() };
+        let (partial, partial_params) = sum_frames_reading(&path, &options, &mut file).unwrap();
+        let options = DecodeOptions { partial_on_cancel: false, ..options };
+        let cancelled = sum_frames(&path, &options);
+        // Already cancelled: nothing is decoded and the partial sum is empty
+        let options = DecodeOptions { partial_on_cancel: true, ..options };
+        let empty = sum_frames(&path, &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((partial[[3, 3]], partial_params.frames_summed), (1, 1));
+        assert!(cancelled.unwrap_err().is::<Cancelled>());
+        assert_eq!((empty.dim(), empty.sum()), ((16, 16), 0));
+    }
+
+    #[test]
+    fn test_open_errors() {
+        let output = std::env::temp_dir().join("emfir_open_errors.png");
+        let missing = std::env::temp_dir().join("emfir_missing.eer");
+        let err = header_report(&missing).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(EerError::NotFound(_))));
+
+        let empty = std::env::temp_dir().join("emfir_empty.eer");
+        std::fs::write(&empty, []).unwrap();
+        let err = generate_thumbnail(&empty, &output, &DecodeOptions::default()).unwrap_err();
+        std::fs::remove_file(&empty).unwrap();
+        assert!(matches!(err.downcast_ref(), Some(EerError::EmptyFile(_))));
+
+        // Running as root ignores file modes, so check the mapping directly
+        let denied = EerError::from_open(&missing, std::io::ErrorKind::PermissionDenied.into());
+        assert!(matches!(denied.downcast_ref(), Some(EerError::PermissionDenied(_))));
+        assert!(denied.to_string().starts_with("Permission denied reading"));
+    }
+
+    #[test]
+    fn test_acquisition_mode() {
+        let mode = |value: &str| {
+            EerMetadata::from_xml(&format!(r#"<metadata><item name="acquisitionMode">{}</item></metadata>"#, value))
+                .acquisition_mode()
+        };
+        assert_eq!(mode("ElectronCounting"), Some(AcquisitionMode::Counting));
+        assert_eq!(mode("Integrating"), Some(AcquisitionMode::Integrating));
+        assert_eq!(mode("Unknown"), None);
+        assert_eq!(EerMetadata::default().acquisition_mode(), None);
+    }
+
+    #[test]
+    fn test_voltage_and_magnification() {
+        let metadata = |name: &str, value: &str| {
+            EerMetadata::from_xml(&format!(r#"<metadata><item name="{}">{}</item></metadata>"#, name, value))
+        };
+        for value in ["300 kV", "300kV", "300000", "300000 V", "300"] {
+            assert_eq!(metadata("voltage", value).voltage_kv(), Some(300.0), "{}", value);
+        }
+        assert_eq!(metadata("voltage", "300 mA").voltage_kv(), None);
+        for value in ["105000", "105000x", "x105000", "105000 X"] {
+            assert_eq!(metadata("magnification", value).nominal_magnification(), Some(105000.0), "{}", value);
+        }
+        assert_eq!(metadata("magnification", "high").nominal_magnification(), None);
+
+        // Absent values are left out of the header JSON
+        let mut eer = synthetic::SyntheticEer::new(8, 8);
+        eer.frames = vec![Vec::new()];
+        eer.xml = Some(r#"<metadata><item name="voltage">200 kV</item></metadata>"#.to_string());
+        let path = std::env::temp_dir().join("emfir_voltage.eer");
+        eer.write(&path).unwrap();
+        let json = serde_json::to_value(header_report(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(json["voltage_kv"], 200.0);
+        assert!(json.get("nominal_magnification").is_none());
+    }
+
+    #[test]
+    fn test_integrating_frames() {
+        use tiff::encoder::{colortype, TiffEncoder};
+
+        // Three 4x2 16-bit pages, frame i holding i + 1 everywhere
+        let path = std::env::temp_dir().join("emfir_integrating.eer");
+        let xml = r#"<metadata><item name="acquisitionMode">Integrating</item></metadata>"#;
+        {
+            let mut encoder = TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+            for frame in 0..3u16 {
+                let mut image = encoder.new_image::<colortype::Gray16>(4, 2).unwrap();
+                if frame == 0 {
+                    image.encoder().write_tag(Tag::Unknown(TAG_XML_DATA), xml.as_bytes()).unwrap();
+                }
+                image.write_data(&[frame + 1; 8]).unwrap();
+            }
+        }
+        assert_eq!(read_metadata(&path).unwrap().acquisition_mode(), Some(AcquisitionMode::Integrating));
+        // Unmarked ordinary pages are frames, not a dark reference
+        assert!(dark_reference(&path).unwrap().is_none());
+
+        let options = DecodeOptions { skip_frames: Some(2), ..Default::default() };
+        let (sum, frames_summed) = decode_integrating_frames(&path, &options).unwrap();
+        assert_eq!((sum.dim(), sum[[1, 3]], frames_summed), ((2, 4), 4, 2));
+
+        // Cancelled before the first frame, a partial sum is empty rather than an error
+        let cancel = Arc::new(AtomicBool::new(true));
+        let options = DecodeOptions { cancel: Some(cancel), partial_on_cancel: true, ..Default::default() };
+        let (sum, frames_summed) = decode_integrating_frames(&path, &options).unwrap();
+        assert_eq!((sum.dim(), sum.sum(), frames_summed), ((2, 4), 0, 0));
+        assert_eq!(sum_frames(&path, &options).unwrap().sum(), 0);
+
+        // The thumbnail takes the TIFF path; the bitstream decoder would find no frames
+        let output = std::env::temp_dir().join("emfir_integrating.png");
+        generate_thumbnail(&path, &output, &DecodeOptions::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn test_sum_does_not_wrap() {
+        use tiff::encoder::{colortype, TiffEncoder};
+
+        // A counted frame holds at most one event per pixel, so deposit 1000 counts per
+        // frame through integrating pages: 100 of them sum past u16::MAX
+        let path = std::env::temp_dir().join("emfir_sum_no_wrap.eer");
+        let xml = r#"<metadata><item name="acquisitionMode">Integrating</item></metadata>"#;
+        {
+            let mut encoder = TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+            let mut data = [0u16; 8];
+            data[5] = 1000;
+            for frame in 0..100 {
+                let mut image = encoder.new_image::<colortype::Gray16>(4, 2).unwrap();
+                if frame == 0 {
+                    image.encoder().write_tag(Tag::Unknown(TAG_XML_DATA), xml.as_bytes()).unwrap();
+                }
+                image.write_data(&data).unwrap();
+            }
+        }
+        let sum = sum_frames(&path, &DecodeOptions::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((sum[[1, 1]], sum.sum()), (100_000, 100_000));
+
+        // Counted frames take the same u32 accumulator
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.frames = vec![vec![(3, 4, 0, 0)]; 100];
+        let path = std::env::temp_dir().join("emfir_sum_no_wrap_counted.eer");
+        eer.write(&path).unwrap();
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let (sum, frames_summed) =
+            decode_frames(&mut decoder, &params, &mut File::open(&path).unwrap(), 100, None, 1, SumOptions::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((sum[[4, 3]], sum.sum(), frames_summed), (100, 100, 100));
+    }
+
+    #[test]
+    fn test_sum_frames() {
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.frames = (0..4).map(|i| vec![(2, 5, 0, 0), (i, 0, 0, 0)]).collect();
+        let path = std::env::temp_dir().join("emfir_sum_frames.eer");
+        eer.write(&path).unwrap();
+
+        let sum = sum_frames(&path, &DecodeOptions::default()).unwrap();
+        let options = DecodeOptions { skip_frames: Some(2), ..Default::default() };
+        let skipped = sum_frames(&path, &options).unwrap();
+        let options = DecodeOptions { bin_factor: 3, ..Default::default() };
+        let binned = sum_frames(&path, &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(sum.dim(), (16, 16));
+        assert_eq!((sum[[5, 2]], sum[[0, 3]], sum.sum()), (4, 1, 8));
+        assert_eq!((skipped[[5, 2]], skipped[[0, 1]]), (2, 0));
+        // 3x3 blocks, the last row and column covering one pixel
+        assert_eq!(binned.dim(), (6, 6));
+        assert_eq!((binned[[1, 0]], binned[[0, 0]], binned[[0, 1]]), (4, 3, 1));
+    }
+
+    #[test]
+    fn test_encode_thumbnail() {
+        let mut eer = synthetic::SyntheticEer::new(8, 8);
+        eer.frames = vec![vec![(1, 2, 0, 0)]; 2];
+        let path = std::env::temp_dir().join("emfir_encode_thumbnail.eer");
+        eer.write(&path).unwrap();
+        let (png, params) = encode_thumbnail(&path, &DecodeOptions::default()).unwrap();
+        assert_eq!(params.frames_summed, 2);
+        std::fs::remove_file(&path).unwrap();
+
+        let img = image::load_from_memory_with_format(&png, image::ImageFormat::Png).unwrap().to_luma8();
+        assert_eq!(img.dimensions(), (8, 8));
+        assert_eq!((img.get_pixel(1, 2)[0], img.get_pixel(0, 0)[0]), (255, 0));
+    }
+
+    #[test]
+    fn test_frame_iterator() {
+        // Frame i has one event in column i; two frames per page behind an overview page
+        let mut eer = synthetic::SyntheticEer::new(16, 2);
+        eer.frames = (0..12).map(|i| vec![(i, 0, 0, 0)]).collect();
+        eer.frames_per_page = 2;
+        eer.overview_first = true;
+        let path = std::env::temp_dir().join("emfir_frame_iterator.eer");
+        eer.write(&path).unwrap();
+
+        let frames = frames::FrameIterator::open(&path, Upsampling::Factor(1)).unwrap();
+        assert_eq!(frames.shape(), (2, 16));
+        let sum = frames.skip(3).step_by(2).take(4).sum_into((2, 16)).unwrap();
+        let hit: Vec<usize> = (0..16).filter(|&col| sum[[0, col]] > 0).collect();
+        assert_eq!(hit, vec![3, 5, 7, 9]);
+
+        let frames = frames::FrameIterator::open(&path, Upsampling::Factor(1)).unwrap();
+        assert_eq!(frames.count(), 12);
+        let mut frames = frames::FrameIterator::open(&path, Upsampling::Factor(1)).unwrap();
+        assert!(frames.nth(12).is_none());
+        let frames = frames::FrameIterator::open(&path, Upsampling::Factor(1)).unwrap();
+        assert!(frames.sum_into((4, 4)).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_auto_upsampling_detector_cap() {
+        // 8x super-resolution encoded, but the Falcon 4i records at most 4x
+        let mut eer = synthetic::SyntheticEer::new(4, 4);
+        eer.compression = EerCompression::CUSTOM_CODE;
+        eer.params = CompressionParams { code_len: 7, horz_sub_bits: 3, vert_sub_bits: 3, pos_skip_max: None };
+        eer.xml = Some("<metadata><item name=\"detectorName\">Falcon 4i</item></metadata>".to_string());
+        eer.frames = vec![vec![(1, 1, 0, 0)]; 2];
+        let path = std::env::temp_dir().join("emfir_auto_upsampling_cap.eer");
+        eer.write(&path).unwrap();
+        let output = std::env::temp_dir().join("emfir_auto_upsampling_cap.png");
+
+        let options = DecodeOptions { upsampling: Upsampling::Auto, ..Default::default() };
+        let frames = frames::FrameIterator::open(&path, Upsampling::Auto).unwrap();
+        assert_eq!(frames.shape(), (16, 16));
+        assert_eq!(sum_frames(&path, &options).unwrap().dim(), (16, 16));
+        montage::generate_montage(&path, &output, 1, 2, &options, false).unwrap();
+        let img = image::open(&output).unwrap().into_luma8();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(img.dimensions(), (32, 16));
+    }
+
+    #[test]
+    fn test_montage() {
+        // Frame i has one event in column i of the bottom row
+        let mut eer = synthetic::SyntheticEer::new(8, 8);
+        eer.frames = (0..6).map(|i| vec![(i, 7, 0, 0)]).collect();
+        let path = std::env::temp_dir().join("emfir_montage.eer");
+        eer.write(&path).unwrap();
+        let output = std::env::temp_dir().join("emfir_montage.png");
+
+        // 2x2 grid over 6 frames shows frames 0, 1, 2 and 3
+        montage::generate_montage(&path, &output, 2, 2, &DecodeOptions::default(), false).unwrap();
+        let img = image::open(&output).unwrap().into_luma8();
+        assert_eq!(img.dimensions(), (16, 16));
+        let lit: Vec<_> = img.enumerate_pixels().filter(|p| p.2[0] == 255).map(|p| (p.0, p.1)).collect();
+        assert_eq!(lit, vec![(0, 7), (9, 7), (2, 15), (11, 15)]);
+
+        // More cells than frames leaves the last ones black; labels add white glyphs
+        montage::generate_montage(&path, &output, 2, 4, &DecodeOptions::default(), true).unwrap();
+        let img = image::open(&output).unwrap().into_luma8();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(img.dimensions(), (32, 16));
+        assert!(img.enumerate_pixels().filter(|p| p.1 >= 8 && p.0 >= 16).all(|p| p.2[0] == 0));
+        assert_eq!(img[(2, 1)][0], 255);
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn test_sum_frames_with_digest() {
+        let mut eer = synthetic::SyntheticEer::new(64, 64);
+        eer.xml = Some(r#"<metadata><item name="exposureTime">2</item></metadata>"#.to_string());
+        eer.rows_per_strip = 16;
+        eer.fill_random(8, 200, 5);
+        let path = std::env::temp_dir().join("emfir_digest.eer");
+        eer.write(&path).unwrap();
+        let expected = blake3::hash(&std::fs::read(&path).unwrap());
+
+        for skip_frames in [None, Some(3)] {
+            let options = DecodeOptions { skip_frames, auto_code_len: true, ..Default::default() };
+            let (sum, digest) = sum_frames_with_digest(&path, &options).unwrap();
+            assert_eq!(digest, expected);
+            assert_eq!(sum, sum_frames(&path, &options).unwrap());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rolling_sum() {
+        let mut eer = synthetic::SyntheticEer::new(8, 8);
+        eer.fill_random(7, 10, 3);
+        let path = std::env::temp_dir().join("emfir_rolling_sum.eer");
+        eer.write(&path).unwrap();
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let mut file = File::open(&path).unwrap();
+        let frames: Vec<_> = (0..7)
+            .map(|page| {
+                decoder.seek_to_image(page).unwrap();
+                decode_eer_frame(&mut decoder, &params, &mut file, 1, 1).unwrap()
+            })
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut rolling = rolling::RollingSum::new(3);
+        for (i, frame) in frames.iter().enumerate() {
+            let sum = rolling.push_frame(frame).unwrap().clone();
+            let start = i.saturating_sub(2);
+            let expected = frames[start..=i].iter().fold(Array2::<u32>::zeros((8, 8)), |acc, f| acc + f.mapv(u32::from));
+            assert_eq!(sum, expected, "frame {}", i);
+        }
+        assert_eq!(rolling.len(), 3);
+        assert!(rolling.push_frame(&Array2::zeros((4, 4))).is_err());
+        rolling.clear();
+        assert!(rolling.is_empty() && rolling.sum().is_none());
+    }
+
+    #[test]
+    fn test_capped_frame_count() {
+        assert_eq!(capped_frame_count(100, Some(10), None).unwrap(), 100);
+        // Frames 0, 10 and 20
+        assert_eq!(capped_frame_count(100, Some(10), Some(3)).unwrap(), 21);
+        assert_eq!(capped_frame_count(100, None, Some(10)).unwrap(), 10);
+        assert_eq!(capped_frame_count(5, None, Some(10)).unwrap(), 5);
+        assert!(capped_frame_count(5, None, Some(0)).is_err());
+        assert!(capped_frame_count(5, Some(0), None).is_err());
+
+        let mut eer = synthetic::SyntheticEer::new(8, 8);
+        eer.fill_random(3, 5, 2);
+        let path = std::env::temp_dir().join("emfir_zero_frame_skip.eer");
+        eer.write(&path).unwrap();
+        let stats = frame_stats(&path, Some(0));
+        std::fs::remove_file(&path).unwrap();
+        assert!(stats.unwrap_err().to_string().contains("Frame skip"));
+    }
+
+    #[test]
+    fn test_thumbnail_memory_limit() {
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.frames = vec![vec![(1, 1, 0, 0)]];
+        let path = std::env::temp_dir().join("emfir_memory_limit.eer");
+        eer.write(&path).unwrap();
+
+        let options = DecodeOptions { memory_limit: Some(1000), ..Default::default() };
+        let output = std::env::temp_dir().join("emfir_memory_limit.png");
+        let err = generate_thumbnail(&path, &output, &options).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.to_string().contains("memory limit"), "{}", err);
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_decode_frames_weighted() {
+        let mut eer = synthetic::SyntheticEer::new(8, 8);
+        eer.frames = vec![vec![(2, 2, 0, 0)], vec![(2, 2, 0, 0), (4, 4, 0, 0)], vec![(4, 4, 0, 0)]];
+        let path = std::env::temp_dir().join("emfir_frames_weighted.eer");
+        eer.write(&path).unwrap();
+
+        let open = || Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = EerCompression::Count7.params();
+        let sum = decode_frames_weighted(&mut open(), &params, &mut File::open(&path).unwrap(), 3, None, 1, &[1.0, 0.5, 0.25]).unwrap();
+        assert_eq!((sum[[2, 2]], sum[[4, 4]]), (1.5, 0.75));
+        // Frames 0 and 2
+        let sum = decode_frames_weighted(&mut open(), &params, &mut File::open(&path).unwrap(), 3, Some(2), 1, &[2.0, 4.0]).unwrap();
+        assert_eq!((sum[[2, 2]], sum[[4, 4]]), (2.0, 4.0));
+
+        let err = decode_frames_weighted(&mut open(), &params, &mut File::open(&path).unwrap(), 3, None, 1, &[1.0]).unwrap_err();
+        assert_eq!(err.to_string(), "Got 1 weights for 3 frames to process");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_decode_frames_stats() {
+        let mut eer = synthetic::SyntheticEer::new(4, 4);
+        // Pixel (1, 1) is hit in frames 0, 1 and 3; pixel (2, 3) in every frame
+        let hits = [true, true, false, true];
+        eer.frames = hits
+            .iter()
+            .map(|&hit| if hit { vec![(1, 1, 0, 0), (2, 3, 0, 0)] } else { vec![(2, 3, 0, 0)] })
+            .collect();
+        let path = std::env::temp_dir().join("emfir_frames_stats.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = EerCompression::Count7.params();
+        let (mean, variance) = decode_frames_stats(&mut decoder, &params, &mut File::open(&path).unwrap(), 4, None, 1).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!((mean[[1, 1]] - 0.75).abs() < 1e-6);
+        // Sample variance of [1, 1, 0, 1]
+        assert!((variance[[1, 1]] - 0.25).abs() < 1e-6);
+        assert_eq!((mean[[3, 2]], variance[[3, 2]]), (1.0, 0.0));
+        assert_eq!((mean[[0, 0]], variance[[0, 0]]), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_packed_frames_per_page() {
+        let mut eer = synthetic::SyntheticEer::new(8, 8);
+        eer.rows_per_strip = 4;
+        eer.frames_per_page = 3;
+        // Five frames over two pages (3 + 2), frame i hitting pixel (i, 6)
+        eer.frames = (0..5).map(|i| vec![(i, 6, 0, 0), (7, 1, 0, 0)]).collect();
+        let path = std::env::temp_dir().join("emfir_packed_frames.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(count_frames(&mut decoder).unwrap(), 5);
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(frames_in_page(&mut decoder).unwrap(), 3);
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let page = decode_page_frames(&mut decoder, &params, &mut File::open(&path).unwrap(), 1).unwrap();
+        assert_eq!(page.len(), 3);
+        assert_eq!((page[2][[6, 2]], page[2][[6, 1]], page[2][[1, 7]]), (1, 0, 1));
+        // The one-frame-per-page decoders refuse packed pages rather than drop frames
+        assert!(decode_eer_frame(&mut decoder, &params, &mut File::open(&path).unwrap(), 1, 1).is_err());
+        assert!(decode_frame_events(&mut decoder, &params, &mut File::open(&path).unwrap()).is_err());
+
+        let frames: Vec<_> = frames::FrameIterator::open(&path, Upsampling::Factor(1)).unwrap()
+            .map(|frame| frame.unwrap())
+            .collect();
+        assert_eq!(frames.len(), 5);
+        assert!(frames.iter().enumerate().all(|(i, frame)| frame[[6, i]] == 1 && frame.sum() == 2));
+
+        // Every other frame: 0 and 2 from the first page, 4 from the second
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = params.clone();
+        let (sum, frames_summed) = decode_frames(&mut decoder, &params, &mut File::open(&path).unwrap(), 5, Some(2), 1, SumOptions::default()).unwrap();
+        assert_eq!(frames_summed, 3);
+        let row: Vec<u32> = (0..5).map(|x| sum[[6, x]]).collect();
+        assert_eq!(row, vec![1, 0, 1, 0, 1]);
+        assert_eq!(sum[[1, 7]], 3);
+
+        assert_eq!(dose_stats(&path).unwrap().frames, 5);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_decode_frames_skips_overview_page() {
+        let mut eer = synthetic::SyntheticEer::new(16, 16);
+        eer.frames = vec![vec![(3, 3, 0, 0)], vec![(3, 3, 0, 0), (5, 9, 0, 0)]];
+        eer.overview_first = true;
+        let path = std::env::temp_dir().join("emfir_overview_first.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        assert!(!is_frame_page(&mut decoder).unwrap());
+        assert_eq!(count_frames(&mut decoder).unwrap(), 2);
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = EerCompression::Count7.params();
+        let (sum, frames_summed) =
+            decode_frames(&mut decoder, &params, &mut File::open(&path).unwrap(), 2, None, 1, SumOptions::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(frames_summed, 2);
+        assert_eq!(sum[[3, 3]], 2);
+        assert_eq!(sum[[9, 5]], 1);
+    }
+
+    #[test]
+    fn test_metadata_after_overview_page() {
+        let mut eer = synthetic::SyntheticEer::new(10, 10);
+        eer.frames = vec![(0..10).map(|x| (x, x, 0, 0)).collect(); 4];
+        eer.overview_first = true;
+        eer.xml = Some(concat!(
+            "<metadata><item name=\"meanDoseRate\">0.25</item><item name=\"exposureTime\">2</item>",
+            "<item name=\"sensorPixelSize.width\">1.2e-10</item></metadata>",
+        ).to_string());
+        let path = std::env::temp_dir().join("emfir_overview_metadata.eer");
+        eer.write(&path).unwrap();
+
+        let report = header_report(&path);
+        let (metadata, raw, stats) = (read_metadata(&path), read_raw_xml(&path), dose_stats(&path));
+        std::fs::remove_file(&path).unwrap();
+
+        let report = report.unwrap();
+        assert_eq!(report.metadata["exposureTime"], "2");
+        assert_eq!(report.image_data.voxel_spacing_x, 1.2e-10);
+        assert_eq!((report.image_data.size_x, report.frame_count), (10, 4));
+        assert_eq!(metadata.unwrap().reported_dose(), Some(0.5));
+        assert_eq!(raw.unwrap().as_deref(), eer.xml.as_deref().map(str::as_bytes));
+        let stats = stats.unwrap();
+        assert_eq!((stats.frames, stats.reported_dose), (4, Some(0.5)));
+        assert!((stats.measured_dose - 0.4).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_detect_code_len_mislabelled() {
+        // Tagged as 65001 (7-bit) but encoded with 8-bit codes
+        let mut eer = synthetic::SyntheticEer::new(256, 64);
+        eer.rows_per_strip = 16;
+        eer.params.code_len = 8;
+        eer.fill_random(3, 500, 7);
+        let path = std::env::temp_dir().join("emfir_detect_code_len.eer");
+        eer.write(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let params = get_compression_params(&mut decoder).unwrap().params();
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(params.code_len, 7);
+        assert_eq!(detect_code_len(&mut decoder, &params, &mut file).unwrap(), 8);
+
+        // Resolved once, the detected length decodes every page
+        let detected = CompressionParams { code_len: 8, ..params.clone() };
+        let (sum, frames_summed) =
+            decode_frames(&mut decoder, &detected, &mut file, 3, None, 1, SumOptions::default()).unwrap();
+        // Repeated pixels within a frame are written once
+        let events: usize = eer
+            .frames
+            .iter()
+            .map(|frame| frame.iter().map(|e| (e.0, e.1)).collect::<HashSet<_>>().len())
+            .sum();
+        assert_eq!((sum.sum() as usize, frames_summed), (events, 3));
+
+        // Correctly labelled 7-bit data keeps its tag value
+        eer.params.code_len = 7;
+        eer.write(&path).unwrap();
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(detect_code_len(&mut decoder, &params, &mut file).unwrap(), 7);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+/// Order of the bits within each byte of a [`BitStream`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitOrder {
+    /// Least significant bit first, with earlier bits in the low bits of a value, as in EER
+    #[default]
+    LsbFirst,
+    /// Most significant bit first, with earlier bits in the high bits of a value
+    MsbFirst,
+}
+
+/// BitStream provides bit-level reading capabilities from a byte buffer
+pub struct BitStream<'a> {
+    buffer: &'a [u8],
+    bit_pos: usize,  // index of next bit to read (from the start of buffer)
+    order: BitOrder,
+}
+
+impl<'a> BitStream<'a> {
+    /// Creates a new BitStream from a byte slice, reading LSB first
+    pub fn new(data_bytes: &'a [u8]) -> Self {
+        Self::with_order(data_bytes, BitOrder::LsbFirst)
+    }
+
+    /// Creates a new BitStream reading the bits of each byte in `order`
+    pub fn with_order(data_bytes: &'a [u8], order: BitOrder) -> Self {
+        BitStream {
+            buffer: data_bytes,
+            bit_pos: 0,
+            order,
+        }
+    }
+
+    /// Reads n bits in the stream's bit order and returns integer value
+    #[inline(always)]
+    pub fn get_bits(&mut self, n: u32) -> u32 {
+        debug_assert!(n <= 32);
+        
+        let byte_index = self.bit_pos / 8;
+        let bit_offset = self.bit_pos % 8;
+        // Past the end of a truncated strip: read zeros rather than underflow below
+        if byte_index >= self.buffer.len() {
+            self.bit_pos += n as usize;
+            return 0;
+        }
+        
+        // Read 8 bytes (or less if at end of buffer); missing bytes stay zero. A u64
+        // holds all 32 bits even when they start at bit 7 of the first byte.
+        let bytes = self.buffer.get(byte_index..byte_index + 8);
+        let val = match self.order {
+            BitOrder::LsbFirst => {
+                let chunk = match bytes {
+                    Some(bytes) => u64::from_le_bytes(bytes.try_into().unwrap()),
+                    None => self.buffer[byte_index..]
+                        .iter()
+                        .enumerate()
+                        .fold(0, |chunk, (i, &byte)| chunk | (byte as u64) << (i * 8)),
+                };
+                // Extract n bits starting at bit_offset
+                let mask = (1u64 << n) - 1;
+                ((chunk >> bit_offset) & mask) as u32
+            }
+            BitOrder::MsbFirst => {
+                let chunk = match bytes {
+                    Some(bytes) => u64::from_be_bytes(bytes.try_into().unwrap()),
+                    None => self.buffer[byte_index..]
+                        .iter()
+                        .enumerate()
+                        .fold(0, |chunk, (i, &byte)| chunk | (byte as u64) << (56 - i * 8)),
+                };
+                // The n bits after bit_offset, counted from the top; two shifts so n = 0 works
+                ((chunk << bit_offset) >> 32 >> (32 - n)) as u32
+            }
+        };
+        
+        self.bit_pos += n as usize;
+        val
+    }
+
+    /// Rewinds to the first bit, to read the same buffer again
+    pub fn reset(&mut self) {
+        self.bit_pos = 0;
+    }
+
+    /// Moves to bit `pos` of the buffer; `pos` may be the end but not past it
+    pub fn seek_bits(&mut self, pos: usize) {
+        debug_assert!(pos <= self.buffer.len() * 8, "seek to bit {} of a {}-bit buffer", pos, self.buffer.len() * 8);
+        self.bit_pos = pos;
+    }
+
+    /// Skips to the start of the next byte, unless already at one
+    pub fn align_to_byte(&mut self) {
+        self.bit_pos = self.bit_pos.next_multiple_of(8);
+    }
+
+    /// Returns true if there are no more bits left to read
+    pub fn no_bits_left(&self) -> bool {
+        (self.buffer.len() * 8) <= self.bit_pos
+    }
+
+    /// Returns the number of unread bits in the buffer
+    pub fn bits_left(&self) -> usize {
+        (self.buffer.len() * 8).saturating_sub(self.bit_pos)
+    }
+}
+
+// Custom TIFF tags for EER format
+pub(crate) const TAG_POS_SKIP_BITS: u16 = 65007;
+pub(crate) const TAG_HORZ_SUB_BITS: u16 = 65008;
+pub(crate) const TAG_VERT_SUB_BITS: u16 = 65009;
+pub const TAG_XML_DATA: u16 = 65001;
+/// Frames packed into one page, a private tag of the `synthetic` writer; see [`frames_in_page`]
+pub(crate) const TAG_FRAMES_PER_PAGE: u16 = 65010;
+/// Non-zero on a page holding the dark reference rather than a frame; see [`dark_reference`]
+pub const TAG_DARK_REFERENCE: u16 = 65011;
+
+// NewSubfileType bit marking a reduced-resolution (overview) page
+const SUBFILE_REDUCED_IMAGE: u32 = 0x1;
+
+pub fn parse_xml_metadata(xml_str: &str) -> HashMap<String, String> {
+    let mut reader = Reader::from_str(xml_str);
+    let mut buf = Vec::new();
+    let mut metadata = HashMap::new();
+    let mut current_name = None;
+    
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(ref e)) if e.name().as_ref() == b"item" => {
+                // Get the name attribute
+                for attr in e.attributes().flatten() {
+                    if attr.key.as_ref() == b"name" {
+                        if let Ok(name) = String::from_utf8(attr.value.to_vec()) {
+                            current_name = Some(name);
+                        }
+                    }
+                }
+            },
+            Ok(Event::Text(e)) => {
+                if let (Some(name), Ok(text)) = (current_name.as_ref(), e.unescape()) {
+                    let text = text.trim();
+                    if !text.is_empty() {
+                        metadata.insert(name.clone(), text.to_string());
+                    }
+                }
+            },
+            Ok(Event::End(ref e)) if e.name().as_ref() == b"item" => {
+                current_name = None;
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => eprintln!("Error parsing XML: {}", e),
+            _ => (),
+        }
+    }
+    buf.clear();
+    
+    metadata
+}
+
+/// Relative difference between reported and measured dose above which they are flagged
+pub const DOSE_MISMATCH_TOLERANCE: f64 = 0.1;
+
+/// The EER XML metadata, with typed accessors for the fields the tools use
+#[derive(Debug, Clone, Default)]
+pub struct EerMetadata {
+    /// Every item from the XML, by name
+    pub items: HashMap<String, String>,
+}
+
+impl EerMetadata {
+    pub fn from_xml(xml_str: &str) -> Self {
+        EerMetadata { items: parse_xml_metadata(xml_str) }
+    }
+
+    fn get_f64(&self, name: &str) -> Option<f64> {
+        self.items.get(name)?.parse().ok().filter(|v: &f64| v.is_finite())
+    }
+
+    /// Total dose reported by the microscope, in electrons per sensor pixel over the
+    /// whole exposure. Taken from `meanDoseRate` (e/pixel/s) times `exposureTime` (s),
+    /// or else from `totalDose` (e/A^2) and `sensorPixelSize` (m). None when the XML
+    /// records neither.
+    pub fn reported_dose(&self) -> Option<f64> {
+        if let (Some(rate), Some(time)) = (self.get_f64("meanDoseRate"), self.get_f64("exposureTime")) {
+            return Some(rate * time);
+        }
+        let total = self.get_f64("totalDose")?;
+        let width = self.get_f64("sensorPixelSize.width")? * 1e10;
+        let height = self.get_f64("sensorPixelSize.height")? * 1e10;
+        Some(total * width * height)
+    }
+
+    /// Acquisition mode from the `acquisitionMode` (or `detectorMode`) item, e.g.
+    /// "ElectronCounting" or "Integrating". None when absent or unrecognised.
+    pub fn acquisition_mode(&self) -> Option<AcquisitionMode> {
+        let value = ["acquisitionMode", "detectorMode"]
+            .iter()
+            .find_map(|key| self.items.get(*key))?
+            .to_ascii_lowercase();
+        if value.contains("count") {
+            Some(AcquisitionMode::Counting)
+        } else if value.contains("integrat") || value.contains("linear") {
+            Some(AcquisitionMode::Integrating)
+        } else {
+            None
+        }
+    }
+
+    /// Accelerating voltage in kV from the `voltage` (or `acceleratingVoltage`,
+    /// `highTension`) item. Values may carry a `kV` or `V` suffix; bare numbers above
+    /// 1000 are taken as volts.
+    pub fn voltage_kv(&self) -> Option<f64> {
+        let (value, unit) = ["voltage", "acceleratingVoltage", "highTension"]
+            .iter()
+            .find_map(|key| self.items.get(*key))
+            .and_then(|value| split_unit(value))?;
+        match unit.to_ascii_lowercase().as_str() {
+            "kv" => Some(value),
+            "v" => Some(value / 1000.0),
+            "" if value > 1000.0 => Some(value / 1000.0),
+            "" => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Nominal magnification from the `magnification` (or `nominalMagnification`) item,
+    /// with or without an `x` before or after the number
+    pub fn nominal_magnification(&self) -> Option<f64> {
+        let (value, unit) = ["magnification", "nominalMagnification"]
+            .iter()
+            .find_map(|key| self.items.get(*key))
+            .and_then(|value| split_unit(value.trim().trim_start_matches(['x', 'X'])))?;
+        matches!(unit, "" | "x" | "X").then_some(value)
+    }
+
+    /// Detector model from the `detectorName` (or `cameraName`, `detector`) item, e.g.
+    /// "Falcon 4i"
+    pub fn detector_model(&self) -> Option<&str> {
+        ["detectorName", "cameraName", "detector"]
+            .iter()
+            .find_map(|key| self.items.get(*key))
+            .map(|name| name.trim())
+    }
+
+    /// Preset for the detector model, if it is a known one
+    pub fn detector_preset(&self) -> Option<&'static DetectorPreset> {
+        DetectorPreset::lookup(self.detector_model()?)
+    }
+}
+
+/// Splits a value such as "300 kV" into its finite number and the (trimmed) unit after it
+fn split_unit(value: &str) -> Option<(f64, &str)> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E')))
+        .unwrap_or(value.len());
+    let number: f64 = value[..end].parse().ok().filter(|v: &f64| v.is_finite())?;
+    Some((number, value[end..].trim()))
+}
+
+/// How the detector recorded the frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AcquisitionMode {
+    /// Electron events, stored as the EER run-length bitstream
+    Counting,
+    /// Integrated intensities, stored as ordinary TIFF images
+    Integrating,
+}
+
+/// Assembles the bytes of the XML tag however the writer typed it: an ASCII string, a
+/// single byte, or a list of bytes, shorts or strings. The decoder widens BYTE values to
+/// integers, so integer items are taken as character codes.
+fn xml_bytes_from_value(value: Value) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut push = |value: Value| -> Result<()> {
+        match value {
+            Value::Ascii(text) => bytes.extend_from_slice(text.as_bytes()),
+            Value::Byte(b) => bytes.push(b),
+            Value::Short(c) => bytes.push(u8::try_from(c)?),
+            Value::Unsigned(c) => bytes.push(u8::try_from(c)?),
+            Value::UnsignedBig(c) => bytes.push(u8::try_from(c)?),
+            other => return Err(anyhow!("Unexpected value {:?} in XML tag", other)),
+        }
+        Ok(())
+    };
+    match value {
+        Value::List(values) => values.into_iter().try_for_each(&mut push)?,
+        value => push(value)?,
+    }
+    Ok(bytes)
+}
+
+/// Extracts the XML text from the XML tag, decompressing it if needed
+fn xml_from_value(value: Value) -> Result<String> {
+    let mut bytes = xml_bytes_from_value(value)?;
+    // Some writers compress the XML; plain XML starts with '<'
+    if bytes.iter().find(|b| !b.is_ascii_whitespace()).is_some_and(|&b| b != b'<') {
+        match decompress_xml(&bytes)? {
+            Some((format, xml)) => {
+                eprintln!("XML metadata is {}-compressed", format);
+                bytes = xml;
+            }
+            None => eprintln!("XML metadata does not start with '<' and is not compressed, reading as text"),
+        }
+    }
+    let text = String::from_utf8(bytes)?;
+    Ok(text.trim_end_matches('\0').to_string())
+}
+
+/// Largest decompressed XML metadata accepted. Real metadata is a few kilobytes; the
+/// cap stops a corrupt or hostile tag from inflating without bound.
+const MAX_XML_BYTES: u64 = 1 << 20;
+
+/// Tries zlib, gzip and raw deflate in turn, returning the format name and the
+/// decompressed bytes of the first that decodes to something starting with '<'.
+/// Fails if a format inflates past [`MAX_XML_BYTES`].
+fn decompress_xml(bytes: &[u8]) -> Result<Option<(&'static str, Vec<u8>)>> {
+    fn inflate(reader: impl Read) -> Result<Option<Vec<u8>>> {
+        let mut out = Vec::new();
+        // One byte past the cap tells oversized output from XML of exactly the cap
+        if reader.take(MAX_XML_BYTES + 1).read_to_end(&mut out).is_err() {
+            return Ok(None);
+        }
+        if out.len() as u64 > MAX_XML_BYTES {
+            return Err(anyhow!("Compressed XML metadata inflates past {} bytes", MAX_XML_BYTES));
+        }
+        Ok(out.trim_ascii_start().starts_with(b"<").then_some(out))
+    }
+    if let Some(xml) = inflate(flate2::read::ZlibDecoder::new(bytes))? {
+        return Ok(Some(("zlib", xml)));
+    }
+    if let Some(xml) = inflate(flate2::read::GzDecoder::new(bytes))? {
+        return Ok(Some(("gzip", xml)));
+    }
+    Ok(inflate(flate2::read::DeflateDecoder::new(bytes))?.map(|xml| ("deflate", xml)))
+}
+
+/// The XML tag of the first frame page, or of page 0 when the frame page has none. Files
+/// that start with a ReducedImage overview keep their metadata on the first frame.
+fn find_xml_tag(decoder: &mut Decoder<File>) -> Result<Option<Value>> {
+    let mut page = 0;
+    if seek_frame_page(decoder, &mut page)? {
+        if let Some(value) = decoder.find_tag(Tag::Unknown(TAG_XML_DATA))? {
+            return Ok(Some(value));
+        }
+    }
+    load_page(decoder, 0)?;
+    Ok(decoder.find_tag(Tag::Unknown(TAG_XML_DATA))?)
+}
+
+/// Reads the XML metadata from the first frame page, if the file has any
+pub fn read_metadata(path: &Path) -> Result<EerMetadata> {
+    let mut decoder = Decoder::new(open_input(path)?)?;
+    let xml = match find_xml_tag(&mut decoder)? {
+        Some(value) => xml_from_value(value)?,
+        None => return Ok(EerMetadata::default()),
+    };
+    Ok(EerMetadata::from_xml(&xml))
+}
+
+/// [`read_metadata`], with empty metadata for unreadable XML: that shouldn't stop a
+/// decode of counting data, though file errors still do
+pub(crate) fn read_metadata_or_default(path: &Path) -> Result<EerMetadata> {
+    match read_metadata(path) {
+        Ok(metadata) => Ok(metadata),
+        Err(e) if e.is::<EerError>() => Err(e),
+        Err(_) => Ok(EerMetadata::default()),
+    }
+}
+
+/// The XML tag of the first frame page exactly as stored, neither decompressed nor
+/// re-encoded, for archiving the original metadata; None if the file has no XML
+pub fn read_raw_xml(path: &Path) -> Result<Option<Vec<u8>>> {
+    let mut decoder = Decoder::new(open_input(path)?)?;
+    find_xml_tag(&mut decoder)?.map(xml_bytes_from_value).transpose()
+}
+
+/// Reported versus measured dose, to catch decode problems and metadata errors
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DoseStats {
+    pub frames: u32,
+    pub total_counts: u64,
+    /// Counted electrons per sensor pixel over all frames
+    pub measured_dose: f64,
+    /// See [`EerMetadata::reported_dose`]
+    pub reported_dose: Option<f64>,
+    /// `(measured - reported) / reported`
+    pub relative_difference: Option<f64>,
+}
+
+impl DoseStats {
+    /// True when the reported and measured dose differ by more than [`DOSE_MISMATCH_TOLERANCE`]
+    pub fn dose_mismatch(&self) -> bool {
+        self.relative_difference.is_some_and(|d| d.abs() > DOSE_MISMATCH_TOLERANCE)
+    }
+}
+
+/// Decodes every frame, counting electrons, and compares the measured dose with the dose
+/// reported in the XML metadata
+pub fn dose_stats(path: &Path) -> Result<DoseStats> {
+    let reported_dose = read_metadata(path)?.reported_dose();
+    let mut decoder = Decoder::new(File::open(path)?)?;
+    let mut file = File::open(path)?;
+
+    let mut frames = 0;
+    let mut total_counts = 0u64;
+    let mut page = 0;
+    let mut more = seek_frame_page(&mut decoder, &mut page)?;
+    // The sensor size of the frames, not of a leading overview
+    let (width, height) = decoder.dimensions()?;
+    while more {
+        let params = get_compression_params(&mut decoder)?.params();
+        for frame in decode_page_frames(&mut decoder, &params, &mut file, 1)? {
+            total_counts += frame.iter().map(|&c| c as u64).sum::<u64>();
+            frames += 1;
+        }
+        more = next_frame_page(&mut decoder, &mut page)?;
+    }
+
+    let measured_dose = total_counts as f64 / (width as f64 * height as f64);
+    let relative_difference = reported_dose
+        .filter(|&reported| reported > 0.0)
+        .map(|reported| (measured_dose - reported) / reported);
+    Ok(DoseStats { frames, total_counts, measured_dose, reported_dose, relative_difference })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CompressionParams {
+    pub code_len: u32,
+    pub horz_sub_bits: u32,
+    pub vert_sub_bits: u32,
+    /// Overrides the skip sentinel, see [`CompressionParams::pos_skip_max`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pos_skip_max: Option<u32>,
+}
+
+impl CompressionParams {
+    /// The run-length value that means "skip this many pixels, no event follows".
+    ///
+    /// Each event starts with a `code_len`-bit count of empty pixels before it. Longer
+    /// gaps are written as repeated sentinels, each skipping that many pixels with no
+    /// sub-pixel bits after it. Standard files use the largest code, `2^code_len - 1`;
+    /// set `pos_skip_max` for variants that reserve a different value.
+    pub fn pos_skip_max(&self) -> u32 {
+        self.pos_skip_max.unwrap_or((1 << self.code_len) - 1)
+    }
+
+    /// Sets the skip sentinel, see [`CompressionParams::validate`] for the accepted range
+    pub fn with_pos_skip_max(self, pos_skip_max: u32) -> Result<Self> {
+        let params = CompressionParams { pos_skip_max: Some(pos_skip_max), ..self };
+        params.validate()?;
+        Ok(params)
+    }
+
+    /// Checks the bit layout can be decoded: run-length codes of 1 to 16 bits and a
+    /// skip sentinel in `1..=2^code_len - 1`. A zero sentinel would never advance past
+    /// a gap, and a larger one can't be written in `code_len` bits.
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=16).contains(&self.code_len) {
+            return Err(anyhow!("Run-length code length {} is outside 1..=16 bits", self.code_len));
+        }
+        let largest = (1 << self.code_len) - 1;
+        if let Some(pos_skip_max) = self.pos_skip_max {
+            if !(1..=largest).contains(&pos_skip_max) {
+                return Err(anyhow!(
+                    "pos_skip_max {} is outside 1..={} for {}-bit codes",
+                    pos_skip_max, largest, self.code_len
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// EER compression schemes, as stored in the TIFF Compression tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EerCompression {
+    /// 65000: 8-bit run-length codes, 2+2 sub-pixel bits
+    Count8,
+    /// 65001: 7-bit run-length codes, 2+2 sub-pixel bits
+    Count7,
+    /// 65002: bit layout given by the custom `TAG_POS_SKIP_BITS`/`TAG_*_SUB_BITS` tags
+    Custom {
+        code_len: u32,
+        horz_sub_bits: u32,
+        vert_sub_bits: u32,
+    },
+}
+
+impl EerCompression {
+    pub const COUNT8_CODE: u32 = 65000;
+    pub const COUNT7_CODE: u32 = 65001;
+    pub const CUSTOM_CODE: u32 = 65002;
+
+    /// Returns true if a TIFF Compression value is one of the EER schemes
+    pub fn is_eer_code(compression: u32) -> bool {
+        matches!(compression, Self::COUNT8_CODE | Self::COUNT7_CODE | Self::CUSTOM_CODE)
+    }
+
+    /// The TIFF Compression tag value for this scheme
+    pub fn code(&self) -> u32 {
+        match self {
+            EerCompression::Count8 => Self::COUNT8_CODE,
+            EerCompression::Count7 => Self::COUNT7_CODE,
+            EerCompression::Custom { .. } => Self::CUSTOM_CODE,
+        }
+    }
+
+    /// Reads the compression scheme of the decoder's current page,
+    /// including the custom bit-layout tags for 65002
+    pub fn read(decoder: &mut Decoder<File>) -> Result<Self> {
+        let compression = decoder.get_tag_u32(Tag::Compression)?;
+        if compression == Self::CUSTOM_CODE {
+            return Ok(EerCompression::Custom {
+                code_len: decoder.get_tag_u32(Tag::Unknown(TAG_POS_SKIP_BITS))?,
+                horz_sub_bits: decoder.get_tag_u32(Tag::Unknown(TAG_HORZ_SUB_BITS))?,
+                vert_sub_bits: decoder.get_tag_u32(Tag::Unknown(TAG_VERT_SUB_BITS))?,
+            });
+        }
+        EerCompression::try_from(compression)
+    }
+
+    /// Bit layout used to decode this scheme
+    pub fn params(&self) -> CompressionParams {
+        match *self {
+            EerCompression::Count8 => CompressionParams {
+                code_len: 8,
+                horz_sub_bits: 2,
+                vert_sub_bits: 2,
+                pos_skip_max: None,
+            },
+            EerCompression::Count7 => CompressionParams {
+                code_len: 7,
+                horz_sub_bits: 2,
+                vert_sub_bits: 2,
+                pos_skip_max: None,
+            },
+            EerCompression::Custom { code_len, horz_sub_bits, vert_sub_bits } => CompressionParams {
+                code_len,
+                horz_sub_bits,
+                vert_sub_bits,
+                pos_skip_max: None,
+            },
+        }
+    }
+}
+
+impl TryFrom<u32> for EerCompression {
+    type Error = anyhow::Error;
+
+    /// Converts a bare Compression tag value. 65002 is rejected because its bit
+    /// layout lives in separate tags; use [`EerCompression::read`] for those files.
+    fn try_from(compression: u32) -> Result<Self> {
+        match compression {
+            Self::COUNT8_CODE => Ok(EerCompression::Count8),
+            Self::COUNT7_CODE => Ok(EerCompression::Count7),
+            Self::CUSTOM_CODE => Err(anyhow!(
+                "Compression 65002 needs its bit layout from the custom EER tags"
+            )),
+            _ => Err(anyhow!("Unsupported compression type: {}", compression)),
+        }
+    }
+}
+
+/// Reads the compression scheme of the decoder's current page, checking that its bit
+/// layout can be decoded; [`EerCompression::params`] gives the layout itself
+pub fn get_compression_params(decoder: &mut Decoder<File>) -> Result<EerCompression> {
+    let compression = EerCompression::read(decoder)?;
+    compression.params().validate()?;
+    Ok(compression)
+}
+
+/// Returns the native super-resolution factor supported by the file's sub-pixel bits
+///
+/// Each sub-pixel bit doubles the resolution along its axis, so a file with
+/// 2 horizontal and 2 vertical sub-pixel bits can be rendered at up to 4x.
+pub fn recommended_upsampling(params: &CompressionParams) -> u8 {
+    let sub_bits = params.horz_sub_bits.min(params.vert_sub_bits).min(7);
+    1 << sub_bits
+}
+
+/// Upsampling factor requested for decoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Upsampling {
+    /// Use the native super-resolution of the file, see [`recommended_upsampling`]
+    Auto,
+    /// Use a fixed factor (1 = sensor resolution)
+    Factor(u32),
+}
+
+impl Upsampling {
+    /// Resolves the requested upsampling against the file's compression parameters
+    pub fn resolve(self, params: &CompressionParams) -> u32 {
+        match self {
+            Upsampling::Auto => recommended_upsampling(params) as u32,
+            Upsampling::Factor(factor) => factor,
+        }
+    }
+}
+
+impl std::str::FromStr for Upsampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Upsampling::Auto);
+        }
+        match s.parse::<u32>() {
+            Ok(factor) if factor.is_power_of_two() => Ok(Upsampling::Factor(factor)),
+            _ => Err(format!("Invalid upsampling '{}': expected 'auto' or a power of two", s)),
+        }
+    }
+}
+
+pub fn compression_to_string(compression: u32) -> &'static str {
+    match compression {
+        1 => "None",
+        2 => "CCITT Group 3",
+        3 => "CCITT T4",
+        4 => "CCITT T6",
+        5 => "LZW",
+        6 => "JPEG (old)",
+        7 => "JPEG",
+        8 => "Adobe Deflate",
+        9 => "JBIG B&W",
+        10 => "JBIG Color",
+        32773 => "PackBits",
+        _ => "Unknown",
+    }
+}
+
+pub fn sample_format_to_string(format: u32) -> &'static str {
+    match format {
+        1 => "Unsigned integer",
+        2 => "Signed integer",
+        3 => "IEEE floating point",
+        4 => "Undefined",
+        _ => "Unknown",
+    }
+}
+
+/// Bins of each histogram pass in [`count_at_rank`]
+const HISTOGRAM_BINS: usize = 1024;
+
+/// Estimates display limits, in counts, from the histogram of `image`, clipping
+/// [`mrc::AUTO_CONTRAST_TAIL`] of the pixels at each tail so hot pixels and empty regions
+/// don't set the scale, as MRC thumbnails do.
+/// The limits are exact pixel counts, found in at most four histogram passes over the
+/// image with constant extra memory.
+pub fn auto_contrast(image: &Array2<u32>) -> (f32, f32) {
+    if image.is_empty() {
+        return (0.0, 0.0);
+    }
+    let clip = ((image.len() as f64 * AUTO_CONTRAST_TAIL) as usize).min(image.len() - 1);
+    let low = count_at_rank(image, clip);
+    let high = count_at_rank(image, image.len() - 1 - clip);
+    (low as f32, high.max(low) as f32)
+}
+
+/// The count of 0-based `rank` among the sorted pixels of a non-empty `image`, without
+/// sorting or copying it. Each pass histograms the counts inside the current window into
+/// at most [`HISTOGRAM_BINS`] bins and narrows the window to the bin holding the rank,
+/// until bins are a single count wide: exact, in O(bins) memory and at most four passes
+/// over the whole u32 range, so a saturated pixel costs no more than any other.
+fn count_at_rank(image: &Array2<u32>, mut rank: usize) -> u32 {
+    let (mut low, mut high) = image.iter().fold((u32::MAX, 0), |(lo, hi), &c| (lo.min(c), hi.max(c)));
+    loop {
+        let span = (high - low) as u64 + 1;
+        let width = span.div_ceil(HISTOGRAM_BINS as u64);
+        let mut counts = [0usize; HISTOGRAM_BINS];
+        for &count in image.iter().filter(|&&c| (low..=high).contains(&c)) {
+            counts[((count - low) as u64 / width) as usize] += 1;
+        }
+        let mut below = 0;
+        let bin = counts.iter().position(|&n| {
+            below += n;
+            below > rank
+        }).unwrap_or(HISTOGRAM_BINS - 1);
+        let bin_start = low as u64 + bin as u64 * width;
+        if width == 1 {
+            return bin_start as u32;
+        }
+        rank -= below - counts[bin];
+        low = bin_start as u32;
+        high = (bin_start + width - 1).min(high as u64) as u32;
+    }
+}
+
+/// Saves a log-scaled PNG of `image`. A `path` of `-` writes the PNG to stdout.
+pub fn save_image(image: &Array2<u32>, path: &Path) -> Result<()> {
+    save_image_with_limits(image, path, None)
+}
+
+/// Like [`save_image`], with the log scale spanning `limits` (in counts) instead of the
+/// full range; values outside them saturate
+pub fn save_image_with_limits(image: &Array2<u32>, path: &Path, limits: Option<(f32, f32)>) -> Result<()> {
+    write_gray_image(&to_gray_image(image, limits)?, path)
+}
+
+/// Saves `img` as a PNG, or writes it to stdout for a `path` of `-`
+fn write_gray_image(img: &image::GrayImage, path: &Path) -> Result<()> {
+    if path == Path::new("-") {
+        // Raw bytes: Rust's stdout does no newline translation, so this is binary-safe on Windows too
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&encode_png_image(img)?)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+    img.save(path)?;
+    Ok(())
+}
+
+/// Encodes a log-scaled PNG of `image` in memory
+pub fn encode_png(image: &Array2<u32>) -> Result<Vec<u8>> {
+    encode_png_image(&to_gray_image(image, None)?)
+}
+
+fn encode_png_image(img: &image::GrayImage) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)?;
+    Ok(bytes)
+}
+
+/// Log-scales `image` and normalizes it to an 8-bit grayscale image, either over the full
+/// range or between `limits` given in counts
+fn to_gray_image(image: &Array2<u32>, limits: Option<(f32, f32)>) -> Result<image::GrayImage> {
+    // Convert to f32 for calculations
+    let float_img = image.mapv(|x| x as f32);
+    
+    // Apply log scaling (add 1 to avoid log(0))
+    let log_img = float_img.mapv(|x| (x + 1.0).ln());
+    
+    // Find min and max of log values
+    let (min_val, max_val) = match limits {
+        Some((low, high)) => ((low + 1.0).ln(), (high + 1.0).ln()),
+        None => (
+            *log_img.iter().min_by(|a, b| a.partial_cmp(b).unwrap()).unwrap(),
+            *log_img.iter().max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap(),
+        ),
+    };
+    let range = max_val - min_val;
+    
+    // Normalize to [0,1] then scale to [0,255]
+    let scaled = log_img.mapv(|x| (((x - min_val) / range).clamp(0.0, 1.0) * 255.0) as u8);
+    
+    // Convert to image buffer
+    let height = scaled.shape()[0];
+    let width = scaled.shape()[1];
+    let (v, _offset) = scaled.as_standard_layout().into_owned().into_raw_vec_and_offset();
+
+    let img = image::GrayImage::from_raw(
+        width as u32,
+        height as u32,
+        v
+    ).ok_or_else(|| anyhow!("Failed to create image"))?;
+    
+    Ok(img)
+}
+
+/// Returns true if the decoder's current page holds a full-resolution EER-compressed
+/// frame; reduced overview pages are not frames even when EER-compressed
+pub fn is_frame_page(decoder: &mut Decoder<File>) -> Result<bool> {
+    let compression = decoder.find_tag_unsigned::<u32>(Tag::Compression)?;
+    if !compression.is_some_and(EerCompression::is_eer_code) {
+        return Ok(false);
+    }
+    let subfile_type = decoder.find_tag_unsigned::<u32>(Tag::NewSubfileType)?.unwrap_or(0);
+    Ok(subfile_type & SUBFILE_REDUCED_IMAGE == 0)
+}
+
+/// Returns true if the current page is a reference image (dark or defect map) stored
+/// with ordinary TIFF compression alongside the frames and marked by a non-zero
+/// [`TAG_DARK_REFERENCE`]. Unmarked pages are never taken for one, so the frames of an
+/// integrating-mode file are not.
+fn is_dark_reference_page(decoder: &mut Decoder<File>) -> Result<bool> {
+    if is_frame_page(decoder)? {
+        return Ok(false);
+    }
+    let marker = decoder.find_tag_unsigned::<u32>(Tag::Unknown(TAG_DARK_REFERENCE))?;
+    Ok(marker.is_some_and(|v| v != 0))
+}
+
+/// Loads page `index`, returning false past the last page.
+///
+/// Pages are always addressed by index: after `seek_to_image` goes back, the tiff
+/// decoder's `next_image` can no longer step forward, but seeking by index still can,
+/// so one decoder can be counted, rewound and walked again.
+fn load_page(decoder: &mut Decoder<File>, index: usize) -> Result<bool> {
+    match decoder.seek_to_image(index) {
+        Ok(()) => Ok(true),
+        Err(TiffError::FormatError(TiffFormatError::ImageFileDirectoryNotFound)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Moves the decoder to the first EER frame page at or after page `*page`, updating
+/// `*page`. Returns false if no frame page remains.
+fn seek_frame_page(decoder: &mut Decoder<File>, page: &mut usize) -> Result<bool> {
+    loop {
+        if !load_page(decoder, *page)? {
+            return Ok(false);
+        }
+        if is_frame_page(decoder)? {
+            return Ok(true);
+        }
+        *page += 1;
+    }
+}
+
+/// Moves the decoder to the EER frame page after page `*page`, skipping reference
+/// pages. Returns false if there is no further frame.
+fn next_frame_page(decoder: &mut Decoder<File>, page: &mut usize) -> Result<bool> {
+    *page += 1;
+    seek_frame_page(decoder, page)
+}
+
+/// Returns true if `path` is a TIFF with at least one EER-compressed frame page.
+/// Integrating-mode files store ordinary TIFF frames, so this is false for them.
+pub fn has_eer_frames(path: &Path) -> Result<bool> {
+    let mut decoder = Decoder::new(open_input(path)?)?;
+    seek_frame_page(&mut decoder, &mut 0)
+}
+
+/// Counts the EER frames in the file, ignoring reference and overview pages and
+/// including every frame of pages that pack several (a layout of the `synthetic`
+/// writer, not of detector software). Leaves the decoder
+/// on the first page, ready for decoding.
+pub fn count_frames(decoder: &mut Decoder<File>) -> Result<u32> {
+    let mut frames = 0;
+    let mut page = 0;
+    let mut more = seek_frame_page(decoder, &mut page)?;
+    while more {
+        frames += frames_in_page(decoder)?;
+        more = next_frame_page(decoder, &mut page)?;
+    }
+    load_page(decoder, 0)?;
+    Ok(frames)
+}
+
+/// Reads the current page as an ordinary TIFF image
+fn read_page_f32(decoder: &mut Decoder<File>) -> Result<Array2<f32>> {
+    let (width, height) = decoder.dimensions()?;
+    let data: Vec<f32> = match decoder.read_image()? {
+        DecodingResult::U8(v) => v.into_iter().map(|x| x as f32).collect(),
+        DecodingResult::U16(v) => v.into_iter().map(|x| x as f32).collect(),
+        DecodingResult::U32(v) => v.into_iter().map(|x| x as f32).collect(),
+        DecodingResult::I8(v) => v.into_iter().map(|x| x as f32).collect(),
+        DecodingResult::I16(v) => v.into_iter().map(|x| x as f32).collect(),
+        DecodingResult::I32(v) => v.into_iter().map(|x| x as f32).collect(),
+        DecodingResult::F32(v) => v,
+        DecodingResult::F64(v) => v.into_iter().map(|x| x as f32).collect(),
+        other => return Err(anyhow!("Unsupported sample type: {:?}", other)),
+    };
+    Ok(Array2::from_shape_vec((height as usize, width as usize), data)?)
+}
+
+/// Sums the frames of an integrating-mode file, which are ordinary TIFF images rather
+/// than EER bitstreams. Honours `skip_frames`, `exclude` and `max_frames` of `options`;
+/// the sum saturates at `u32::MAX`. Returns the image and the number of frames summed.
+pub fn decode_integrating_frames(path: &Path, options: &DecodeOptions) -> Result<(Array2<u32>, u32)> {
+    let step = frame_step(options.skip_frames)?;
+    let max_frames = options.max_frames.unwrap_or(u32::MAX);
+    if max_frames == 0 {
+        return Err(anyhow!("max_frames must be at least 1"));
+    }
+    let mut decoder = Decoder::new(open_input(path)?)?;
+    let mut sum: Option<Array2<f32>> = None;
+    let (mut frame_idx, mut processed, mut frames_summed) = (0, 0, 0);
+
+    loop {
+        let subfile_type = decoder.find_tag_unsigned::<u32>(Tag::NewSubfileType)?.unwrap_or(0);
+        if subfile_type & SUBFILE_REDUCED_IMAGE == 0 && !is_dark_reference_page(&mut decoder)? {
+            if options.is_cancelled() {
+                if !options.partial_on_cancel {
+                    return Err(Cancelled.into());
+                }
+                eprintln!("Cancelled after summing {} frames", frames_summed);
+                // Cancelled before the first frame: an empty sum, as for counted frames
+                let (width, height) = decoder.dimensions()?;
+                sum.get_or_insert_with(|| Array2::zeros((height as usize, width as usize)));
+                break;
+            }
+            if frame_idx % step == 0 && processed < max_frames {
+                processed += 1;
+                if !options.exclude.contains(&frame_idx) {
+                    let frame = read_page_f32(&mut decoder)?;
+                    match &mut sum {
+                        Some(sum) if sum.dim() != frame.dim() => {
+                            return Err(anyhow!("Frame {} has a different size from the first", frame_idx));
+                        }
+                        Some(sum) => *sum += &frame,
+                        None => sum = Some(frame),
+                    }
+                    frames_summed += 1;
+                }
+            }
+            frame_idx += 1;
+        }
+        if !decoder.more_images() {
+            break;
+        }
+        decoder.next_image()?;
+    }
+
+    let sum = sum.ok_or_else(|| anyhow!("No frames to sum in {}", path.display()))?;
+    Ok((sum.mapv(|v| v.round().clamp(0.0, u32::MAX as f32) as u32), frames_summed))
+}
+
+/// Reads the dark/defect reference image embedded as an extra TIFF page marked with
+/// [`TAG_DARK_REFERENCE`], if any
+pub fn dark_reference(path: &Path) -> Result<Option<Array2<f32>>> {
+    let file = File::open(path)?;
+    let mut decoder = Decoder::new(file)?;
+
+    loop {
+        if is_dark_reference_page(&mut decoder)? {
+            return Ok(Some(read_page_f32(&mut decoder)?));
+        }
+        if !decoder.more_images() {
+            return Ok(None);
+        }
+        decoder.next_image()?;
+    }
+}
+
+/// Subtracts a per-frame dark reference, scaled by the number of summed frames, from a sum image.
+///
+/// The dark reference is on the sensor grid; when the sum is upsampled each dark
+/// pixel is spread evenly over the super-resolution pixels it covers.
+pub fn subtract_dark_reference(
+    sum: &Array2<u32>,
+    dark: &Array2<f32>,
+    frames_summed: u32,
+) -> Result<Array2<u32>> {
+    let (height, width) = sum.dim();
+    let (dark_height, dark_width) = dark.dim();
+    if dark_height == 0 || height % dark_height != 0 || width % dark_width != 0
+        || height / dark_height != width / dark_width
+    {
+        return Err(anyhow!(
+            "Dark reference shape {}x{} does not match image shape {}x{}",
+            dark_width, dark_height, width, height
+        ));
+    }
+    let up = height / dark_height;
+    let scale = frames_summed as f32 / (up * up) as f32;
+
+    Ok(Array2::from_shape_fn((height, width), |(row, col)| {
+        let corrected = sum[[row, col]] as f32 - dark[[row / up, col / up]] * scale;
+        corrected.round().clamp(0.0, u32::MAX as f32) as u32
+    }))
+}
+
+/// Location of one compressed strip within the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StripInfo {
+    pub offset: u64,
+    pub size: u64,
+}
+
+fn get_strips_info(decoder: &mut Decoder<File>) -> Result<Vec<StripInfo>> {
+    let offsets = decoder.get_tag_u64_vec(Tag::StripOffsets)?;
+    let sizes = decoder.get_tag_u64_vec(Tag::StripByteCounts)?;
+    pair_strips(offsets, sizes)
+}
+
+/// Pairs strip offsets with their byte counts; a length mismatch means a corrupt file,
+/// and zipping would silently drop the trailing strips
+fn pair_strips(offsets: Vec<u64>, sizes: Vec<u64>) -> Result<Vec<StripInfo>> {
+    if offsets.len() != sizes.len() {
+        return Err(anyhow!(
+            "StripOffsets has {} entries but StripByteCounts has {}",
+            offsets.len(),
+            sizes.len()
+        ));
+    }
+    Ok(offsets.into_iter()
+        .zip(sizes)
+        .map(|(offset, size)| StripInfo { offset, size })
+        .collect())
+}
+
+/// Reads the raw compressed strips of EER frame `frame` (0-based), returning the strip
+/// locations and their bytes concatenated in order
+pub fn read_frame_strips(path: &Path, frame: u32) -> Result<(Vec<StripInfo>, Vec<u8>)> {
+    let mut file = open_input(path)?;
+    let mut decoder = Decoder::new(File::open(path)?)?;
+
+    let mut page = 0;
+    let mut found = seek_frame_page(&mut decoder, &mut page)?;
+    for _ in 0..frame {
+        if !found {
+            break;
+        }
+        found = next_frame_page(&mut decoder, &mut page)?;
+    }
+    if !found {
+        return Err(anyhow!("Frame {} not found in {}", frame, path.display()));
+    }
+
+    let strips = get_strips_info(&mut decoder)?;
+    let mut data = Vec::with_capacity(strips.iter().map(|s| s.size as usize).sum());
+    for strip in &strips {
+        file.seek(SeekFrom::Start(strip.offset))?;
+        (&mut file).take(strip.size).read_to_end(&mut data)?;
+    }
+    if data.len() as u64 != strips.iter().map(|s| s.size).sum::<u64>() {
+        return Err(anyhow!("Strip data of frame {} runs past the end of the file", frame));
+    }
+    Ok((strips, data))
+}
+
+/// Number of frames in the current page.
+///
+/// Packed pages are not part of the EER format as written by the detector software: this
+/// reads the private `EerFramesPerPage` tag that the `synthetic` writer uses for several
+/// frames in one IFD. Each strip then holds the frames' bitstreams back to back, every
+/// frame ending on a byte boundary. Pages without the tag hold one frame.
+pub(crate) fn frames_in_page(decoder: &mut Decoder<File>) -> Result<u32> {
+    let frames = decoder
+        .find_tag(Tag::Unknown(TAG_FRAMES_PER_PAGE))?
+        .map(|value| value.into_u32())
+        .transpose()?
+        .unwrap_or(1);
+    if frames == 0 {
+        return Err(anyhow!("EerFramesPerPage tag is zero"));
+    }
+    Ok(frames)
+}
+
+/// Returns log2 of the upsampling factor, checking the file has enough sub-pixel bits for it
+fn upsampling_shift(params: &CompressionParams, upsampling: u32) -> Result<u32> {
+    if !upsampling.is_power_of_two() {
+        return Err(anyhow!("Upsampling factor must be a power of two, got {}", upsampling));
+    }
+    let shift = upsampling.trailing_zeros();
+    if shift > params.horz_sub_bits || shift > params.vert_sub_bits {
+        return Err(anyhow!(
+            "Upsampling factor {} exceeds the native super-resolution of this file ({})",
+            upsampling,
+            recommended_upsampling(params)
+        ));
+    }
+    Ok(shift)
+}
+
+/// Walks the strips of the first `frames` frames of the decoder's current page (one
+/// unless the page is packed, see [`frames_in_page`]), calling `visit` with the frame's
+/// position in the page and the sensor row, column and raw vertical/horizontal sub-pixel
+/// bits of every electron event. The frames of a packed page follow one another within
+/// each strip, so they are all decoded in a single pass over it.
+fn visit_frame_events(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),
+    frames: u32,
+    mut visit: impl FnMut(u32, usize, usize, u32, u32),
+) -> Result<()> {
+    params.validate()?;
+    let height = decoder.dimensions()?.1 as usize;
+    let width = decoder.dimensions()?.0 as usize;
+    
+    let page_frames = frames_in_page(decoder)?;
+    if frames > page_frames {
+        return Err(anyhow!("{} frames requested from a page holding {}", frames, page_frames));
+    }
+    let strips_info = get_strips_info(decoder)?;
+    let rows_per_strip = decoder.get_tag_u32(Tag::RowsPerStrip)? as usize;
+    
+    // `file` is a second handle, so nothing ties it to the decoder's file; a strip past
+    // its end means it was opened on something else
+    let file_len = file.seek(SeekFrom::End(0))?;
+    if let Some(strip) = strips_info.iter().find(|s| s.offset + s.size > file_len) {
+        return Err(anyhow!(
+            "Strip at offset {} ({} bytes) lies past the end of the {}-byte file; \
+             the file handle must be opened on the decoder's file",
+            strip.offset, strip.size, file_len
+        ));
+    }
+
+    // Pre-allocate buffer for largest strip
+    let max_strip_size = strips_info.iter().map(|s| s.size as usize).max().unwrap_or(0);
+    let mut raw_data = vec![0u8; max_strip_size];
+    
+    for (strip_idx, strip_info) in strips_info.iter().enumerate() {
+        // Read strip data
+        file.seek(SeekFrom::Start(strip_info.offset))?;
+        file.read_exact(&mut raw_data[..strip_info.size as usize])?;
+        
+        let mut bs = BitStream::new(&raw_data[..strip_info.size as usize]);
+        
+        let start_row = strip_idx * rows_per_strip;
+        let end_row = (start_row + rows_per_strip).min(height);
+        let strip_pixel_start = start_row * width;
+        let strip_pixels = end_row.saturating_sub(start_row) * width;
+
+        for sub_frame in 0..frames {
+            walk_strip(&mut bs, params, strip_pixels, |pixel, v_sub, h_sub| {
+                let global_pixel = strip_pixel_start + pixel;
+                visit(sub_frame, global_pixel / width, global_pixel % width, v_sub, h_sub);
+            });
+            // Packed frames each start on a byte boundary
+            bs.align_to_byte();
+        }
+    }
+    
+    Ok(())
+}
+
+/// Decodes one frame's run-length codes over a strip of `strip_pixels` pixels, calling
+/// `visit` with the strip-relative pixel and raw vertical/horizontal sub-pixel bits of
+/// every event
+fn walk_strip(
+    bs: &mut BitStream,
+    params: &CompressionParams,
+    strip_pixels: usize,
+    mut visit: impl FnMut(usize, u32, u32),
+) {
+    let pos_skip_max = params.pos_skip_max();
+    let mut pos = 0;
+    while pos < strip_pixels {
+        let skip = bs.get_bits(params.code_len);
+        pos += skip as usize;
+        
+        if pos >= strip_pixels {
+            break;
+        }
+        
+        if skip < pos_skip_max {
+            let v_sub = bs.get_bits(params.vert_sub_bits);
+            let h_sub = bs.get_bits(params.horz_sub_bits);
+            visit(pos, v_sub, h_sub);
+            pos += 1;
+        }
+        // skip == max => no event here, continue
+    }
+}
+
+/// Largest `bin_factor` of [`decode_eer_frame`]: a frame has at most one event per
+/// pixel, so the counts of a 255x255 bin still fit in u16
+pub const MAX_FRAME_BIN_FACTOR: u32 = 255;
+
+/// Decodes the decoder's current frame into counts.
+///
+/// The `tiff` decoder cannot hand out its reader, so strips are read through `file`, a
+/// separate handle on the same file. The two never share a cursor: strip offsets come
+/// from the decoder's current page and every read on `file` seeks to an absolute offset,
+/// so `file`'s position beforehand doesn't matter. Callers only need to keep the decoder
+/// on the page they want decoded.
+///
+/// `upsampling` places events on a finer grid using the sub-pixel bits; `bin_factor`
+/// instead sums `bin_factor`x`bin_factor` sensor pixels into one output pixel while
+/// decoding, for cheap previews. Edge bins of sensors not divisible by `bin_factor` cover
+/// fewer pixels. At most one of the two may exceed 1, and `bin_factor` at most
+/// [`MAX_FRAME_BIN_FACTOR`].
+///
+/// Pages holding several frames are rejected; [`frames::FrameIterator`] and
+/// [`decode_frames`] step through their frames.
+pub fn decode_eer_frame(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),  // Take file handle as parameter
+    upsampling: u32,
+    bin_factor: u32,
+) -> Result<Array2<u16>> {
+    single_frame_page(decoder)?;
+    Ok(decode_frames_at(decoder, params, file, 1, upsampling, bin_factor)?.remove(0))
+}
+
+/// Errors if the current page is packed, for the functions that decode one frame per page
+fn single_frame_page(decoder: &mut Decoder<File>) -> Result<()> {
+    match frames_in_page(decoder)? {
+        1 => Ok(()),
+        frames => Err(anyhow!("Page holds {} packed frames; iterate over them with FrameIterator", frames)),
+    }
+}
+
+/// Decodes every frame of the decoder's current page: one for ordinary pages, several
+/// for packed ones (see [`frames_in_page`])
+pub(crate) fn decode_page_frames(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),
+    upsampling: u32,
+) -> Result<Vec<Array2<u16>>> {
+    let frames = frames_in_page(decoder)?;
+    decode_frames_at(decoder, params, file, frames, upsampling, 1)
+}
+
+/// [`decode_eer_frame`] for the first `frames` frames of the current page, in one pass
+fn decode_frames_at(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),
+    frames: u32,
+    upsampling: u32,
+    bin_factor: u32,
+) -> Result<Vec<Array2<u16>>> {
+    let height = decoder.dimensions()?.1 as usize;
+    let width = decoder.dimensions()?.0 as usize;
+    if bin_factor == 0 {
+        return Err(anyhow!("Bin factor must be at least 1"));
+    }
+    if bin_factor > MAX_FRAME_BIN_FACTOR {
+        return Err(anyhow!("Frame bin factor {} exceeds {}", bin_factor, MAX_FRAME_BIN_FACTOR));
+    }
+    if bin_factor > 1 {
+        if upsampling > 1 {
+            return Err(anyhow!("Cannot both upsample and bin a frame"));
+        }
+        let bin = bin_factor as usize;
+        let out_width = width.div_ceil(bin);
+        let mut images = vec![Array2::<u16>::zeros((height.div_ceil(bin), out_width)); frames as usize];
+        let mut slices: Vec<_> = images.iter_mut().map(|image| image.as_slice_mut().unwrap()).collect();
+        visit_frame_events(decoder, params, file, frames, |sub_frame, row, col, _, _| {
+            slices[sub_frame as usize][(row / bin) * out_width + col / bin] += 1;
+        })?;
+        return Ok(images);
+    }
+
+    let shift = upsampling_shift(params, upsampling)?;
+    let up = upsampling as usize;
+    let out_width = width * up;
+    let mut images = vec![Array2::<u16>::zeros((height * up, out_width)); frames as usize];
+    // Direct slice access is faster than using the index operator
+    let mut slices: Vec<_> = images.iter_mut().map(|image| image.as_slice_mut().unwrap()).collect();
+    
+    visit_frame_events(decoder, params, file, frames, |sub_frame, row, col, v_sub, h_sub| {
+        // Keep only the most significant sub-pixel bits needed for this upsampling
+        let row = row * up + (v_sub >> (params.vert_sub_bits - shift)) as usize;
+        let col = col * up + (h_sub >> (params.horz_sub_bits - shift)) as usize;
+        slices[sub_frame as usize][row * out_width + col] += 1;
+    })?;
+    
+    Ok(images)
+}
+
+/// A single decoded electron hit.
+///
+/// Sub-pixel offsets are fixed-point fractions of a sensor pixel in 1/256 units, so
+/// events from files with different sub-pixel bit counts are directly comparable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElectronEvent {
+    /// Sensor column
+    pub x: u32,
+    /// Sensor row
+    pub y: u32,
+    /// Horizontal sub-pixel offset, in 1/256 pixel
+    pub sub_x: u8,
+    /// Vertical sub-pixel offset, in 1/256 pixel
+    pub sub_y: u8,
+}
+
+/// Decodes the decoder's current frame into a list of electron events, in stream order.
+/// Like [`decode_eer_frame`], pages holding several frames are rejected.
+pub fn decode_frame_events(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),
+) -> Result<Vec<ElectronEvent>> {
+    if params.horz_sub_bits > 8 || params.vert_sub_bits > 8 {
+        return Err(anyhow!("Sub-pixel bit counts above 8 are not supported"));
+    }
+    single_frame_page(decoder)?;
+    let mut events = Vec::new();
+    visit_frame_events(decoder, params, file, 1, |_, row, col, v_sub, h_sub| {
+        events.push(ElectronEvent {
+            x: col as u32,
+            y: row as u32,
+            sub_x: (h_sub << (8 - params.horz_sub_bits)) as u8,
+            sub_y: (v_sub << (8 - params.vert_sub_bits)) as u8,
+        });
+    })?;
+    Ok(events)
+}
+
+/// [`decode_frame_events`] as (column, row, horizontal sub-pixel, vertical sub-pixel)
+/// tuples with the sub-pixel offsets as stored, in `horz_sub_bits`/`vert_sub_bits` bits;
+/// the layout of [`synthetic::SyntheticEvent`]
+pub fn decode_eer_frame_events(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),
+) -> Result<Vec<(u32, u32, u8, u8)>> {
+    let stored = |offset: u8, bits: u32| (u32::from(offset) >> (8 - bits)) as u8;
+    Ok(decode_frame_events(decoder, params, file)?
+        .into_iter()
+        .map(|event| {
+            let sub_x = stored(event.sub_x, params.horz_sub_bits);
+            (event.x, event.y, sub_x, stored(event.sub_y, params.vert_sub_bits))
+        })
+        .collect())
+}
+
+/// Rasterizes electron events onto a `(height*upsampling, width*upsampling)` grid.
+///
+/// For any frame, `events_to_image(&decode_frame_events(..)?, w, h, up)` equals
+/// `decode_eer_frame(.., up, 1)`, which makes the two decode paths checkable against
+/// each other. `width`/`height` are the sensor dimensions.
+pub fn events_to_image(
+    events: &[ElectronEvent],
+    width: u32,
+    height: u32,
+    upsampling: u32,
+) -> Result<Array2<u16>> {
+    if !upsampling.is_power_of_two() || upsampling > 256 {
+        return Err(anyhow!("Upsampling factor must be a power of two up to 256, got {}", upsampling));
+    }
+    let shift = upsampling.trailing_zeros();
+    let up = upsampling as usize;
+    let mut image = Array2::<u16>::zeros((height as usize * up, width as usize * up));
+
+    for event in events {
+        if event.x >= width || event.y >= height {
+            return Err(anyhow!("Event at ({}, {}) lies outside a {}x{} sensor", event.x, event.y, width, height));
+        }
+        let row = event.y as usize * up + (event.sub_y as u32 >> (8 - shift)) as usize;
+        let col = event.x as usize * up + (event.sub_x as u32 >> (8 - shift)) as usize;
+        image[[row, col]] += 1;
+    }
+    Ok(image)
+}
+
+/// Outcome of scanning one frame's bitstream with a candidate code length
+#[derive(Debug)]
+struct CodeLenScan {
+    events: u64,
+    /// Strips whose decoded codes and events take exactly the strip's bytes
+    matching_strips: usize,
+    overrun: bool,
+}
+
+/// Walks the current frame's strips with `params` without building an image,
+/// counting events and checking the stream ends inside each strip. A strip matches
+/// when the codes and events decoded from it, each frame padded to a whole byte, add
+/// up to its stored size.
+fn scan_frame(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),
+) -> Result<CodeLenScan> {
+    let width = decoder.dimensions()?.0 as usize;
+    let height = decoder.dimensions()?.1 as usize;
+    let rows_per_strip = decoder.get_tag_u32(Tag::RowsPerStrip)? as usize;
+    let page_frames = frames_in_page(decoder)?;
+    params.validate()?;
+    let pos_skip_max = params.pos_skip_max();
+    let sub_bits = (params.horz_sub_bits + params.vert_sub_bits) as usize;
+    let mut scan = CodeLenScan { events: 0, matching_strips: 0, overrun: false };
+
+    for (strip_idx, strip_info) in get_strips_info(decoder)?.iter().enumerate() {
+        let mut raw_data = vec![0u8; strip_info.size as usize];
+        file.seek(SeekFrom::Start(strip_info.offset))?;
+        file.read_exact(&mut raw_data)?;
+        let mut bs = BitStream::new(&raw_data);
+
+        let start_row = strip_idx * rows_per_strip;
+        let strip_pixels = (start_row + rows_per_strip).min(height).saturating_sub(start_row) * width;
+        let mut decoded_bytes = 0;
+        for frame in 0..page_frames {
+            if frame > 0 {
+                bs.align_to_byte();
+            }
+            let (mut pos, mut codes, mut events) = (0, 0, 0);
+            while pos < strip_pixels {
+                if bs.bits_left() < params.code_len as usize {
+                    scan.overrun = true;
+                    return Ok(scan);
+                }
+                let skip = bs.get_bits(params.code_len);
+                codes += 1;
+                pos += skip as usize;
+                if pos >= strip_pixels {
+                    break;
+                }
+                if skip < pos_skip_max {
+                    if bs.bits_left() < sub_bits {
+                        scan.overrun = true;
+                        return Ok(scan);
+                    }
+                    bs.get_bits(params.vert_sub_bits);
+                    bs.get_bits(params.horz_sub_bits);
+                    events += 1;
+                    pos += 1;
+                }
+            }
+            scan.events += events as u64;
+            decoded_bytes += (codes * params.code_len as usize + events * sub_bits).div_ceil(8);
+        }
+        if decoded_bytes == raw_data.len() {
+            scan.matching_strips += 1;
+        }
+    }
+
+    Ok(scan)
+}
+
+/// Guesses whether the current frame is encoded with 7- or 8-bit run-length codes.
+///
+/// Each candidate is scanned over the frame; a candidate is plausible when its
+/// stream ends inside every strip and yields at most one event per pixel. Plausible
+/// candidates score by the number of strips whose decoded event and code counts
+/// account for exactly the strip's bytes, since a wrong code length stops at the end
+/// of the strip's pixels with data left over or runs out before reaching it. The
+/// highest score wins, with `params.code_len` kept on a tie or when neither candidate
+/// is plausible. Warns on stderr when the detected value disagrees with the
+/// compression tag.
+pub fn detect_code_len(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),
+) -> Result<u32> {
+    let (width, height) = decoder.dimensions()?;
+    let pixels = width as u64 * height as u64 * frames_in_page(decoder)? as u64;
+
+    let mut best: Option<(u32, usize)> = None;
+    // The tag's own code length goes first, so a tie keeps it
+    let candidates = if params.code_len == 8 { [8, 7] } else { [7, 8] };
+    for code_len in candidates {
+        let candidate = CompressionParams { code_len, ..params.clone() };
+        let scan = scan_frame(decoder, &candidate, file)?;
+        if scan.overrun || scan.events > pixels {
+            continue;
+        }
+        if best.is_none_or(|(_, matching)| scan.matching_strips > matching) {
+            best = Some((code_len, scan.matching_strips));
+        }
+    }
+
+    let code_len = best.map_or(params.code_len, |(code_len, _)| code_len);
+    if code_len != params.code_len {
+        eprintln!(
+            "Warning: compression tag implies {}-bit codes but the data decodes as {}-bit",
+            params.code_len, code_len
+        );
+    }
+    Ok(code_len)
+}
+
+/// Callback for each decoded frame, called with (frame index, frame)
+pub type OnFrame<'a> = &'a mut dyn FnMut(u32, &Array2<u16>);
+
+/// What [`decode_frames`] does besides summing the selected frames. The default sums
+/// every one of them.
+#[derive(Default)]
+pub struct SumOptions<'a> {
+    /// Shown each decoded frame and its index before it is added to the sum, e.g. to show
+    /// frames in a GUI as they stream in without a second decode
+    pub on_frame: Option<OnFrame<'a>>,
+    /// Frame indices to leave out (e.g. bad frames found in QC). The decoder still steps
+    /// past them, and the returned frame count only includes frames actually accumulated.
+    pub exclude: Option<&'a HashSet<u32>>,
+    /// Checked before each frame; once set, the decode fails with [`Cancelled`], so
+    /// another thread can stop a long decode within a frame
+    pub cancel: Option<&'a AtomicBool>,
+    /// Process at most this many of the selected frames, for a quick preview. Excluded
+    /// frames count towards it; a cap above the frames available is clamped with a
+    /// warning.
+    pub max_frames: Option<u32>,
+}
+
+impl SumOptions<'_> {
+    fn is_cancelled(&self) -> bool {
+        self.cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    fn is_excluded(&self, frame_idx: u32) -> bool {
+        self.exclude.is_some_and(|exclude| exclude.contains(&frame_idx))
+    }
+}
+
+/// Decodes and sums every `skip_frames`-th frame, as `options` select.
+/// Returns the summed image and the number of frames actually accumulated.
+pub fn decode_frames(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),
+    num_frames: u32,
+    skip_frames: Option<u32>,
+    upsampling: u32,
+    mut options: SumOptions,
+) -> Result<(Array2<u32>, u32)> {
+    let (sum, frames_summed, cancelled) =
+        accumulate_frames(decoder, params, file, num_frames, skip_frames, upsampling, &mut options)?;
+    if cancelled {
+        return Err(Cancelled.into());
+    }
+    Ok((sum, frames_summed))
+}
+
+/// Decodes the frames with indices in `range` and returns each one separately, e.g. for
+/// motion correction or per-frame dose weighting. Params are refreshed per page as in
+/// [`decode_frames`].
+///
+/// The whole stack is held in memory: 2 bytes per pixel per frame, so 1000 frames of a
+/// 4096x4096 sensor take 32 GiB, and 16 times that at 4x upsampling. Decode in chunks
+/// of frames, or use [`frames::FrameIterator`], when that is too much.
+pub fn decode_frame_stack(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),
+    range: Range<u32>,
+    upsampling: u32,
+) -> Result<Vec<Array2<u16>>> {
+    let total_frames = count_frames(decoder)?;
+    if range.start > range.end || range.end > total_frames {
+        return Err(anyhow!("Frame range {:?} is outside the {} frames of the file", range, total_frames));
+    }
+    let mut stack = Vec::with_capacity(range.len());
+    walk_frames(decoder, params, file, range.end, None, |frame_idx, _, frame| {
+        if frame_idx >= range.start {
+            stack.push(frame.decode(upsampling)?);
+        }
+        Ok(())
+    })?;
+    Ok(stack)
+}
+
+/// Sums the frames [`walk_frames`] reaches as `options` select. Returns the sum, the
+/// frames summed and whether the walk stopped early on cancellation, keeping the sum
+/// so far.
+fn accumulate_frames(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),
+    num_frames: u32,
+    skip_frames: Option<u32>,
+    upsampling: u32,
+    options: &mut SumOptions,
+) -> Result<(Array2<u32>, u32, bool)> {
+    let num_frames = capped_frame_count(num_frames, skip_frames, options.max_frames)?;
+    let (height, width) = first_frame_dims(decoder)?;
+    let up = upsampling as usize;
+    let mut sum_image = Array2::<u32>::zeros((height * up, width * up));
+    let mut frames_summed = 0;
+
+    let walked = walk_frames(decoder, params, file, num_frames, skip_frames, |frame_idx, _, frame| {
+        if options.is_cancelled() {
+            // Unwinds the walk; told apart from real errors below
+            return Err(Cancelled.into());
+        }
+        if options.is_excluded(frame_idx) {
+            return Ok(());
+        }
+        let frame_image = frame.decode(upsampling)?;
+        if let Some(on_frame) = options.on_frame.as_mut() {
+            on_frame(frame_idx, &frame_image);
+        }
+        sum_image.zip_mut_with(&frame_image, |acc, &count| *acc += u32::from(count));
+        frames_summed += 1;
+        Ok(())
+    });
+    let stopped = match walked {
+        Ok(()) => false,
+        Err(e) if e.is::<Cancelled>() => true,
+        Err(e) => return Err(e),
+    };
+    Ok((sum_image, frames_summed, stopped))
+}
+
+/// Decodes every `skip_frames`-th frame and accumulates it multiplied by its weight,
+/// for simple linear dose weighting. `weights` holds one weight per processed frame,
+/// i.e. `num_frames.div_ceil(skip_frames)` of them.
+pub fn decode_frames_weighted(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),
+    num_frames: u32,
+    skip_frames: Option<u32>,
+    upsampling: u32,
+    weights: &[f32],
+) -> Result<Array2<f32>> {
+    let frames_to_process = num_frames.div_ceil(frame_step(skip_frames)?) as usize;
+    if weights.len() != frames_to_process {
+        return Err(anyhow!(
+            "Got {} weights for {} frames to process",
+            weights.len(),
+            frames_to_process
+        ));
+    }
+
+    let (height, width) = first_frame_dims(decoder)?;
+    let up = upsampling as usize;
+    let mut sum_image = Array2::<f32>::zeros((height * up, width * up));
+
+    walk_frames(decoder, params, file, num_frames, skip_frames, |_, ordinal, frame| {
+        let frame_image = frame.decode(upsampling)?;
+        let weight = weights[ordinal as usize];
+        sum_image.zip_mut_with(&frame_image, |acc, &count| *acc += weight * count as f32);
+        Ok(())
+    })?;
+
+    Ok(sum_image)
+}
+
+/// Per-pixel mean and unbiased variance of the counts across every `skip_frames`-th
+/// frame, computed in one pass with Welford's algorithm. A Poisson detector has a
+/// variance/mean ratio near 1. The variance is zero when only one frame is processed.
+pub fn decode_frames_stats(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut (impl Read + Seek),
+    num_frames: u32,
+    skip_frames: Option<u32>,
+    upsampling: u32,
+) -> Result<(Array2<f32>, Array2<f32>)> {
+    let (height, width) = first_frame_dims(decoder)?;
+    let up = upsampling as usize;
+    let mut mean = Array2::<f32>::zeros((height * up, width * up));
+    // Sum of squared deviations from the running mean
+    let mut m2 = Array2::<f32>::zeros(mean.raw_dim());
+    let mut n = 0u32;
+
+    walk_frames(decoder, params, file, num_frames, skip_frames, |_, _, frame| {
+        let frame = frame.decode(upsampling)?;
+        n += 1;
+        let inv_n = 1.0 / n as f32;
+        ndarray::Zip::from(&mut mean).and(&mut m2).and(&frame).for_each(|mean, m2, &count| {
+            let x = count as f32;
+            let delta = x - *mean;
+            *mean += delta * inv_n;
+            *m2 += delta * (x - *mean);
+        });
+        Ok(())
+    })?;
+
+    if n > 1 {
+        m2 /= (n - 1) as f32;
+    } else {
+        m2.fill(0.0);
+    }
+    Ok((mean, m2))
+}
+
+/// Moves the decoder onto the first frame page, wherever it was before, and returns
+/// the page index and (height, width)
+fn first_frame_page(decoder: &mut Decoder<File>) -> Result<(usize, (usize, usize))> {
+    // Step over any leading overview or reference pages
+    let mut page = 0;
+    if !seek_frame_page(decoder, &mut page)? {
+        return Err(anyhow!("No EER frames found"));
+    }
+    let (width, height) = decoder.dimensions()?;
+    Ok((page, (height as usize, width as usize)))
+}
+
+/// Moves the decoder onto the first frame page and returns its (height, width)
+fn first_frame_dims(decoder: &mut Decoder<File>) -> Result<(usize, usize)> {
+    Ok(first_frame_page(decoder)?.1)
+}
+
+/// A frame reached by [`walk_frames`]: the decoder on its page, the compression params
+/// for that page and the frame's position within a packed page
+struct FrameRef<'a, R> {
+    decoder: &'a mut Decoder<File>,
+    params: &'a CompressionParams,
+    file: &'a mut R,
+    sub_frame: u32,
+    /// The frames of the current page not yet handed out, decoded together at the
+    /// upsampling of the first request
+    page_cache: &'a mut Option<(u32, Vec<Option<Array2<u16>>>)>,
+}
+
+impl<R: Read + Seek> FrameRef<'_, R> {
+    fn decode(&mut self, upsampling: u32) -> Result<Array2<u16>> {
+        if !matches!(self.page_cache, Some((cached, _)) if *cached == upsampling) {
+            let frames = decode_page_frames(self.decoder, self.params, self.file, upsampling)?;
+            *self.page_cache = Some((upsampling, frames.into_iter().map(Some).collect()));
+        }
+        let (_, frames) = self.page_cache.as_mut().unwrap();
+        match frames[self.sub_frame as usize].take() {
+            Some(frame) => Ok(frame),
+            // Asked for twice; decode the page again rather than keep copies around
+            None => Ok(decode_page_frames(self.decoder, self.params, self.file, upsampling)?
+                .swap_remove(self.sub_frame as usize)),
+        }
+    }
+}
+
+/// The stride between processed frames: every frame when `skip_frames` is `None`
+fn frame_step(skip_frames: Option<u32>) -> Result<u32> {
+    match skip_frames {
+        Some(0) => Err(anyhow!("Frame skip must be at least 1")),
+        step => Ok(step.unwrap_or(1)),
+    }
+}
+
+/// Calls `visit` with the frame index and ordinal of every `skip_frames`-th frame,
+/// stepping through the frames of packed pages as well as across pages. Every page is
+/// decoded with `params`, resolved once from the first frame page by the caller.
+fn walk_frames<R: Read + Seek>(
+    decoder: &mut Decoder<File>,
+    params: &CompressionParams,
+    file: &mut R,
+    num_frames: u32,
+    skip_frames: Option<u32>,
+    mut visit: impl FnMut(u32, u32, &mut FrameRef<R>) -> Result<()>,
+) -> Result<()> {
+    let (mut page, _) = first_frame_page(decoder)?;
+    let mut page_frames = frames_in_page(decoder)?;
+    let mut sub_frame = 0;
+
+    // Decode frames with skipping
+    let step = frame_step(skip_frames)?;
+    let mut page_cache = None;
+    for (ordinal, frame_idx) in (0..num_frames).step_by(step as usize).enumerate() {
+        let mut frame = FrameRef {
+            decoder: &mut *decoder,
+            params,
+            file: &mut *file,
+            sub_frame,
+            page_cache: &mut page_cache,
+        };
+        visit(frame_idx, ordinal as u32, &mut frame)?;
+
+        // Skip frames, stepping over any reference pages in between
+        for _ in 0..step.min(num_frames - frame_idx - 1) {
+            sub_frame += 1;
+            if sub_frame < page_frames {
+                continue;
+            }
+            sub_frame = 0;
+            page_cache = None;
+            if next_frame_page(decoder, &mut page)? {
+                page_frames = frames_in_page(decoder)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum VoxelType {
+    UnsignedInt16,
+}
+
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ImageData {
+    pub size_x: i32,
+    pub size_y: i32,
+    pub size_z: i32,
+    pub size_t: i32,
+    pub size_c: i32,
+    pub voxel_type: VoxelType,
+    pub voxel_spacing_x: f32,
+    pub voxel_spacing_y: f32,
+    pub voxel_spacing_z: f32,
 }
 
-/// BitStream provides bit-level reading capabilities from a byte buffer
-pub struct BitStream<'a> {
-    buffer: &'a [u8],
-    bit_pos: usize,  // index of next bit to read (from the start of buffer)
+/// Summary of an EER file's header, see [`header_report`]
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct HeaderReport {
+    pub image_data: ImageData,
+    /// Bit layout of the first frame page, if it has a recognised compression
+    pub compression: Option<CompressionParams>,
+    /// See [`EerMetadata::detector_model`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detector: Option<String>,
+    /// See [`EerMetadata::voltage_kv`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voltage_kv: Option<f64>,
+    /// See [`EerMetadata::nominal_magnification`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nominal_magnification: Option<f64>,
+    /// XML metadata sorted by key; empty (and not serialized) when the file has none
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// TIFF pages, including any overview and reference pages
+    pub page_count: u32,
+    pub frame_count: u32,
+    /// Ways the frames disagree with the metadata's detector, see [`DetectorPreset::check`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// Settings an image of the file was actually decoded with, as returned by
+    /// [`sum_frames_with_params`]; unset by [`header_report`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decode_params: Option<DecodeParams>,
 }
 
-impl<'a> BitStream<'a> {
-    /// Creates a new BitStream from a byte slice
-    pub fn new(data_bytes: &'a [u8]) -> Self {
-        BitStream {
-            buffer: data_bytes,
-            bit_pos: 0,
+/// The settings a sum was made with, as the decode resolved them against the file, so a
+/// thumbnail or sum can be reproduced from the JSON that describes it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DecodeParams {
+    /// Frames that went into the sum: every `skip_frames`-th up to `max_frames`, less the
+    /// excluded ones, and fewer if the decode was cancelled part way
+    pub frames_summed: u32,
+    pub skip_frames: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_frames: Option<u32>,
+    /// Excluded frame indices, sorted
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub excluded: Vec<u32>,
+    /// Resolved upsampling factor
+    pub upsampling: u32,
+    pub bin_factor: u32,
+    /// Crop on the decoded grid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crop: Option<Crop>,
+    pub subtract_dark: bool,
+    /// See [`GainReference::source`]; "" for a gain map with no recorded source
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gain_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub defect_threshold: Option<f32>,
+    /// Thumbnail contrast: "auto" or "minmax"
+    pub contrast: String,
+}
+
+impl DecodeParams {
+    /// Records a sum made with `options` of `frames_summed` frames, stepping by
+    /// `skip_frames` at `upsampling`, with `crop` the crop actually applied
+    fn new(options: &DecodeOptions, frames_summed: u32, skip_frames: u32, upsampling: u32, crop: Option<Crop>) -> Self {
+        let mut excluded: Vec<u32> = options.exclude.iter().copied().collect();
+        excluded.sort_unstable();
+        DecodeParams {
+            frames_summed,
+            skip_frames,
+            max_frames: options.max_frames,
+            excluded,
+            upsampling,
+            bin_factor: options.bin_factor,
+            crop,
+            subtract_dark: options.subtract_dark,
+            gain_file: options.gain.as_ref().map(|gain| gain.source().unwrap_or_default().to_string()),
+            defect_threshold: options.gain.as_ref().map(GainReference::defect_threshold),
+            contrast: if options.auto_contrast { "auto" } else { "minmax" }.to_string(),
         }
     }
+}
 
-    /// Reads n bits (LSB->MSB within each byte) and returns integer value
-    #[inline(always)]
-    pub fn get_bits(&mut self, n: u32) -> u32 {
-        debug_assert!(n <= 32);
-        
-        let byte_index = self.bit_pos / 8;
-        let bit_offset = self.bit_pos % 8;
-        
-        // Read 4 bytes (or less if at end of buffer)
-        let mut chunk: u32 = 0;
-        for i in 0..4.min(self.buffer.len() - byte_index) {
-            chunk |= (self.buffer[byte_index + i] as u32) << (i * 8);
+impl HeaderReport {
+    /// Rescales the image data from the sensor grid to the grid of images decoded with
+    /// `options`: upsampling multiplies the size and divides the voxel spacing, cropping
+    /// sets the size, and binning (`bin_factor`) divides the size and multiplies the
+    /// spacing, with partial edge bins counted as whole pixels.
+    /// `Upsampling::Auto` resolves against the first frame's compression, or to 1 when
+    /// there is none.
+    pub fn for_output(mut self, options: &DecodeOptions) -> Self {
+        let metadata = EerMetadata { items: self.metadata.clone().into_iter().collect() };
+        let preset = metadata.detector_preset();
+        let upsampling = self.compression.as_ref().map_or(1, |params| options.upsampling.resolve_for(params, preset));
+        let bin = options.bin_factor.max(1);
+        let data = &mut self.image_data;
+        let (mut width, mut height) = (data.size_x as u32 * upsampling, data.size_y as u32 * upsampling);
+        if let Some(crop) = options.crop {
+            (width, height) = (crop.width, crop.height);
         }
-        
-        // Extract n bits starting at bit_offset
-        let mask = if n == 32 { !0 } else { (1 << n) - 1 };
-        let val = (chunk >> bit_offset) & mask;
-        
-        self.bit_pos += n as usize;
-        val
+        data.size_x = width.div_ceil(bin) as i32;
+        data.size_y = height.div_ceil(bin) as i32;
+        let scale = bin as f32 / upsampling as f32;
+        data.voxel_spacing_x *= scale;
+        data.voxel_spacing_y *= scale;
+        self
     }
+}
 
-    /// Returns true if there are no more bits left to read
-    pub fn no_bits_left(&self) -> bool {
-        (self.buffer.len() * 8) <= self.bit_pos
-    }
+
+/// Options controlling how EER frames are decoded and summed
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+    /// Sum every Nth frame
+    pub skip_frames: Option<u32>,
+    pub upsampling: Upsampling,
+    /// Subtract the embedded dark reference, if the file has one
+    pub subtract_dark: bool,
+    /// Detect 7- vs 8-bit codes from the data instead of trusting the compression tag
+    pub auto_code_len: bool,
+    /// Stretch the thumbnail between histogram-based limits from [`auto_contrast`]
+    pub auto_contrast: bool,
+    /// 0-based frame indices to leave out of the sum
+    pub exclude: HashSet<u32>,
+    /// Process at most this many frames (after `skip_frames`), for a quick preview
+    pub max_frames: Option<u32>,
+    /// Working memory budget in bytes. Frames are always accumulated one at a time, so
+    /// this only rejects outputs whose buffers alone would exceed it.
+    pub memory_limit: Option<usize>,
+    /// Spatial downsampling of the sum: each output pixel sums a block of this many
+    /// pixels square. Edge blocks of images not divisible by it cover fewer pixels.
+    pub bin_factor: u32,
+    /// Region of the sum to keep, in pixels of the decoded (upsampled) image, applied
+    /// before binning
+    pub crop: Option<Crop>,
+    /// Checked between frames; once set, the sum stops with a [`Cancelled`] error, or
+    /// with the frames summed so far if `partial_on_cancel`: an all-zero image when it
+    /// was set before the first frame
+    pub cancel: Option<Arc<AtomicBool>>,
+    pub partial_on_cancel: bool,
+    /// Gain reference multiplied into the sum before cropping, zeroing its defects
+    pub gain: Option<GainReference>,
 }
 
-// Custom TIFF tags for EER format
-const TAG_POS_SKIP_BITS: u16 = 65007;
-const TAG_HORZ_SUB_BITS: u16 = 65008;
-const TAG_VERT_SUB_BITS: u16 = 65009;
-pub const TAG_XML_DATA: u16 = 65001;
+/// A rectangle of an image, in pixels from the top-left corner
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
 
-pub fn parse_xml_metadata(xml_str: &str) -> HashMap<String, String> {
-    let mut reader = Reader::from_str(xml_str);
-    let mut buf = Vec::new();
-    let mut metadata = HashMap::new();
-    let mut current_name = None;
-    
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(ref e)) => {
-                if e.name().as_ref() == b"item" {
-                    // Get the name attribute
-                    for attr in e.attributes() {
-                        if let Ok(attr) = attr {
-                            if attr.key.as_ref() == b"name" {
-                                if let Ok(name) = String::from_utf8(attr.value.to_vec()) {
-                                    current_name = Some(name);
-                                }
-                            }
-                        }
-                    }
-                }
-            },
-            Ok(Event::Text(e)) => {
-                if let (Some(name), Ok(text)) = (current_name.as_ref(), e.unescape()) {
-                    let text = text.trim();
-                    if !text.is_empty() {
-                        metadata.insert(name.clone(), text.to_string());
-                    }
-                }
-            },
-            Ok(Event::End(ref e)) => {
-                if e.name().as_ref() == b"item" {
-                    current_name = None;
-                }
-            },
-            Ok(Event::Eof) => break,
-            Err(e) => eprintln!("Error parsing XML: {}", e),
-            _ => (),
+impl Crop {
+    /// Cuts the rectangle out of `image`, failing if it is empty or reaches outside it
+    pub fn apply<T: Clone>(&self, image: &Array2<T>) -> Result<Array2<T>> {
+        let (height, width) = image.dim();
+        let (x, y, w, h) = (self.x as usize, self.y as usize, self.width as usize, self.height as usize);
+        let fits = |start: usize, len: usize, size: usize| start.checked_add(len).is_some_and(|end| end <= size);
+        if w == 0 || h == 0 || !fits(x, w, width) || !fits(y, h, height) {
+            return Err(anyhow!(
+                "Crop {}x{} at ({}, {}) does not fit the {}x{} decoded image",
+                w, h, x, y, width, height
+            ));
         }
+        Ok(image.slice(ndarray::s![y..y + h, x..x + w]).to_owned())
     }
-    buf.clear();
-    
-    metadata
 }
 
-#[derive(Debug)]
-pub struct CompressionParams {
-    pub code_len: u32,
-    pub horz_sub_bits: u32,
-    pub vert_sub_bits: u32,
+impl std::str::FromStr for Crop {
+    type Err = String;
+
+    /// Parses "x0,y0,w,h"
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<u32>().map_err(|e| format!("{}: {}", v, e)))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        match values[..] {
+            [x, y, width, height] => Ok(Crop { x, y, width, height }),
+            _ => Err(format!("Invalid crop '{}': expected x0,y0,w,h", s)),
+        }
+    }
 }
 
-pub fn get_compression_params(decoder: &mut Decoder<File>) -> Result<CompressionParams> {
-    let compression = decoder.get_tag_u32(Tag::Compression)?;
-    
-    match compression {
-        65000 => Ok(CompressionParams {
-            code_len: 8,
-            horz_sub_bits: 2,
-            vert_sub_bits: 2,
-        }),
-        65001 => Ok(CompressionParams {
-            code_len: 7,
-            horz_sub_bits: 2,
-            vert_sub_bits: 2,
-        }),
-        65002 => {
-            // Read from custom tags
-            let code_len = decoder.get_tag_u32(Tag::Unknown(TAG_POS_SKIP_BITS))?;
-            let horz_sub_bits = decoder.get_tag_u32(Tag::Unknown(TAG_HORZ_SUB_BITS))?;
-            let vert_sub_bits = decoder.get_tag_u32(Tag::Unknown(TAG_VERT_SUB_BITS))?;
-            
-            Ok(CompressionParams {
-                code_len,
-                horz_sub_bits,
-                vert_sub_bits,
-            })
-        },
-        _ => Err(anyhow!("Unsupported compression type: {}", compression))
+impl DecodeOptions {
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed))
     }
 }
 
-pub fn compression_to_string(compression: u32) -> &'static str {
-    match compression {
-        1 => "None",
-        2 => "CCITT Group 3",
-        3 => "CCITT T4",
-        4 => "CCITT T6",
-        5 => "LZW",
-        6 => "JPEG (old)",
-        7 => "JPEG",
-        8 => "Adobe Deflate",
-        9 => "JBIG B&W",
-        10 => "JBIG Color",
-        32773 => "PackBits",
-        _ => "Unknown",
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            skip_frames: None,
+            upsampling: Upsampling::Factor(1),
+            subtract_dark: false,
+            auto_code_len: false,
+            auto_contrast: false,
+            exclude: HashSet::new(),
+            max_frames: None,
+            memory_limit: None,
+            bin_factor: 1,
+            crop: None,
+            cancel: None,
+            partial_on_cancel: false,
+            gain: None,
+        }
     }
 }
 
-pub fn sample_format_to_string(format: u32) -> &'static str {
-    match format {
-        1 => "Unsigned integer",
-        2 => "Signed integer",
-        3 => "IEEE floating point",
-        4 => "Undefined",
-        _ => "Unknown",
+/// Number of leading frames to walk so that at most `max_frames` of every
+/// `skip_frames`-th frame are processed. A cap above the frames available is clamped
+/// with a warning.
+fn capped_frame_count(total_frames: u32, skip_frames: Option<u32>, max_frames: Option<u32>) -> Result<u32> {
+    let step = frame_step(skip_frames)?;
+    let available = total_frames.div_ceil(step);
+    match max_frames {
+        Some(0) => Err(anyhow!("max_frames must be at least 1")),
+        Some(max) if max > available => {
+            eprintln!("Only {} frames available, processing all of them", available);
+            Ok(total_frames)
+        }
+        // The walk covers frames 0, step, ..., (max - 1) * step
+        Some(max) => Ok((max - 1) * step + 1),
+        None => Ok(total_frames),
     }
 }
 
-pub fn save_image(image: &Array2<u16>, path: &Path) -> Result<()> {
-    // Convert to f32 for calculations
-    let float_img = image.mapv(|x| x as f32);
-    
-    // Apply log scaling (add 1 to avoid log(0))
-    let log_img = float_img.mapv(|x| (x + 1.0).ln());
-    
-    // Find min and max of log values
-    let min_val = log_img.iter().min_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
-    let max_val = log_img.iter().max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
-    let range = max_val - min_val;
-    
-    // Normalize to [0,1] then scale to [0,255]
-    let scaled = log_img.mapv(|x| (((x - min_val) / range) * 255.0) as u8);
-    
-    // Convert to image buffer
-    let height = scaled.shape()[0];
-    let width = scaled.shape()[1];
-    let (v, _offset) = scaled.as_standard_layout().into_owned().into_raw_vec_and_offset();
+/// Decodes and sums the movie selected by `options`, returning the raw accumulated
+/// counts. This is what [`generate_thumbnail`] normalizes and saves.
+///
+/// Files whose metadata reports an integrating acquisition hold ordinary TIFF images
+/// instead of EER bitstreams; those are read with the TIFF decoder (see
+/// [`decode_integrating_frames`]) without upsampling or dark subtraction, and a note
+/// on stderr says so.
+pub fn sum_frames(path: &Path, options: &DecodeOptions) -> Result<Array2<u32>> {
+    Ok(sum_frames_reading(path, options, &mut open_input(path)?)?.0)
+}
 
-    let img = image::GrayImage::from_raw(
-        width as u32,
-        height as u32,
-        v
-    ).ok_or_else(|| anyhow!("Failed to create image"))?;
-    
-    // Save
-    img.save(path)?;
-    Ok(())
+/// Like [`sum_frames`], also returning the settings the sum was actually made with:
+/// the frames summed, frame step, upsampling and crop the decode used, for recording
+/// next to the image
+pub fn sum_frames_with_params(path: &Path, options: &DecodeOptions) -> Result<(Array2<u32>, DecodeParams)> {
+    sum_frames_reading(path, options, &mut open_input(path)?)
 }
 
-#[derive(Debug)]
-struct StripInfo {
-    offset: u64,
-    size: u64,
+/// Like [`sum_frames`], also returning the BLAKE3 digest of the whole file, as `b3sum`
+/// prints it. The digest is fed from the strip reads the decode makes anyway, so only
+/// the bytes it does not read (TIFF structure, metadata, skipped frames) are read
+/// again. Integrating-mode files are decoded through a separate handle, so for them
+/// the digest costs one extra full read.
+#[cfg(feature = "checksum")]
+pub fn sum_frames_with_digest(path: &Path, options: &DecodeOptions) -> Result<(Array2<u32>, blake3::Hash)> {
+    let mut reader = checksum::HashingReader::new(open_input(path)?);
+    let (sum, _) = sum_frames_reading(path, options, &mut reader)?;
+    Ok((sum, reader.finish()?))
 }
 
-fn get_strips_info(decoder: &mut Decoder<File>) -> Result<Vec<StripInfo>> {
-    let offsets = decoder.get_tag_u64_vec(Tag::StripOffsets)?;
-    let sizes = decoder.get_tag_u64_vec(Tag::StripByteCounts)?;
-    
-    Ok(offsets.into_iter()
-        .zip(sizes.into_iter())
-        .map(|(offset, size)| StripInfo { offset, size })
-        .collect())
+/// [`sum_frames_with_params`] reading EER strips through `file`, a handle on `path`
+fn sum_frames_reading(
+    path: &Path,
+    options: &DecodeOptions,
+    file: &mut (impl Read + Seek),
+) -> Result<(Array2<u32>, DecodeParams)> {
+    let metadata = read_metadata_or_default(path)?;
+    let mode = metadata.acquisition_mode();
+
+    if options.bin_factor == 0 {
+        return Err(anyhow!("Bin factor must be at least 1"));
+    }
+
+    let skip_frames = frame_step(options.skip_frames)?;
+    let (image, frames_summed, upsampling) = if mode == Some(AcquisitionMode::Integrating) {
+        eprintln!("Integrating-mode acquisition: reading frames as standard TIFF images");
+        if options.subtract_dark {
+            eprintln!("Dark subtraction only applies to counting data, skipping");
+        }
+        let (image, frames_summed) = decode_integrating_frames(path, options)?;
+        if frames_summed == 0 && !options.is_cancelled() {
+            return Err(anyhow!("Every selected frame was excluded"));
+        }
+        (image, frames_summed, 1)
+    } else {
+        sum_counted_frames(path, options, &metadata, file)?
+    };
+    let image = match &options.gain {
+        Some(gain) => {
+            eprintln!("Applying the gain reference, masking {} defective pixels", gain.defect_count());
+            gain.apply(&image)?
+        }
+        None => image,
+    };
+
+    let crop = options.crop;
+    let image = match crop {
+        Some(crop) => crop.apply(&image)?,
+        None => image,
+    };
+    let params = DecodeParams::new(options, frames_summed, skip_frames, upsampling, crop);
+    Ok((bin_sum(&image, options.bin_factor as usize), params))
 }
 
-pub fn decode_eer_frame(
-    decoder: &mut Decoder<File>,
-    params: &CompressionParams,
-    file: &mut File,  // Take file handle as parameter
-) -> Result<Array2<u16>> {
-    let height = decoder.dimensions()?.1 as usize;
-    let width = decoder.dimensions()?.0 as usize;
-    let mut image = Array2::<u16>::zeros((height, width));
-    
-    let strips_info = get_strips_info(decoder)?;
-    let pos_skip_max = (1 << params.code_len) - 1;
-    let rows_per_strip = decoder.get_tag_u32(Tag::RowsPerStrip)? as usize;
-    
-    // Pre-allocate buffer for largest strip
-    let max_strip_size = strips_info.iter().map(|s| s.size as usize).max().unwrap_or(0);
-    let mut raw_data = vec![0u8; max_strip_size];
-    
-    for (strip_idx, strip_info) in strips_info.iter().enumerate() {
-        // Read strip data
-        file.seek(SeekFrom::Start(strip_info.offset))?;
-        file.read_exact(&mut raw_data[..strip_info.size as usize])?;
-        
-        let mut bs = BitStream::new(&raw_data[..strip_info.size as usize]);
-        
-        let start_row = strip_idx * rows_per_strip;
-        let end_row = (start_row + rows_per_strip).min(height);
-        let strip_pixel_start = start_row * width;
-        let strip_pixel_end = end_row * width;
-        
-        let mut pos = 0;
-        while (strip_pixel_start + pos) < strip_pixel_end {
-            let skip = bs.get_bits(params.code_len);
-            pos += skip as usize;
-            
-            if (strip_pixel_start + pos) >= strip_pixel_end {
-                break;
-            }
-            
-            if skip < pos_skip_max {
-                // Read subpixel bits (currently ignored)
-                let _v_sub = bs.get_bits(params.vert_sub_bits);
-                let _h_sub = bs.get_bits(params.horz_sub_bits);
-                
-                // Calculate pixel position more efficiently
-                let global_pixel = strip_pixel_start + pos;
-                let row = global_pixel / width;
-                let col = global_pixel % width;
-                
-                // Direct array access is faster than using index operator
-                let slice = image.as_slice_mut().unwrap();
-                slice[row * width + col] += 1;
-                
-                pos += 1;
-            }
-            // skip == max => no event here, continue
+/// Sums the frames of an acquisition split across several files (part1, part2, ...), in
+/// order, as [`sum_frames`] would for one file. `options` apply to each part on its own,
+/// so e.g. `skip_frames` restarts at the first frame of every part. Fails before
+/// decoding anything if the parts differ in frame size or compression.
+pub fn sum_frame_series(paths: &[&Path], options: &DecodeOptions) -> Result<Array2<u32>> {
+    let Some(first) = paths.first() else {
+        return Err(anyhow!("No files to sum"));
+    };
+    let layout = |path: &Path| -> Result<((usize, usize), CompressionParams, u32)> {
+        let mut decoder = Decoder::new(open_input(path)?)?;
+        let frames = count_frames(&mut decoder)?;
+        let dims = first_frame_dims(&mut decoder).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        Ok((dims, get_compression_params(&mut decoder)?.params(), frames))
+    };
+    let (dims, params, mut total_frames) = layout(first)?;
+    for path in &paths[1..] {
+        let (part_dims, part_params, frames) = layout(path)?;
+        if part_dims != dims {
+            return Err(anyhow!(
+                "{} has {}x{} frames but {} has {}x{}",
+                path.display(), part_dims.1, part_dims.0, first.display(), dims.1, dims.0
+            ));
+        }
+        if part_params != params {
+            return Err(anyhow!(
+                "{} is compressed as {:?} but {} as {:?}",
+                path.display(), part_params, first.display(), params
+            ));
         }
+        total_frames += frames;
     }
-    
-    Ok(image)
-}
 
-pub fn decode_frames(
-    decoder: &mut Decoder<File>,
-    params: &mut CompressionParams,
-    path: &Path,
-    num_frames: u32,
-    skip_frames: Option<u32>,
-) -> Result<Array2<u16>> {
-    let mut file = File::open(path)?;
-    // Get dimensions from first frame
-    let height = decoder.dimensions()?.1;
-    let width = decoder.dimensions()?.0;
-    let mut sum_image = Array2::<u16>::zeros((height as usize, width as usize));
-
-    // Calculate effective number of frames to process
-    let step = skip_frames.unwrap_or(1);
-    let frames_to_process = (num_frames + step - 1) / step;
-    
-    // Decode and sum frames with skipping
-    for frame_idx in (0..num_frames).step_by(step as usize) {
-        println!("Decoding frame {} of {} (total frames to process: {})", 
-                frame_idx + 1, num_frames, frames_to_process);
-        
-        let frame_image = decode_eer_frame(decoder, params, &mut file)?;
-        sum_image += &frame_image;
+    let mut sum = sum_frames(first, options)?;
+    for path in &paths[1..] {
+        sum += &sum_frames(path, options)?;
+    }
+    eprintln!("Summed {} files with {} frames in total", paths.len(), total_frames);
+    Ok(sum)
+}
 
-        // Skip frames
-        for _ in 0..step.min(num_frames - frame_idx - 1) {
-            if decoder.more_images() {
-                decoder.next_image()?;
-                // Update compression params for new frame
-                *params = get_compression_params(decoder)?;
-            }
-        }
+/// Sums `factor` x `factor` blocks of `image`
+fn bin_sum(image: &Array2<u32>, factor: usize) -> Array2<u32> {
+    if factor == 1 {
+        return image.clone();
+    }
+    let (height, width) = image.dim();
+    let mut binned = Array2::<u32>::zeros((height.div_ceil(factor), width.div_ceil(factor)));
+    for ((y, x), &v) in image.indexed_iter() {
+        binned[[y / factor, x / factor]] += v;
     }
+    binned
+}
 
-    Ok(sum_image)
+/// Decodes and sums the movie (see [`sum_frames`]) and saves it as a PNG; an `output`
+/// of `-` writes to stdout. Progress goes to stderr so stdout stays clean for piping.
+pub fn generate_thumbnail(path: &Path, output: &Path, options: &DecodeOptions) -> Result<()> {
+    write_gray_image(&thumbnail_image(path, options)?, output)?;
+    eprintln!("\nSaved thumbnail to {}", output.display());
+    Ok(())
 }
 
+/// The thumbnail [`generate_thumbnail`] would save, as PNG bytes in memory, with the
+/// settings it was made with (see [`sum_frames_with_params`])
+pub fn encode_thumbnail(path: &Path, options: &DecodeOptions) -> Result<(Vec<u8>, DecodeParams)> {
+    let (sum, params) = sum_frames_with_params(path, options)?;
+    Ok((encode_png_image(&sum_to_gray_image(sum, options)?)?, params))
+}
 
-#[derive(Debug, Serialize)]
-pub enum VoxelType {
-    UnsignedInt16,
+/// [`generate_thumbnail`] for an acquisition split across several files, see
+/// [`sum_frame_series`]
+pub fn generate_series_thumbnail(paths: &[&Path], output: &Path, options: &DecodeOptions) -> Result<()> {
+    write_gray_image(&sum_to_gray_image(sum_frame_series(paths, options)?, options)?, output)?;
+    eprintln!("\nSaved thumbnail of {} files to {}", paths.len(), output.display());
+    Ok(())
 }
 
+fn thumbnail_image(path: &Path, options: &DecodeOptions) -> Result<image::GrayImage> {
+    sum_to_gray_image(sum_frames(path, options)?, options)
+}
 
-#[derive(Debug, Serialize)]
-pub struct ImageData {
-    size_x: i32,
-    size_y: i32,
-    size_z: i32,
-    size_t: i32,
-    size_c: i32,
-    voxel_type: VoxelType,
-    voxel_spacing_x: f32,
-    voxel_spacing_y: f32,
-    voxel_spacing_z: f32,
+fn sum_to_gray_image(sum: Array2<u32>, options: &DecodeOptions) -> Result<image::GrayImage> {
+    let limits = options.auto_contrast.then(|| auto_contrast(&sum));
+    to_gray_image(&sum, limits)
 }
 
+/// The counting-mode half of [`sum_frames`]: decodes and sums the EER frames, returning
+/// the sum, the frames summed and the upsampling used
+fn sum_counted_frames(
+    path: &Path,
+    options: &DecodeOptions,
+    metadata: &EerMetadata,
+    file: &mut (impl Read + Seek),
+) -> Result<(Array2<u32>, u32, u32)> {
+    // One decoder for the IFDs and the one `file` for reading strips, shared by every step
+    let mut decoder = Decoder::new(open_input(path)?)?;
 
-pub fn generate_thumbnail(path: &Path, output: &Path, skip_frames: Option<u32>) -> Result<()> {
-    let file = File::open(path)?;
-    let mut decoder = Decoder::new(file)?;
+    // Count total frames; this leaves the decoder back on the first page
+    let total_frames = count_frames(&mut decoder)?;
+    if total_frames == 0 {
+        return Err(anyhow!("No EER frames found in {}", path.display()));
+    }
 
-    // Count total frames
-    let mut total_frames = 1;
-    while decoder.more_images() {
-        total_frames += 1;
-        decoder.next_image()?;
+    first_frame_dims(&mut decoder)?;
+    let mut params = get_compression_params(&mut decoder)?.params();
+    if options.auto_code_len {
+        params.code_len = detect_code_len(&mut decoder, &params, file)?;
     }
+    let preset = metadata.detector_preset();
+    let upsampling = options.upsampling.resolve_for(&params, preset);
 
-    // Reset decoder to start
-    let file = File::open(path)?;
-    let mut decoder = Decoder::new(file)?;
-    let mut params = get_compression_params(&mut decoder)?;
+    if let Some(limit) = options.memory_limit {
+        let (width, height) = decoder.dimensions()?;
+        let pixels = width as usize * height as usize * (upsampling * upsampling) as usize;
+        // The u32 sum, one decoded u16 frame and the 8-bit image
+        let needed = pixels * (4 + 2 + 1);
+        if needed > limit {
+            return Err(anyhow!(
+                "Thumbnail needs about {} bytes, over the memory limit of {} bytes; lower the upsampling",
+                needed,
+                limit
+            ));
+        }
+    }
 
     // Decode frames with optional skipping
-    let image = decode_frames(&mut decoder, &mut params, path, total_frames, skip_frames)?;
-    
-    // Save the thumbnail
-    save_image(&image, output)?;
-    println!("\nSaved thumbnail to {}", output.display());
-    Ok(())
+    let mut sum_options = SumOptions {
+        exclude: Some(&options.exclude),
+        cancel: options.cancel.as_deref(),
+        max_frames: options.max_frames,
+        ..Default::default()
+    };
+    let (mut image, frames_summed, cancelled) = accumulate_frames(
+        &mut decoder, &params, file, total_frames, options.skip_frames, upsampling, &mut sum_options,
+    )?;
+    if cancelled {
+        if !options.partial_on_cancel {
+            return Err(Cancelled.into());
+        }
+        eprintln!("Cancelled after summing {} frames", frames_summed);
+    } else if frames_summed == 0 {
+        return Err(anyhow!("Every selected frame was excluded"));
+    }
+
+    if options.subtract_dark {
+        match dark_reference(path)? {
+            Some(dark) => {
+                image = subtract_dark_reference(&image, &dark, frames_summed)?;
+            }
+            None => eprintln!("No dark reference found in file, skipping subtraction"),
+        }
+    }
+    Ok((image, frames_summed, upsampling))
 }
 
+/// Opens `path` and returns the per-pixel mean and variance of every `skip_frames`-th
+/// frame at native resolution; see [`decode_frames_stats`]
+pub fn frame_stats(path: &Path, skip_frames: Option<u32>) -> Result<(Array2<f32>, Array2<f32>)> {
+    let mut decoder = Decoder::new(open_input(path)?)?;
+    let total_frames = count_frames(&mut decoder)?;
+    if total_frames == 0 {
+        return Err(anyhow!("No EER frames found in {}", path.display()));
+    }
+
+    first_frame_dims(&mut decoder)?;
+    let params = get_compression_params(&mut decoder)?.params();
+    decode_frames_stats(&mut decoder, &params, &mut File::open(path)?, total_frames, skip_frames, 1)
+}
 
-pub fn show_header_info(path: &Path) -> Result<()> {
-    let file = File::open(path)?;
-    let mut decoder = Decoder::new(file)?;
-    
+/// Reads the image size, compression, XML metadata and page and frame counts of an
+/// EER file. Unreadable XML leaves the metadata empty, with a warning on stderr.
+pub fn header_report(path: &Path) -> Result<HeaderReport> {
+    let mut decoder = Decoder::new(open_input(path)?)?;
     let mut image_data = ImageData {
         size_x: 0,
         size_y: 0,
@@ -395,84 +3768,54 @@ pub fn show_header_info(path: &Path) -> Result<()> {
         voxel_spacing_y: 0.0,
         voxel_spacing_z: 0.0,
     };
-    
-    if let Ok(dims) = decoder.dimensions() {
-        image_data.size_x = dims.0 as i32;
-        image_data.size_y = dims.1 as i32;
-        
-        // Get XML metadata
-        match decoder.get_tag(Tag::Unknown(TAG_XML_DATA)) {
-            Ok(value) => {
-                // println!("Value (debug): {:?}", value);
-        
-                match value {
-                    // You might still have other variants, handle them as needed
-                    Value::List(list_of_values) => {
-                        // println!("\nDebug: Found List variant with {} values", list_of_values.len());
-                        // Convert [Byte(60), Byte(109), ...] into a real Vec<u8>
-                        let bytes: Vec<u8> = list_of_values.iter()
-                            .filter_map(|v| {
-                                if let Value::Byte(b) = v {
-                                    Some(*b)  // Byte(60) -> 60
-                                } else {
-                                    None      // skip any non-Byte items
-                                }
-                            })
-                            .collect();
-        
-                        // Now try interpreting those bytes as UTF-8 text
-                        if let Ok(xml_str) = String::from_utf8(bytes) {
-                            // println!("\nDebug: Successfully converted bytes to UTF-8 string");
-                            // println!("Debug: XML content:\n{}", xml_str);
-                            let metadata = parse_xml_metadata(&xml_str);
-                            
-                            // Extract pixel sizes
-                            if let Some(width) = metadata.get("sensorPixelSize.width") {
-                                if let Ok(width) = width.parse::<f32>() {
-                                    image_data.voxel_spacing_x = width;
-                                }
-                            }
-                            if let Some(height) = metadata.get("sensorPixelSize.height") {
-                                if let Ok(height) = height.parse::<f32>() {
-                                    image_data.voxel_spacing_y = height;
-                                }
-                            }
-                        } else {
-                            println!("Not valid UTF-8");
-                        }
-                    },
-        
-                    // If you still have an Ascii or a single Byte variant, handle them here...
-                    Value::Ascii(s) => {
-                        println!("Ascii text: {s}");
-                    }
-                    Value::Byte(b) => {
-                        println!("Single byte: {b}");
-                    }
-                    _ => {
-                        println!("Unhandled variant");
-                    }
-                }
-            }
-            Err(e) => {
-                println!("Error: {:?}", e);
-            }
-        }
-        
-        
+    // Sizes of the frames rather than of a leading overview, if there are any frames
+    let mut page = 0;
+    if !seek_frame_page(&mut decoder, &mut page)? {
+        load_page(&mut decoder, 0)?;
     }
-    
-    // Count total pages
-    let mut page_count = 1;
-    while decoder.more_images() {
+    let (width, height) = decoder.dimensions()?;
+    image_data.size_x = width as i32;
+    image_data.size_y = height as i32;
+
+    let metadata = match find_xml_tag(&mut decoder)?.map(xml_from_value) {
+        Some(Ok(xml)) => EerMetadata::from_xml(&xml),
+        Some(Err(e)) => {
+            eprintln!("Unreadable XML metadata: {}", e);
+            EerMetadata::default()
+        }
+        None => EerMetadata::default(),
+    };
+    let spacing = |key: &str| metadata.items.get(key).and_then(|v| v.parse::<f32>().ok());
+    image_data.voxel_spacing_x = spacing("sensorPixelSize.width").unwrap_or(0.0);
+    image_data.voxel_spacing_y = spacing("sensorPixelSize.height").unwrap_or(0.0);
+
+    let mut page_count = 0;
+    while load_page(&mut decoder, page_count)? {
         page_count += 1;
-        decoder.next_image()?;
     }
-    
-    println!("\nTotal number of pages in TIFF: {}", page_count);
-    
-    // Output JSON representation
-    // println!("\nImage Data:");
-    println!("{}", serde_json::to_string_pretty(&image_data)?);
-    Ok(())
+    let frame_count = count_frames(&mut decoder)?;
+    let mut warnings = Vec::new();
+    let compression = if frame_count > 0 {
+        let dims = first_frame_dims(&mut decoder)?;
+        let params = get_compression_params(&mut decoder).ok().map(|compression| compression.params());
+        if let (Some(preset), Some(params)) = (metadata.detector_preset(), &params) {
+            warnings = preset.check(dims, params);
+        }
+        params
+    } else {
+        None
+    };
+
+    Ok(HeaderReport {
+        image_data,
+        compression,
+        detector: metadata.detector_model().map(str::to_string),
+        voltage_kv: metadata.voltage_kv(),
+        nominal_magnification: metadata.nominal_magnification(),
+        metadata: metadata.items.into_iter().collect(),
+        page_count: page_count as u32,
+        frame_count,
+        warnings,
+        decode_params: None,
+    })
 }
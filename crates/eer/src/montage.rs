@@ -0,0 +1,115 @@
+//! A grid of individual decoded frames, to show how a movie evolves over time.
+
+use std::fs::File;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use image::GrayImage;
+use tiff::decoder::Decoder;
+
+use crate::{
+    auto_contrast, capped_frame_count, count_frames, first_frame_dims, get_compression_params,
+    open_input, read_metadata_or_default, to_gray_image, walk_frames, write_gray_image,
+    DecodeOptions,
+};
+
+/// 3x5 bitmaps of the digits 0-9, one row of three bits per entry, most significant bit left
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// Picks up to `rows * cols` evenly spaced frames, decodes each on its own, normalizes it
+/// (with auto contrast if `options.auto_contrast`) and tiles them row by row into one
+/// PNG; an `output` of `-` writes to stdout. Cells beyond the frame count stay black.
+/// With `labels`, each tile is marked with its frame index in the top-left corner.
+///
+/// `options.skip_frames` is ignored, as the spacing follows from the grid size; upsampling,
+/// exclusions and `max_frames` apply as for thumbnails.
+pub fn generate_montage(
+    path: &Path,
+    output: &Path,
+    rows: u32,
+    cols: u32,
+    options: &DecodeOptions,
+    labels: bool,
+) -> Result<()> {
+    let cells = rows.checked_mul(cols).filter(|&cells| cells > 0)
+        .ok_or_else(|| anyhow!("A montage needs at least one row and one column"))?;
+
+    let mut decoder = Decoder::new(open_input(path)?)?;
+    let mut file = File::open(path)?;
+    let total_frames = count_frames(&mut decoder)?;
+    if total_frames == 0 {
+        return Err(anyhow!("No EER frames found in {}", path.display()));
+    }
+    let available = options.max_frames.map_or(total_frames, |max| max.min(total_frames));
+    let shown = cells.min(available);
+    if shown < cells {
+        eprintln!("Montage has {} cells but only {} frames; leaving the rest empty", cells, shown);
+    }
+    let step = (available / shown).max(1);
+
+    let (height, width) = first_frame_dims(&mut decoder)?;
+    let params = get_compression_params(&mut decoder)?.params();
+    let preset = read_metadata_or_default(path)?.detector_preset();
+    let upsampling = options.upsampling.resolve_for(&params, preset);
+    let (tile_w, tile_h) = (width as u32 * upsampling, height as u32 * upsampling);
+    let mut montage = GrayImage::new(tile_w * cols, tile_h * rows);
+
+    let num_frames = capped_frame_count(total_frames, Some(step), Some(shown))?;
+    walk_frames(&mut decoder, &params, &mut file, num_frames, Some(step), |frame_idx, cell, frame| {
+        if options.exclude.contains(&frame_idx) {
+            return Ok(());
+        }
+        let frame = frame.decode(upsampling)?.mapv(u32::from);
+        let limits = options.auto_contrast.then(|| auto_contrast(&frame));
+        let mut tile = to_gray_image(&frame, limits)?;
+        if labels {
+            draw_label(&mut tile, frame_idx);
+        }
+        let (x, y) = ((cell % cols) * tile_w, (cell / cols) * tile_h);
+        image::imageops::replace(&mut montage, &tile, x as i64, y as i64);
+        Ok(())
+    })?;
+
+    write_gray_image(&montage, output)?;
+    eprintln!("\nSaved {}x{} montage to {}", rows, cols, output.display());
+    Ok(())
+}
+
+/// Writes `number` in white on a black box in the top-left corner, with the glyphs
+/// scaled to stay legible on large frames
+fn draw_label(tile: &mut GrayImage, number: u32) {
+    let scale = (tile.width() / 128).max(1);
+    let text = number.to_string();
+    let box_w = ((text.len() as u32) * 4 + 1) * scale;
+    let box_h = 7 * scale;
+    for y in 0..box_h.min(tile.height()) {
+        for x in 0..box_w.min(tile.width()) {
+            tile.put_pixel(x, y, image::Luma([0]));
+        }
+    }
+    for (i, digit) in text.bytes().map(|b| (b - b'0') as usize).enumerate() {
+        for (row, bits) in DIGITS[digit].iter().enumerate() {
+            for col in 0..3 {
+                if bits >> (2 - col) & 1 == 0 {
+                    continue;
+                }
+                let (x0, y0) = ((1 + i as u32 * 4 + col) * scale, (1 + row as u32) * scale);
+                for y in y0..(y0 + scale).min(tile.height()) {
+                    for x in x0..(x0 + scale).min(tile.width()) {
+                        tile.put_pixel(x, y, image::Luma([255]));
+                    }
+                }
+            }
+        }
+    }
+}
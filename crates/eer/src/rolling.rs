@@ -0,0 +1,67 @@
+//! Running sum over the most recent frames, for live views.
+
+use anyhow::{Result, anyhow};
+use ndarray::{Array2, Zip};
+
+/// Sum of the last `window` frames pushed. The frames are kept in a ring of `window`
+/// slots; once it is full each push overwrites the oldest slot in place, after
+/// subtracting it from the sum, so memory stays at `window` u16 frames plus one u32 sum.
+#[derive(Debug)]
+pub struct RollingSum {
+    window: usize,
+    frames: Vec<Array2<u16>>,
+    /// Slot of the oldest frame once the ring is full
+    oldest: usize,
+    sum: Option<Array2<u32>>,
+}
+
+impl RollingSum {
+    /// Creates an empty window of `window` frames; panics if `window` is 0
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "RollingSum window must hold at least one frame");
+        RollingSum { window, frames: Vec::with_capacity(window), oldest: 0, sum: None }
+    }
+
+    /// Adds `frame`, dropping the oldest frame once the window is full, and returns the
+    /// sum of the frames now in the window. Every frame must have the same shape.
+    pub fn push_frame(&mut self, frame: &Array2<u16>) -> Result<&Array2<u32>> {
+        let sum = self.sum.get_or_insert_with(|| Array2::zeros(frame.dim()));
+        if sum.dim() != frame.dim() {
+            return Err(anyhow!("Frame of shape {:?} pushed onto a window of {:?}", frame.dim(), sum.dim()));
+        }
+
+        if self.frames.len() < self.window {
+            Zip::from(&mut *sum).and(frame).for_each(|s, &v| *s += v as u32);
+            self.frames.push(frame.clone());
+        } else {
+            let slot = &mut self.frames[self.oldest];
+            Zip::from(&mut *sum).and(&*slot).and(frame).for_each(|s, &old, &new| {
+                *s = *s - old as u32 + new as u32;
+            });
+            slot.assign(frame);
+            self.oldest = (self.oldest + 1) % self.window;
+        }
+        Ok(sum)
+    }
+
+    /// Sum of the frames in the window; None before the first push
+    pub fn sum(&self) -> Option<&Array2<u32>> {
+        self.sum.as_ref()
+    }
+
+    /// Number of frames currently in the window, at most `window`
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Empties the window, e.g. when the live view switches to frames of another shape
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.oldest = 0;
+        self.sum = None;
+    }
+}
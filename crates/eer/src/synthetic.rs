@@ -0,0 +1,338 @@
+//! Small synthetic EER files, so tests and benchmarks can run without large fixtures.
+//!
+//! The writer produces a little-endian classic TIFF with one IFD per frame (or per group
+//! of frames, for packed pages), encoding electron events with the same run-length
+//! scheme that `decode_eer_frame` reads.
+
+use std::fs;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+
+use crate::{
+    CompressionParams, EerCompression, SUBFILE_REDUCED_IMAGE, TAG_DARK_REFERENCE, TAG_FRAMES_PER_PAGE,
+    TAG_HORZ_SUB_BITS, TAG_POS_SKIP_BITS, TAG_VERT_SUB_BITS, TAG_XML_DATA,
+};
+
+/// One electron event: sensor column, row, horizontal and vertical sub-pixel offsets
+pub type SyntheticEvent = (u32, u32, u8, u8);
+
+/// Description of a synthetic EER file
+#[derive(Debug)]
+pub struct SyntheticEer {
+    pub width: u32,
+    pub height: u32,
+    pub rows_per_strip: u32,
+    /// TIFF compression value, see the `EerCompression` codes
+    pub compression: u32,
+    /// Bit layout used to encode events; written as custom tags for compression 65002
+    pub params: CompressionParams,
+    /// XML metadata stored in `TAG_XML_DATA` on the first page
+    pub xml: Option<String>,
+    /// Events for each frame; repeated pixels within a frame are dropped
+    pub frames: Vec<Vec<SyntheticEvent>>,
+    /// Writes a leading ReducedImage page holding an EER-encoded copy of the first frame,
+    /// as some writers do for an overview, which readers must not count as a frame
+    pub overview_first: bool,
+    /// Frames packed into each IFD, recorded in the private `EerFramesPerPage` tag; 1 for
+    /// ordinary files. Detector software never writes packed pages.
+    pub frames_per_page: usize,
+    /// Row-major per-frame dark reference, appended after the frames as an uncompressed
+    /// float page marked with `TAG_DARK_REFERENCE`
+    pub dark_reference: Option<Vec<f32>>,
+}
+
+impl SyntheticEer {
+    /// Creates an empty 7-bit (`EerCompression::Count7`) file with a single strip per frame
+    pub fn new(width: u32, height: u32) -> Self {
+        SyntheticEer {
+            width,
+            height,
+            rows_per_strip: height,
+            compression: EerCompression::Count7.code(),
+            params: EerCompression::Count7.params(),
+            xml: None,
+            frames: Vec::new(),
+            overview_first: false,
+            frames_per_page: 1,
+            dark_reference: None,
+        }
+    }
+
+    /// Adds `num_frames` frames with `events_per_frame` pseudo-random events each.
+    /// The sequence is deterministic for a given seed.
+    pub fn fill_random(&mut self, num_frames: usize, events_per_frame: usize, seed: u64) {
+        let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let mut next = move || {
+            // 64-bit LCG, high bits are the best distributed
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as u32
+        };
+        let h_max = 1u32 << self.params.horz_sub_bits;
+        let v_max = 1u32 << self.params.vert_sub_bits;
+
+        for _ in 0..num_frames {
+            let events = (0..events_per_frame)
+                .map(|_| {
+                    (
+                        next() % self.width,
+                        next() % self.height,
+                        (next() % h_max) as u8,
+                        (next() % v_max) as u8,
+                    )
+                })
+                .collect();
+            self.frames.push(events);
+        }
+    }
+
+    /// Encodes the events of one strip covering rows `start_row..end_row`
+    fn encode_strip(&self, events: &[SyntheticEvent], start_row: u32, end_row: u32) -> Vec<u8> {
+        let width = self.width as u64;
+        let strip_start = start_row as u64 * width;
+        let strip_end = end_row as u64 * width;
+        let pos_skip_max = self.params.pos_skip_max() as u64;
+
+        let mut pixels: Vec<(u64, u8, u8)> = events
+            .iter()
+            .filter(|e| e.1 >= start_row && e.1 < end_row)
+            .map(|&(x, y, h, v)| (y as u64 * width + x as u64 - strip_start, h, v))
+            .collect();
+        // The run-length code advances past each event, so a pixel holds at most one per frame
+        pixels.sort_by_key(|p| p.0);
+        pixels.dedup_by_key(|p| p.0);
+
+        let mut writer = BitWriter::default();
+        let mut pos = 0u64;
+        for (pixel, h_sub, v_sub) in pixels {
+            let mut skip = pixel - pos;
+            while skip >= pos_skip_max {
+                writer.put(pos_skip_max as u32, self.params.code_len);
+                skip -= pos_skip_max;
+            }
+            writer.put(skip as u32, self.params.code_len);
+            writer.put(v_sub as u32, self.params.vert_sub_bits);
+            writer.put(h_sub as u32, self.params.horz_sub_bits);
+            pos = pixel + 1;
+        }
+
+        // Run out the rest of the strip so the decoder stops exactly at its end
+        let mut remaining = (strip_end - strip_start) - pos;
+        while remaining >= pos_skip_max {
+            writer.put(pos_skip_max as u32, self.params.code_len);
+            remaining -= pos_skip_max;
+        }
+        if remaining > 0 {
+            writer.put(remaining as u32, self.params.code_len);
+        }
+        writer.finish()
+    }
+
+    /// Serializes the file to TIFF bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        if self.frames.is_empty() {
+            return Err(anyhow!("A synthetic EER file needs at least one frame"));
+        }
+        if self.rows_per_strip == 0 {
+            return Err(anyhow!("rows_per_strip must be non-zero"));
+        }
+        if self.frames_per_page == 0 {
+            return Err(anyhow!("frames_per_page must be non-zero"));
+        }
+        self.params.validate()?;
+
+        let mut out = vec![b'I', b'I', 42, 0, 0, 0, 0, 0];
+        let mut ifd_link = 4usize;
+
+        if self.overview_first {
+            let mut entries = self.write_page(&mut out, &self.frames[..1]);
+            entries.push(IfdEntry::long(254, &[SUBFILE_REDUCED_IMAGE]));
+            ifd_link = write_ifd(&mut out, ifd_link, entries);
+        }
+
+        for (page_idx, frames) in self.frames.chunks(self.frames_per_page).enumerate() {
+            let mut entries = self.write_page(&mut out, frames);
+            if let (0, Some(xml)) = (page_idx, &self.xml) {
+                entries.push(IfdEntry::bytes(TAG_XML_DATA, xml.as_bytes()));
+            }
+
+            ifd_link = write_ifd(&mut out, ifd_link, entries);
+        }
+
+        if let Some(dark) = &self.dark_reference {
+            if dark.len() != self.width as usize * self.height as usize {
+                return Err(anyhow!("The dark reference needs {}x{} values", self.width, self.height));
+            }
+            let offset = out.len() as u32;
+            out.extend(dark.iter().flat_map(|v| v.to_le_bytes()));
+            let entries = vec![
+                IfdEntry::long(256, &[self.width]),
+                IfdEntry::long(257, &[self.height]),
+                IfdEntry::short(258, &[32]),
+                IfdEntry::short(259, &[1]),
+                IfdEntry::short(262, &[1]),
+                IfdEntry::long(273, &[offset]),
+                IfdEntry::short(277, &[1]),
+                IfdEntry::long(278, &[self.height]),
+                IfdEntry::long(279, &[dark.len() as u32 * 4]),
+                IfdEntry::short(339, &[3]),
+                IfdEntry::short(TAG_DARK_REFERENCE, &[1]),
+            ];
+            write_ifd(&mut out, ifd_link, entries);
+        }
+
+        Ok(out)
+    }
+
+    /// Appends the strips of one page's frames to `out` and returns the IFD entries
+    /// describing them
+    fn write_page(&self, out: &mut Vec<u8>, frames: &[Vec<SyntheticEvent>]) -> Vec<IfdEntry> {
+        // Strip data; each strip holds every frame's bitstream, byte-aligned
+        let mut offsets = Vec::new();
+        let mut sizes = Vec::new();
+        let mut start_row = 0;
+        while start_row < self.height {
+            let end_row = (start_row + self.rows_per_strip).min(self.height);
+            let strip: Vec<u8> = frames
+                .iter()
+                .flat_map(|events| self.encode_strip(events, start_row, end_row))
+                .collect();
+            offsets.push(out.len() as u32);
+            sizes.push(strip.len() as u32);
+            out.extend_from_slice(&strip);
+            start_row = end_row;
+        }
+
+        let mut entries = vec![
+            IfdEntry::long(256, &[self.width]),
+            IfdEntry::long(257, &[self.height]),
+            IfdEntry::short(258, &[1]),
+            IfdEntry::short(259, &[self.compression as u16]),
+            IfdEntry::short(262, &[1]),
+            IfdEntry::long(273, &offsets),
+            IfdEntry::short(277, &[1]),
+            IfdEntry::long(278, &[self.rows_per_strip]),
+            IfdEntry::long(279, &sizes),
+        ];
+        if self.frames_per_page > 1 {
+            entries.push(IfdEntry::short(TAG_FRAMES_PER_PAGE, &[frames.len() as u16]));
+        }
+        if self.compression == EerCompression::CUSTOM_CODE {
+            entries.push(IfdEntry::short(TAG_POS_SKIP_BITS, &[self.params.code_len as u16]));
+            entries.push(IfdEntry::short(TAG_HORZ_SUB_BITS, &[self.params.horz_sub_bits as u16]));
+            entries.push(IfdEntry::short(TAG_VERT_SUB_BITS, &[self.params.vert_sub_bits as u16]));
+        }
+        entries
+    }
+
+    /// Writes the file to disk
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+}
+
+/// LSB-first bit packer, the inverse of `BitStream`
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_pos: usize,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u32, n: u32) {
+        for i in 0..n {
+            if self.bit_pos.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 1 << (self.bit_pos % 8);
+            }
+            self.bit_pos += 1;
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+struct IfdEntry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    data: Vec<u8>,
+}
+
+impl IfdEntry {
+    fn short(tag: u16, values: &[u16]) -> Self {
+        IfdEntry {
+            tag,
+            field_type: 3,
+            count: values.len() as u32,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn long(tag: u16, values: &[u32]) -> Self {
+        IfdEntry {
+            tag,
+            field_type: 4,
+            count: values.len() as u32,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn bytes(tag: u16, values: &[u8]) -> Self {
+        IfdEntry {
+            tag,
+            field_type: 1,
+            count: values.len() as u32,
+            data: values.to_vec(),
+        }
+    }
+}
+
+/// Appends an IFD (and any out-of-line values) to `out`, patches the previous link to
+/// point at it and returns the position of this IFD's next-IFD link
+fn write_ifd(out: &mut Vec<u8>, prev_link: usize, mut entries: Vec<IfdEntry>) -> usize {
+    entries.sort_by_key(|e| e.tag);
+
+    // Out-of-line values go before the IFD itself
+    let mut value_offsets = Vec::with_capacity(entries.len());
+    for entry in &entries {
+        if entry.data.len() > 4 {
+            if out.len() % 2 == 1 {
+                out.push(0);
+            }
+            value_offsets.push(Some(out.len() as u32));
+            out.extend_from_slice(&entry.data);
+        } else {
+            value_offsets.push(None);
+        }
+    }
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+
+    let ifd_offset = out.len() as u32;
+    out[prev_link..prev_link + 4].copy_from_slice(&ifd_offset.to_le_bytes());
+
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (entry, value_offset) in entries.iter().zip(value_offsets) {
+        out.extend_from_slice(&entry.tag.to_le_bytes());
+        out.extend_from_slice(&entry.field_type.to_le_bytes());
+        out.extend_from_slice(&entry.count.to_le_bytes());
+        match value_offset {
+            Some(offset) => out.extend_from_slice(&offset.to_le_bytes()),
+            None => {
+                let mut inline = [0u8; 4];
+                inline[..entry.data.len()].copy_from_slice(&entry.data);
+                out.extend_from_slice(&inline);
+            }
+        }
+    }
+
+    let link = out.len();
+    out.extend_from_slice(&0u32.to_le_bytes());
+    link
+}
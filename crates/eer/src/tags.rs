@@ -0,0 +1,188 @@
+//! Lists every TIFF tag on every IFD, like `tiffinfo`, for inspecting vendor files.
+//!
+//! The `tiff` decoder has no way to enumerate the tags of a page, so the tag numbers are
+//! read from the IFD structure directly and the values are then fetched through the
+//! decoder.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use tiff::decoder::Decoder;
+use tiff::decoder::ifd::Value;
+use tiff::tags::Tag;
+
+use crate::{TAG_FRAMES_PER_PAGE, TAG_HORZ_SUB_BITS, TAG_POS_SKIP_BITS, TAG_VERT_SUB_BITS, TAG_XML_DATA};
+
+/// Longest list or byte string shown in full by [`format_value`]
+const MAX_SHOWN_VALUES: usize = 8;
+const MAX_SHOWN_TEXT: usize = 60;
+
+/// TIFF field types holding raw bytes
+const FIELD_BYTE: u16 = 1;
+const FIELD_UNDEFINED: u16 = 7;
+
+/// One tag of an IFD
+#[derive(Debug, Clone)]
+pub struct TagEntry {
+    pub code: u16,
+    pub name: String,
+    pub value: Value,
+}
+
+/// Name of a tag, including the EER-specific ones
+pub fn tag_name(code: u16) -> String {
+    match code {
+        TAG_XML_DATA => "EerXmlData".to_string(),
+        TAG_POS_SKIP_BITS => "EerPosSkipBits".to_string(),
+        TAG_HORZ_SUB_BITS => "EerHorzSubBits".to_string(),
+        TAG_VERT_SUB_BITS => "EerVertSubBits".to_string(),
+        TAG_FRAMES_PER_PAGE => "EerFramesPerPage".to_string(),
+        _ => match Tag::from_u16_exhaustive(code) {
+            Tag::Unknown(_) => format!("Unknown({})", code),
+            tag => format!("{:?}", tag),
+        },
+    }
+}
+
+/// Reads every tag of every IFD, in file order
+pub fn read_ifd_tags(path: &Path) -> Result<Vec<Vec<TagEntry>>> {
+    let codes = ifd_tag_codes(&mut BufReader::new(File::open(path)?))?;
+    let mut decoder = Decoder::new(File::open(path)?)?;
+
+    let mut ifds = Vec::with_capacity(codes.len());
+    for (index, ifd_codes) in codes.iter().enumerate() {
+        if index > 0 {
+            decoder.next_image()?;
+        }
+        let mut entries = Vec::with_capacity(ifd_codes.len());
+        for &(code, field_type) in ifd_codes {
+            if let Some(value) = decoder.find_tag(Tag::from_u16_exhaustive(code))? {
+                let value = if field_type == FIELD_BYTE || field_type == FIELD_UNDEFINED {
+                    as_bytes(value)
+                } else {
+                    value
+                };
+                entries.push(TagEntry { code, name: tag_name(code), value });
+            }
+        }
+        ifds.push(entries);
+    }
+    Ok(ifds)
+}
+
+/// The decoder widens BYTE values to integers; restores them so byte strings are
+/// recognisable
+fn as_bytes(value: Value) -> Value {
+    match value {
+        Value::List(values) => Value::List(values.into_iter().map(as_bytes).collect()),
+        Value::Unsigned(v) => Value::Byte(v as u8),
+        Value::UnsignedBig(v) => Value::Byte(v as u8),
+        other => other,
+    }
+}
+
+/// Formats a tag value for display. Byte lists that hold text are shown as text, and
+/// long lists and strings are shortened.
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::Ascii(text) => format!("{:?}", shorten(text)),
+        Value::List(values) => {
+            let bytes: Option<Vec<u8>> = values
+                .iter()
+                .map(|v| if let Value::Byte(b) = v { Some(*b) } else { None })
+                .collect();
+            if let Some(text) = bytes.and_then(|b| String::from_utf8(b).ok()) {
+                return format!("{} bytes: {:?}", values.len(), shorten(&text));
+            }
+            let shown: Vec<String> = values.iter().take(MAX_SHOWN_VALUES).map(format_value).collect();
+            if values.len() > MAX_SHOWN_VALUES {
+                format!("[{}, ...] ({} values)", shown.join(", "), values.len())
+            } else {
+                format!("[{}]", shown.join(", "))
+            }
+        }
+        Value::Byte(v) => v.to_string(),
+        Value::Short(v) => v.to_string(),
+        Value::Signed(v) => v.to_string(),
+        Value::SignedBig(v) => v.to_string(),
+        Value::Unsigned(v) => v.to_string(),
+        Value::UnsignedBig(v) => v.to_string(),
+        Value::Float(v) => v.to_string(),
+        Value::Double(v) => v.to_string(),
+        Value::Rational(n, d) => format!("{}/{}", n, d),
+        Value::RationalBig(n, d) => format!("{}/{}", n, d),
+        Value::SRational(n, d) => format!("{}/{}", n, d),
+        Value::SRationalBig(n, d) => format!("{}/{}", n, d),
+        Value::Ifd(offset) => format!("IFD at {}", offset),
+        Value::IfdBig(offset) => format!("IFD at {}", offset),
+        other => format!("{:?}", other),
+    }
+}
+
+fn shorten(text: &str) -> String {
+    let text = text.trim_end_matches('\0');
+    match text.char_indices().nth(MAX_SHOWN_TEXT) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Walks the IFD chain of a classic or BigTIFF file, returning the tag numbers and field
+/// types of each IFD
+fn ifd_tag_codes<R: Read + Seek>(reader: &mut R) -> Result<Vec<Vec<(u16, u16)>>> {
+    let mut header = [0u8; 4];
+    reader.read_exact(&mut header)?;
+    let big_endian = match &header[..2] {
+        b"II" => false,
+        b"MM" => true,
+        _ => return Err(anyhow!("Not a TIFF file")),
+    };
+    // Reads an unsigned integer of any width in the file's byte order
+    let read_uint = |bytes: &[u8]| {
+        bytes.iter().enumerate().fold(0u64, |acc, (i, &b)| {
+            if big_endian { (acc << 8) | b as u64 } else { acc | (b as u64) << (8 * i) }
+        })
+    };
+
+    let bigtiff = match read_uint(&header[2..]) {
+        42 => false,
+        43 => true,
+        _ => return Err(anyhow!("Not a TIFF file")),
+    };
+    // Offsets are 4 bytes in classic TIFF, 8 in BigTIFF (after 4 bytes of offset size/padding)
+    let (offset_size, count_size, entry_size) = if bigtiff { (8, 8, 20) } else { (4, 2, 12) };
+    if bigtiff {
+        reader.seek(SeekFrom::Current(4))?;
+    }
+
+    let mut buf = vec![0u8; offset_size];
+    reader.read_exact(&mut buf)?;
+    let mut next = read_uint(&buf);
+    let mut seen = HashSet::new();
+    let mut ifds = Vec::new();
+
+    while next != 0 {
+        if !seen.insert(next) {
+            return Err(anyhow!("IFD chain loops back to offset {}", next));
+        }
+        reader.seek(SeekFrom::Start(next))?;
+        let mut count_buf = vec![0u8; count_size];
+        reader.read_exact(&mut count_buf)?;
+        let count = read_uint(&count_buf) as usize;
+
+        let mut entries = vec![0u8; count * entry_size];
+        reader.read_exact(&mut entries)?;
+        ifds.push(
+            entries
+                .chunks_exact(entry_size)
+                .map(|e| (read_uint(&e[..2]) as u16, read_uint(&e[2..4]) as u16))
+                .collect(),
+        );
+
+        reader.read_exact(&mut buf)?;
+        next = read_uint(&buf);
+    }
+    Ok(ifds)
+}
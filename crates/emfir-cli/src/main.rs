@@ -1,58 +1,479 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use clap::{Parser, Subcommand};
-use mrc::MrcFile;
-use eer::{show_header_info, generate_thumbnail};
-use std::path::PathBuf;
+use mrc::{Contrast, DownsampleMode, MrcFile, ProjectionMode};
+use eer::{
+    dose_stats, encode_thumbnail, has_eer_frames, header_report, generate_series_thumbnail, generate_thumbnail,
+    read_frame_strips, read_raw_xml, Crop, DecodeOptions, Upsampling,
+};
+use eer::export::{save_npy, Endianness};
+use eer::gain::{GainReference, DEFAULT_DEFECT_THRESHOLD};
+use eer::montage::generate_montage;
+use eer::tags::{format_value, read_ifd_tags};
+use schemars::{schema_for, JsonSchema};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::process;
 
 #[derive(Parser)]
 #[command(name = "emfir-cli")]
 #[command(about = "CLI for handling MRC and EER data", long_about = None)]
+#[command(subcommand_negates_reqs = true)]
 struct Cli {
-    /// Path to the input file
-    #[arg(short, long)]
-    file: PathBuf,
+    #[command(subcommand)]
+    subcommand: Option<Subcommands>,
 
-    /// Command: either "header" or "thumbnail"
-    #[arg(short, long)]
-    command: String,
+    /// Path to the input file. Several EER files (e.g. `-f part*.eer`) are summed as one
+    /// acquisition by the thumbnail command.
+    #[arg(short, long = "file", value_name = "FILE", num_args = 1.., required = true)]
+    files: Vec<PathBuf>,
+
+    /// Command: "header", "thumbnail" or "stats" (EER only)
+    #[arg(short, long, required_unless_present_any = ["dump_strips", "tags", "verify", "variance", "print_schema"])]
+    command: Option<String>,
     
-    /// Output path for thumbnail (required for thumbnail command)
+    /// Output path for thumbnail (required for thumbnail command); "-" writes the PNG to stdout
     #[arg(short, long)]
     output: Option<PathBuf>,
     
-    /// Downsample factor for thumbnail generation (process every Nth frame)
-    #[arg(short, long, default_value = "10")]
-    downsample: u32,
+    /// Deprecated: frame skip for EER, spatial downsampling for MRC; use --frame-skip or
+    /// --spatial-downsample instead
+    #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    downsample: Option<u32>,
+
+    /// Process every Nth EER frame [default: 10]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    frame_skip: Option<u32>,
+
+    /// Spatial downsampling: keep every Nth pixel of MRC thumbnails [default: 10], or sum
+    /// NxN pixel blocks of the EER sum [default: 1]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    spatial_downsample: Option<u32>,
+
+    /// EER super-resolution factor: "auto" for the file's native resolution, or 1, 2, 4
+    #[arg(short, long, default_value = "1")]
+    upsampling: Upsampling,
+
+    /// Subtract the dark reference embedded in the EER file, if present
+    #[arg(long)]
+    subtract_dark: bool,
+
+    /// Detect 7- vs 8-bit EER codes from the data instead of trusting the compression tag
+    #[arg(long)]
+    auto_codelen: bool,
+
+    /// Include every key/value from the EER XML metadata in the header JSON (header command)
+    #[arg(long)]
+    metadata: bool,
+
+    /// Write the EER XML metadata exactly as stored in the file to PATH (header command)
+    #[arg(long, value_name = "PATH")]
+    extract_xml: Option<PathBuf>,
+
+    /// Render a Z projection of an MRC volume instead of the first slice: mean, max or sum.
+    /// EER files ignore this.
+    #[arg(long)]
+    projection: Option<ProjectionMode>,
+
+    /// Render this Z slice (from 0) of an MRC volume instead of the first. EER files
+    /// ignore this.
+    #[arg(long, value_name = "N", conflicts_with = "projection")]
+    slice: Option<u32>,
+
+    /// Thumbnail contrast: "minmax" stretches the full range, "auto" clips histogram tails,
+    /// "percentile:LOW,HIGH" (MRC only) stretches between two percentiles, e.g. percentile:1,99,
+    /// "header" (MRC only) stretches over the dmin/dmax range recorded in the header
+    #[arg(long, default_value = "minmax")]
+    contrast: Contrast,
+
+    /// MRC thumbnail downsampling: "nearest" keeps every Nth pixel, "mean" averages NxN blocks
+    #[arg(long, default_value = "nearest")]
+    downsample_mode: DownsampleMode,
+
+    /// Sum at most this many EER frames (after --frame-skip), for a quick preview
+    #[arg(long, value_name = "N")]
+    max_frames: Option<u32>,
+
+    /// Multiply the EER sum by this gain reference MRC (first section), on the sensor or
+    /// the decoded grid
+    #[arg(long, value_name = "PATH")]
+    gain: Option<PathBuf>,
+
+    /// Gain values at or below this mark defective pixels, which are zeroed in the
+    /// gain-corrected sum; Thermo gain references use 0
+    #[arg(long, value_name = "VALUE", default_value_t = DEFAULT_DEFECT_THRESHOLD, requires = "gain")]
+    defect_threshold: f32,
+
+    /// Keep only this region of the EER sum, in pixels of the upsampled image before
+    /// --spatial-downsample
+    #[arg(long, value_name = "X0,Y0,W,H")]
+    crop: Option<Crop>,
+
+    /// Tile evenly spaced EER frames into a ROWS,COLS grid instead of summing them
+    #[arg(long, value_name = "ROWS,COLS", value_parser = parse_grid)]
+    montage: Option<(u32, u32)>,
+
+    /// Mark each montage tile with its frame index
+    #[arg(long, requires = "montage")]
+    montage_labels: bool,
+
+    /// EER frame indices to leave out of the sum, e.g. 3,7,12
+    #[arg(long, value_delimiter = ',')]
+    exclude: Vec<u32>,
+
+    /// Write the per-pixel variance across EER frames to this .npy file (float32)
+    #[arg(long, value_name = "PATH")]
+    variance: Option<PathBuf>,
+
+    /// Working memory budget in MiB; forces section-by-section MRC projections and
+    /// rejects outputs that cannot fit
+    #[arg(long, value_name = "MIB")]
+    limit_memory: Option<usize>,
+
+    /// Read MRC voxel data from this byte offset instead of the one the header implies,
+    /// for files with prepended bytes; a wrong value gives garbage images
+    #[arg(long, value_name = "BYTES")]
+    data_offset: Option<u64>,
+
+    /// Accept legacy MRC files without the "MAP " stamp at offset 208, with a warning
+    #[arg(long, global = true)]
+    permissive: bool,
+
+    /// Add the thumbnail to the header or stats JSON as a base64 PNG data URI under "preview"
+    #[arg(long)]
+    preview: bool,
+
+    /// Print the JSON Schema of the header JSON (or of the stats JSON with -c stats) for
+    /// the format of --file and exit; the file itself is not read
+    #[arg(long)]
+    print_schema: bool,
+
+    /// List MRC header fields that deviate from the canonical values
+    #[arg(long)]
+    verify: bool,
+
+    /// List every TIFF tag on every IFD of an EER file
+    #[arg(long)]
+    tags: bool,
+
+    /// Write the raw compressed strip bytes of EER frame N to --output and list the strips
+    #[arg(long, value_name = "N")]
+    dump_strips: Option<u32>,
+}
+
+#[derive(Subcommand)]
+enum Subcommands {
+    /// Print the header fields that differ between two files as JSON; an MRC can be
+    /// compared with an EER
+    Compare {
+        left: PathBuf,
+        right: PathBuf,
+    },
+}
+
+/// Parses a "ROWS,COLS" grid size
+fn parse_grid(s: &str) -> Result<(u32, u32), String> {
+    let (rows, cols) = s.split_once(',').ok_or("expected ROWS,COLS, e.g. 3,4")?;
+    let parse = |n: &str| n.trim().parse::<u32>().map_err(|e| format!("{}: {}", n, e));
+    Ok((parse(rows)?, parse(cols)?))
+}
+
+/// JSON output with an optional inline thumbnail
+#[derive(Serialize, JsonSchema)]
+struct WithPreview<'a, T: Serialize> {
+    #[serde(flatten)]
+    data: &'a T,
+    /// PNG thumbnail as a `data:image/png;base64,...` URI, with --preview
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preview: Option<String>,
+}
+
+/// Prints `data` as pretty JSON, adding `preview` PNG bytes (if any) as a data URI
+fn print_json<T: Serialize>(data: &T, preview: Option<Vec<u8>>) {
+    let output = WithPreview {
+        data,
+        preview: preview.map(|png| format!("data:image/png;base64,{}", BASE64.encode(png))),
+    };
+    match serde_json::to_string_pretty(&output) {
+        Ok(json) => println!("{}", json),
+        Err(e) => {
+            eprintln!("Error serializing to JSON: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Draws a one-line progress bar on stderr
+fn print_progress(done: usize, total: usize) {
+    const WIDTH: usize = 40;
+    let filled = (done * WIDTH).checked_div(total).unwrap_or(WIDTH);
+    eprint!("\r[{}{}] {}/{} slices", "#".repeat(filled), " ".repeat(WIDTH - filled), done, total);
+    if done == total {
+        eprintln!();
+    }
+}
+
+/// Writes the raw strips of one EER frame to `output` and lists them on stderr, leaving
+/// stdout to the bytes when `output` is `-`
+fn dump_strips(file: &Path, frame: u32, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let (strips, data) = read_frame_strips(file, frame)?;
+    for (i, strip) in strips.iter().enumerate() {
+        eprintln!("strip {}: offset {} size {}", i, strip.offset, strip.size);
+    }
+    if output == Path::new("-") {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&data)?;
+        stdout.flush()?;
+    } else {
+        std::fs::write(output, &data)?;
+    }
+    eprintln!("Wrote {} bytes of frame {} to {:?}", data.len(), frame, output);
+    Ok(())
+}
+
+/// Writes the per-pixel variance of every `skip`-th frame and reports the mean
+/// variance/mean ratio
+fn write_variance(file: &Path, output: &Path, skip: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let (mean, variance) = eer::frame_stats(file, Some(skip))?;
+    save_npy(&variance, output, Endianness::Little)?;
+
+    let ratios: Vec<f32> = mean.iter().zip(&variance).filter(|(&m, _)| m > 0.0).map(|(m, v)| v / m).collect();
+    if !ratios.is_empty() {
+        eprintln!("Mean variance/mean ratio: {:.4}", ratios.iter().sum::<f32>() / ratios.len() as f32);
+    }
+    eprintln!("Variance map written to {:?}", output);
+    Ok(())
+}
+
+/// Opens an MRC file, without requiring the "MAP " stamp when `permissive`
+fn open_mrc(path: &Path, permissive: bool) -> Result<MrcFile, mrc::MrcError> {
+    match permissive {
+        true => MrcFile::open_permissive(&path.to_string_lossy()),
+        false => MrcFile::open(&path.to_string_lossy()),
+    }
+}
+
+/// Reads the first section of a gain reference MRC
+fn load_gain(path: &Path, defect_threshold: f32, permissive: bool) -> Result<GainReference, Box<dyn std::error::Error>> {
+    let mrc = open_mrc(path, permissive)?;
+    let section = mrc.read_slices(0..1)?;
+    let (_, height, width) = section.dim();
+    let gain = GainReference::new(section.into_shape_with_order((height, width))?, defect_threshold)
+        .with_source(path.display().to_string());
+    eprintln!("Gain reference {:?}: {} defective pixels", path, gain.defect_count());
+    Ok(gain)
+}
+
+/// Prints the tags of every IFD, one per line
+fn print_tags(file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    for (index, ifd) in read_ifd_tags(file)?.iter().enumerate() {
+        println!("IFD {}", index);
+        for tag in ifd {
+            println!("  {} ({}): {}", tag.name, tag.code, format_value(&tag.value));
+        }
+    }
+    Ok(())
+}
+
+/// A header field that differs between the compared files; `null` on the side that
+/// lacks it
+#[derive(Serialize)]
+struct FieldDiff {
+    field: String,
+    left: Value,
+    right: Value,
+}
+
+#[derive(Serialize)]
+struct HeaderDiff<'a> {
+    left: &'a Path,
+    right: &'a Path,
+    matching_fields: usize,
+    differences: Vec<FieldDiff>,
+}
+
+/// The file formats the CLI reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Mrc,
+    Eer,
+}
+
+/// TIFF and BigTIFF signatures, little- and big-endian
+const TIFF_MAGIC: [&[u8; 4]; 4] = [b"II*\0", b"MM\0*", b"II+\0", b"MM\0+"];
+
+/// Identifies a file by its content: the "MAP " stamp at offset 208 of an MRC header,
+/// or a TIFF with EER-compressed frames. Content that doesn't settle it (older MRCs
+/// without the stamp, integrating-mode EERs, unreadable files) falls back to the
+/// extension.
+fn detect_format(path: &Path) -> Result<Format, Box<dyn std::error::Error>> {
+    let mut head = Vec::with_capacity(212);
+    if let Ok(file) = File::open(path) {
+        file.take(212).read_to_end(&mut head)?;
+    }
+    if head.get(208..212) == Some(b"MAP ") {
+        return Ok(Format::Mrc);
+    }
+    if TIFF_MAGIC.iter().any(|magic| head.starts_with(*magic)) && has_eer_frames(path).unwrap_or(false) {
+        return Ok(Format::Eer);
+    }
+    match path.extension().and_then(|ext| ext.to_str()).unwrap_or("") {
+        "mrc" => Ok(Format::Mrc),
+        "eer" => Ok(Format::Eer),
+        _ => Err(format!("Can't identify {:?} as an MRC or EER file", path).into()),
+    }
+}
+
+/// Header fields of an MRC or EER file keyed by dotted path, e.g. "density.min". The
+/// EER image data is lifted to the top level so its fields line up with an MRC's.
+fn header_fields(path: &Path, permissive: bool) -> Result<BTreeMap<String, Value>, Box<dyn std::error::Error>> {
+    let value = match detect_format(path)? {
+        Format::Mrc => serde_json::to_value(open_mrc(path, permissive)?.get_image_data())?,
+        Format::Eer => {
+            let mut report = serde_json::to_value(header_report(path)?)?;
+            if let Some(Value::Object(image_data)) = report.as_object_mut().and_then(|r| r.remove("image_data")) {
+                report.as_object_mut().unwrap().extend(image_data);
+            }
+            report
+        }
+    };
+    let mut fields = BTreeMap::new();
+    flatten_json(String::new(), value, &mut fields);
+    Ok(fields)
+}
+
+fn flatten_json(path: String, value: Value, fields: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = if path.is_empty() { key } else { format!("{}.{}", path, key) };
+                flatten_json(path, value, fields);
+            }
+        }
+        value => {
+            fields.insert(path, value);
+        }
+    }
+}
+
+/// Prints the header fields that differ between two files as JSON
+fn compare_headers(left: &Path, right: &Path, permissive: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut left_fields = header_fields(left, permissive)?;
+    let mut right_fields = header_fields(right, permissive)?;
+    let names: BTreeSet<String> = left_fields.keys().chain(right_fields.keys()).cloned().collect();
+
+    let mut matching_fields = 0;
+    let mut differences = Vec::new();
+    for field in names {
+        let left = left_fields.remove(&field).unwrap_or(Value::Null);
+        let right = right_fields.remove(&field).unwrap_or(Value::Null);
+        if left == right {
+            matching_fields += 1;
+        } else {
+            differences.push(FieldDiff { field, left, right });
+        }
+    }
+    eprintln!("{} fields differ, {} match", differences.len(), matching_fields);
+    print_json(&HeaderDiff { left, right, matching_fields, differences }, None);
+    Ok(())
 }
 
 fn main() {
     let cli = Cli::parse();
+    if let Some(Subcommands::Compare { left, right }) = &cli.subcommand {
+        if let Err(e) = compare_headers(left, right, cli.permissive) {
+            eprintln!("Error comparing headers: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    let command = cli.command.clone().unwrap_or_default();
+    let memory_limit = cli.limit_memory.map(|mib| mib * 1024 * 1024);
+    if cli.downsample.is_some() {
+        eprintln!(
+            "Warning: --downsample is deprecated; use --frame-skip for EER frames or --spatial-downsample for pixels"
+        );
+    }
 
-    let extension = cli.file
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("");
+    let file = cli.files[0].clone();
+    if cli.files.len() > 1 {
+        let is_eer = cli.files.iter().all(|path| matches!(detect_format(path), Ok(Format::Eer)));
+        if !is_eer || command != "thumbnail" || cli.montage.is_some() {
+            eprintln!("Several input files are only supported for EER thumbnails");
+            process::exit(1);
+        }
+    }
 
-    match extension {
-        "mrc" => {
-            match MrcFile::open(&cli.file.to_string_lossy()) {
-                Ok(mrc) => {
+    let format = match detect_format(&file) {
+        Ok(format) => format,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
 
-                    match cli.command.as_str() {
+    if cli.print_schema {
+        let schema = match (format, command.as_str()) {
+            (Format::Mrc, _) => schema_for!(WithPreview<'static, mrc::ImageData>),
+            (Format::Eer, "stats") => schema_for!(WithPreview<'static, eer::DoseStats>),
+            (Format::Eer, _) => schema_for!(WithPreview<'static, eer::HeaderReport>),
+        };
+        print_json(&schema, None);
+        return;
+    }
+
+    match format {
+        Format::Mrc => {
+            match open_mrc(&file, cli.permissive) {
+                Ok(mut mrc) => {
+                    mrc.set_memory_limit(memory_limit);
+                    if let Err(e) = mrc.set_data_offset_override(cli.data_offset) {
+                        eprintln!("Error setting the data offset: {}", e);
+                        process::exit(1);
+                    }
+                    let downsample = cli.spatial_downsample.or(cli.downsample).unwrap_or(10);
+                    if cli.verify {
+                        let fields = mrc.header().nonstandard_fields();
+                        if fields.is_empty() {
+                            println!("No nonstandard header fields");
+                        }
+                        for (name, value) in fields {
+                            println!("{}: {}", name, value);
+                        }
+                        return;
+                    }
+
+                    match command.as_str() {
                         "header" => {
-                            let image_data = mrc.get_image_data();
-                            match serde_json::to_string_pretty(image_data) {
-                                Ok(json) => println!("{}", json),
+                            let preview = cli.preview.then(|| {
+                                mrc.render_thumbnail(downsample, cli.contrast).and_then(|img| mrc::encode_png(&img))
+                            });
+                            match preview.transpose() {
+                                Ok(preview) => print_json(mrc.get_image_data(), preview),
                                 Err(e) => {
-                                    eprintln!("Error serializing to JSON: {}", e);
+                                    eprintln!("Error generating preview: {}", e);
                                     process::exit(1);
                                 }
                             }
                         },
                         "thumbnail" => {
                             if let Some(output_path) = &cli.output {
-                                match mrc.save_thumbnail(&output_path.to_string_lossy(), cli.downsample) {
-                                    Ok(_) => println!("Thumbnail generated at {:?}", output_path),
+                                let output = output_path.to_string_lossy();
+                                let result = match cli.projection {
+                                    Some(mode) => mrc.save_projection_with_progress(
+                                        &output, mode, downsample, cli.contrast, cli.downsample_mode,
+                                        Some(&print_progress),
+                                    ),
+                                    None => mrc.save_slice_with_options(
+                                        &output, cli.slice.unwrap_or(0), downsample, cli.contrast, cli.downsample_mode,
+                                    ),
+                                };
+                                match result {
+                                    Ok(_) => eprintln!("Thumbnail generated at {:?}", output_path),
                                     Err(e) => {
                                         eprintln!("Error generating thumbnail: {}", e);
                                         process::exit(1);
@@ -64,7 +485,8 @@ fn main() {
                             }
                         },
                         _ => {
-                            eprintln!("Unknown command: {}. Use 'header' or 'thumbnail'.", cli.command);
+                            eprintln!("Unknown command: {}. Use 'header' or 'thumbnail'.", command);
+                            process::exit(1);
                         }
                     }
                 }
@@ -74,15 +496,121 @@ fn main() {
                 }
             }
         }
-        "eer" => {
-             match cli.command.as_str() {
+        Format::Eer => {
+            let frame_skip = cli.frame_skip.or(cli.downsample).unwrap_or(10);
+            let gain = match cli.gain.as_deref().map(|path| load_gain(path, cli.defect_threshold, cli.permissive)).transpose() {
+                Ok(gain) => gain,
+                Err(e) => {
+                    eprintln!("Error reading gain reference: {}", e);
+                    process::exit(1);
+                }
+            };
+            let options = DecodeOptions {
+                skip_frames: Some(frame_skip),
+                upsampling: cli.upsampling,
+                subtract_dark: cli.subtract_dark,
+                auto_code_len: cli.auto_codelen,
+                auto_contrast: cli.contrast == Contrast::Auto,
+                exclude: cli.exclude.iter().copied().collect(),
+                max_frames: cli.max_frames,
+                memory_limit,
+                bin_factor: cli.spatial_downsample.unwrap_or(1),
+                crop: cli.crop,
+                cancel: None,
+                partial_on_cancel: false,
+                gain,
+            };
+            let preview = || match cli.preview.then(|| encode_thumbnail(&file, &options)).transpose() {
+                Ok(preview) => preview,
+                Err(e) => {
+                    eprintln!("Error generating preview: {}", e);
+                    process::exit(1);
+                }
+            };
+            if cli.tags {
+                if let Err(e) = print_tags(&file) {
+                    eprintln!("Error reading TIFF tags: {}", e);
+                    process::exit(1);
+                }
+                return;
+            }
+            if let Some(output) = &cli.variance {
+                if let Err(e) = write_variance(&file, output, frame_skip) {
+                    eprintln!("Error computing frame variance: {}", e);
+                    process::exit(1);
+                }
+                return;
+            }
+            if let Some(frame) = cli.dump_strips {
+                let Some(output_path) = &cli.output else {
+                    eprintln!("Output path is required for --dump-strips. Use --output");
+                    process::exit(1);
+                };
+                if let Err(e) = dump_strips(&file, frame, output_path) {
+                    eprintln!("Error dumping strips: {}", e);
+                    process::exit(1);
+                }
+                return;
+            }
+             match command.as_str() {
                 "header" => {
-                    show_header_info(&cli.file);
+                    if let Some(xml_path) = &cli.extract_xml {
+                        match read_raw_xml(&file) {
+                            Ok(Some(xml)) => {
+                                if let Err(e) = std::fs::write(xml_path, xml) {
+                                    eprintln!("Error writing XML metadata to {:?}: {}", xml_path, e);
+                                    process::exit(1);
+                                }
+                                eprintln!("XML metadata written to {:?}", xml_path);
+                            }
+                            Ok(None) => {
+                                eprintln!("{:?} has no XML metadata tag to extract", file);
+                                process::exit(1);
+                            }
+                            Err(e) => {
+                                eprintln!("Error reading XML metadata: {}", e);
+                                process::exit(1);
+                            }
+                        }
+                    }
+                    match header_report(&file) {
+                        Ok(report) => {
+                            for warning in &report.warnings {
+                                eprintln!("Warning: {}", warning);
+                            }
+                            // Sizes and spacing of the images decoded with these options
+                            let mut report = report.for_output(&options);
+                            if !cli.metadata {
+                                report.metadata.clear();
+                            }
+                            // Provenance of the preview, when one was decoded
+                            let preview = preview();
+                            report.decode_params = preview.as_ref().map(|(_, params)| params.clone());
+                            print_json(&report, preview.map(|(png, _)| png));
+                        }
+                        Err(e) => {
+                            eprintln!("Error reading EER header: {}", e);
+                            process::exit(1);
+                        }
+                    }
                 },
                 "thumbnail" => {
                     if let Some(output_path) = &cli.output {
-                        match generate_thumbnail(&cli.file, output_path, Some(cli.downsample)) {
-                            Ok(_) => println!("Thumbnail generated at {:?}", output_path),
+                        if !cli.exclude.is_empty() {
+                            eprintln!("Excluding frames {:?} from the sum", cli.exclude);
+                        }
+                        let result = match cli.montage {
+                            Some((rows, cols)) => {
+                                generate_montage(&file, output_path, rows, cols, &options, cli.montage_labels)
+                            }
+                            None if cli.files.len() > 1 => {
+                                let parts: Vec<&Path> = cli.files.iter().map(PathBuf::as_path).collect();
+                                generate_series_thumbnail(&parts, output_path, &options)
+                            }
+                            None => generate_thumbnail(&file, output_path, &options),
+                        };
+                        match result {
+                            Ok(_) => eprintln!("Thumbnail generated at {:?}", output_path),
                             Err(e) => {
                                 eprintln!("Error generating thumbnail: {}", e);
                                 process::exit(1);
@@ -93,14 +621,30 @@ fn main() {
                         process::exit(1);
                     }
                 },
+                "stats" => {
+                    match dose_stats(&file) {
+                        Ok(stats) => {
+                            if stats.dose_mismatch() {
+                                eprintln!(
+                                    "Warning: measured dose {:.4} e/pixel differs from the reported {:.4} e/pixel",
+                                    stats.measured_dose,
+                                    stats.reported_dose.unwrap_or_default()
+                                );
+                            }
+                            print_json(&stats, preview().map(|(png, _)| png));
+                        }
+                        Err(e) => {
+                            eprintln!("Error computing EER stats: {}", e);
+                            process::exit(1);
+                        }
+                    }
+                },
                 _ => {
-                    eprintln!("Unknown command: {}. Use 'header' or 'thumbnail'.", cli.command);
+                    eprintln!("Unknown command: {}. Use 'header', 'thumbnail' or 'stats'.", command);
+                    process::exit(1);
                 }
             }
         }
-        _ => {
-            eprintln!("Can't handle file with this extension: {}", extension);
-        }
     }
 
 }
@@ -0,0 +1,189 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn emfir_cli() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_emfir-cli"))
+}
+
+fn header(path: &Path) -> Output {
+    emfir_cli().arg("--file").arg(path).args(["--command", "header"]).output().unwrap()
+}
+
+/// A 4x4x1 float MRC with 1 A pixels
+fn mrc_bytes() -> Vec<u8> {
+    mrc_volume_bytes(&(0..16).map(|v| v as f32).collect::<Vec<_>>())
+}
+
+/// A float MRC of 4x4 sections holding `values`, with 1 A pixels
+fn mrc_volume_bytes(values: &[f32]) -> Vec<u8> {
+    let nz = values.len() / 16;
+    let mut bytes = vec![0u8; 1024];
+    for (i, v) in [4i32, 4, nz as i32, 2].iter().enumerate() {
+        bytes[i * 4..i * 4 + 4].copy_from_slice(&v.to_le_bytes());
+    }
+    for (i, v) in [4.0f32, 4.0, nz as f32, 90.0, 90.0, 90.0].iter().enumerate() {
+        bytes[40 + i * 4..44 + i * 4].copy_from_slice(&v.to_le_bytes());
+    }
+    for (i, v) in [1i32, 2, 3].iter().enumerate() {
+        bytes[64 + i * 4..68 + i * 4].copy_from_slice(&v.to_le_bytes());
+    }
+    bytes[208..212].copy_from_slice(b"MAP ");
+    bytes[212..214].copy_from_slice(&[0x44, 0x44]);
+    bytes.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+    bytes
+}
+
+/// Renders a thumbnail of `mrc` with `args`, returning its grey levels
+fn mrc_thumbnail(name: &str, mrc: &[u8], args: &[&str]) -> Result<Vec<u8>, String> {
+    let input = std::env::temp_dir().join(format!("{}.mrc", name));
+    let output = std::env::temp_dir().join(format!("{}.png", name));
+    std::fs::write(&input, mrc).unwrap();
+    let result = emfir_cli()
+        .arg("--file").arg(&input)
+        .arg("--output").arg(&output)
+        .args(["--command", "thumbnail", "--spatial-downsample", "1"])
+        .args(args)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&input).unwrap();
+    if !result.status.success() {
+        return Err(String::from_utf8_lossy(&result.stderr).into_owned());
+    }
+    let gray = image::open(&output).unwrap().to_luma8().into_raw();
+    std::fs::remove_file(&output).unwrap();
+    Ok(gray)
+}
+
+#[test]
+fn test_header_of_non_tiff_eer_fails() {
+    let path = std::env::temp_dir().join("emfir_cli_not_a_tiff.eer");
+    std::fs::write(&path, b"not a TIFF file").unwrap();
+    let output = header(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Error reading EER header"));
+}
+
+#[test]
+fn test_format_detected_from_content() {
+    let mrc = std::env::temp_dir().join("emfir_cli_renamed_mrc.map");
+    std::fs::write(&mrc, mrc_bytes()).unwrap();
+    let mut eer = eer::synthetic::SyntheticEer::new(16, 16);
+    eer.fill_random(2, 10, 1);
+    let eer_path = std::env::temp_dir().join("emfir_cli_renamed_eer");
+    eer.write(&eer_path).unwrap();
+    let unknown = std::env::temp_dir().join("emfir_cli_unknown.dat");
+    std::fs::write(&unknown, b"neither format").unwrap();
+
+    let (mrc_output, eer_output, unknown_output) = (header(&mrc), header(&eer_path), header(&unknown));
+    for path in [&mrc, &eer_path, &unknown] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    assert!(mrc_output.status.success(), "{}", String::from_utf8_lossy(&mrc_output.stderr));
+    assert!(String::from_utf8_lossy(&mrc_output.stdout).contains("\"size_x\": 4"));
+    assert!(eer_output.status.success(), "{}", String::from_utf8_lossy(&eer_output.stderr));
+    assert!(String::from_utf8_lossy(&eer_output.stdout).contains("\"image_data\""));
+    assert!(!unknown_output.status.success());
+    assert!(String::from_utf8_lossy(&unknown_output.stderr).contains("as an MRC or EER file"));
+}
+
+#[test]
+fn test_mrc_thumbnail_slice_and_projection() {
+    // Three sections, each bright in a different pixel of the first row
+    let mut values = vec![0.0f32; 48];
+    for z in 0..3 {
+        values[z * 16 + z] = 10.0;
+    }
+    let mrc = mrc_volume_bytes(&values);
+
+    let max = mrc_thumbnail("emfir_cli_projection_max", &mrc, &["--projection", "max"]).unwrap();
+    assert_eq!(max[..4], [255, 255, 255, 0]);
+    assert!(max[4..].iter().all(|&g| g == 0));
+    let slice = mrc_thumbnail("emfir_cli_slice", &mrc, &["--slice", "2"]).unwrap();
+    assert_eq!(slice[..4], [0, 0, 255, 0]);
+    let first = mrc_thumbnail("emfir_cli_first_slice", &mrc, &[]).unwrap();
+    assert_eq!(first[..4], [255, 0, 0, 0]);
+
+    let both = mrc_thumbnail("emfir_cli_slice_and_projection", &mrc, &["--slice", "1", "--projection", "max"]);
+    assert!(both.unwrap_err().contains("cannot be used with"));
+    let past_end = mrc_thumbnail("emfir_cli_slice_past_end", &mrc, &["--slice", "3"]);
+    assert!(past_end.unwrap_err().contains("outside 0..3"));
+    for flag in ["--spatial-downsample", "--downsample", "--frame-skip"] {
+        let zero = emfir_cli().args(["--file", "unused.mrc", "--command", "thumbnail", flag, "0"]).output().unwrap();
+        assert!(!zero.status.success());
+        assert!(String::from_utf8_lossy(&zero.stderr).contains("is not in 1.."), "{}", flag);
+    }
+}
+
+#[test]
+fn test_eer_header_metadata() {
+    let mut eer = eer::synthetic::SyntheticEer::new(16, 16);
+    eer.fill_random(2, 10, 1);
+    eer.xml = Some(concat!(
+        "<metadata><item name=\"numberOfFrames\">2</item>",
+        "<item name=\"sensorPixelSize.width\">1e-10</item><item name=\"sensorPixelSize.height\">1e-10</item>",
+        "<item name=\"gainReference\">gain.mrc</item></metadata>",
+    ).to_string());
+    let path = std::env::temp_dir().join("emfir_cli_metadata.eer");
+    eer.write(&path).unwrap();
+    let run = |extra: &[&str]| {
+        let output = emfir_cli().arg("--file").arg(&path).args(["--command", "header"]).args(extra).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+    let (full, plain) = (run(&["--metadata"]), run(&[]));
+    let previewed = run(&["--preview", "--frame-skip", "1"]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(full["metadata"]["numberOfFrames"], "2");
+    assert_eq!(full["metadata"]["gainReference"], "gain.mrc");
+    // The typed pixel size stays alongside the raw items
+    assert!(full["image_data"]["voxel_spacing_x"].is_number());
+    assert!(plain.get("metadata").is_none());
+    // Decode settings are only recorded for a preview that was actually decoded
+    assert!(plain.get("decode_params").is_none());
+    assert_eq!(previewed["decode_params"]["frames_summed"], 2);
+    assert_eq!(previewed["decode_params"]["skip_frames"], 1);
+}
+
+#[test]
+fn test_dump_strips_to_stdout() {
+    let mut eer = eer::synthetic::SyntheticEer::new(16, 16);
+    eer.rows_per_strip = 4;
+    eer.fill_random(2, 20, 3);
+    let path = std::env::temp_dir().join("emfir_cli_dump_strips.eer");
+    eer.write(&path).unwrap();
+    let output = emfir_cli().arg("--file").arg(&path).args(["--dump-strips", "1", "--output", "-"]).output().unwrap();
+    let (_, expected) = eer::read_frame_strips(&path, 1).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // Only the strip bytes go to stdout; the strip list goes to stderr
+    assert_eq!(output.stdout, expected);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("strip 3: offset"));
+}
+
+#[test]
+fn test_compare_headers() {
+    let left = std::env::temp_dir().join("emfir_cli_compare_left.mrc");
+    let right = std::env::temp_dir().join("emfir_cli_compare_right.mrc");
+    std::fs::write(&left, mrc_bytes()).unwrap();
+    std::fs::write(&right, mrc_volume_bytes(&[1.0; 32])).unwrap();
+    let output = emfir_cli().arg("compare").arg(&left).arg(&right).output().unwrap();
+    // The old flag form is gone
+    let flags =
+        emfir_cli().arg("--file").arg(&left).args(["--command", "compare", "--other"]).arg(&right).output().unwrap();
+    std::fs::remove_file(&left).unwrap();
+    std::fs::remove_file(&right).unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let diff: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let differences = diff["differences"].as_array().unwrap();
+    let size_z = differences.iter().find(|d| d["field"] == "size_z").unwrap();
+    assert_eq!((&size_z["left"], &size_z["right"]), (&1.into(), &2.into()));
+    assert!(differences.iter().all(|d| d["field"] != "size_x"));
+    assert!(!flags.status.success());
+}
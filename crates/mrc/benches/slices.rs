@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use mrc::MrcFile;
+
+const NX: i32 = 512;
+const NY: i32 = 512;
+const NZ: i32 = 64;
+
+/// Writes a minimal mode 1 (int16) MRC volume
+fn fixture() -> PathBuf {
+    let mut bytes = vec![0u8; 1024];
+    for (i, v) in [NX, NY, NZ, 1].iter().enumerate() {
+        bytes[i * 4..i * 4 + 4].copy_from_slice(&v.to_le_bytes());
+    }
+    // Cell dimensions at word 11, giving 1 A voxels
+    for (i, v) in [NX as f32, NY as f32, NZ as f32].iter().enumerate() {
+        bytes[40 + i * 4..44 + i * 4].copy_from_slice(&v.to_le_bytes());
+    }
+    for (i, v) in [1i32, 2, 3].iter().enumerate() {
+        bytes[64 + i * 4..68 + i * 4].copy_from_slice(&v.to_le_bytes());
+    }
+    bytes[208..212].copy_from_slice(b"MAP ");
+    bytes[212..214].copy_from_slice(&[0x44, 0x44]);
+    for i in 0..NX * NY * NZ {
+        bytes.extend_from_slice(&(i as i16).to_le_bytes());
+    }
+
+    let path = std::env::temp_dir().join("emfir_bench_slices.mrc");
+    std::fs::write(&path, bytes).expect("failed to write synthetic MRC fixture");
+    path
+}
+
+fn bench_read_slices(c: &mut Criterion) {
+    let path = fixture();
+    let mrc = MrcFile::open(&path.to_string_lossy()).unwrap();
+    let range = 0..NZ as usize;
+
+    let mut group = c.benchmark_group("read_slices");
+    group.bench_function("bulk", |b| {
+        b.iter(|| mrc.read_slices(range.clone()).unwrap());
+    });
+    group.bench_function("one_at_a_time", |b| {
+        b.iter(|| range.clone().map(|z| mrc.read_slices(z..z + 1).unwrap()).collect::<Vec<_>>());
+    });
+    group.finish();
+
+    std::fs::remove_file(&path).ok();
+}
+
+criterion_group!(benches, bench_read_slices);
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use mrc::MrcFile;
+
+const NX: i32 = 2048;
+const NY: i32 = 2048;
+
+/// Writes a minimal mode 2 (float32) MRC with a gradient image
+fn fixture() -> PathBuf {
+    let mut bytes = vec![0u8; 1024];
+    for (i, v) in [NX, NY, 1, 2].iter().enumerate() {
+        bytes[i * 4..i * 4 + 4].copy_from_slice(&v.to_le_bytes());
+    }
+    // Cell dimensions at word 11, giving 1 A pixels
+    for (i, v) in [NX as f32, NY as f32, 1.0].iter().enumerate() {
+        bytes[40 + i * 4..44 + i * 4].copy_from_slice(&v.to_le_bytes());
+    }
+    for (i, v) in [1i32, 2, 3].iter().enumerate() {
+        bytes[64 + i * 4..68 + i * 4].copy_from_slice(&v.to_le_bytes());
+    }
+    bytes[208..212].copy_from_slice(b"MAP ");
+    bytes[212..214].copy_from_slice(&[0x44, 0x44]);
+    for y in 0..NY {
+        for x in 0..NX {
+            bytes.extend_from_slice(&((x + y) as f32).to_le_bytes());
+        }
+    }
+
+    let path = std::env::temp_dir().join("emfir_bench_thumbnail.mrc");
+    std::fs::write(&path, bytes).expect("failed to write synthetic MRC fixture");
+    path
+}
+
+fn bench_thumbnail(c: &mut Criterion) {
+    let path = fixture();
+    let output = std::env::temp_dir().join("emfir_bench_thumbnail.png");
+    let mrc = MrcFile::open(&path.to_string_lossy()).unwrap();
+
+    let mut group = c.benchmark_group("save_thumbnail");
+    for downsample in [4, 16] {
+        group.bench_function(format!("downsample_{}", downsample), |b| {
+            b.iter(|| mrc.save_thumbnail(&output.to_string_lossy(), downsample).unwrap());
+        });
+    }
+    group.finish();
+
+    std::fs::remove_file(&path).ok();
+    std::fs::remove_file(&output).ok();
+}
+
+criterion_group!(benches, bench_thumbnail);
+criterion_main!(benches);
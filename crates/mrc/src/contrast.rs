@@ -0,0 +1,119 @@
+use std::str::FromStr;
+
+use crate::finite_range;
+
+/// Fraction of pixels saturated at each end by [`auto_contrast`], matching ImageJ's
+/// default 0.35% total. The EER thumbnails clip the same fraction.
+pub const AUTO_CONTRAST_TAIL: f64 = 0.0035 / 2.0;
+/// Bins of each histogram pass in [`percentile`]
+const HISTOGRAM_BINS: usize = 1024;
+/// Histogram passes of [`percentile`] at most; each narrows the range by the bin count
+pub const PERCENTILE_PASSES: usize = 3;
+
+/// How thumbnail grey levels are mapped from the data
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Contrast {
+    /// Stretch the full finite range
+    #[default]
+    MinMax,
+    /// Histogram-based limits from [`auto_contrast`]
+    Auto,
+    /// Limits at these percentiles (0 to 100) of the data, see [`percentile`]
+    Percentile { low: f32, high: f32 },
+    /// The dmin/dmax range recorded in the MRC header, which covers the whole volume
+    /// rather than only the sampled pixels. Like `MinMax` where the header leaves the
+    /// range undetermined, and for data the header doesn't describe (projections).
+    Header,
+}
+
+impl Contrast {
+    /// Display limits for `values` under this contrast mode
+    pub fn limits(self, values: &[f32]) -> (f32, f32) {
+        match self {
+            Contrast::MinMax | Contrast::Header => finite_range(values),
+            Contrast::Auto => auto_contrast(values),
+            Contrast::Percentile { low, high } => {
+                (percentile(values, low as f64 / 100.0), percentile(values, high as f64 / 100.0))
+            }
+        }
+    }
+}
+
+impl FromStr for Contrast {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Unknown contrast mode '{}': use minmax, auto, header or percentile:LOW,HIGH", s);
+        match s {
+            "minmax" => Ok(Contrast::MinMax),
+            "auto" => Ok(Contrast::Auto),
+            "header" => Ok(Contrast::Header),
+            _ => {
+                let (low, high) = s.strip_prefix("percentile:").and_then(|p| p.split_once(',')).ok_or_else(invalid)?;
+                let parse = |v: &str| v.trim().parse::<f32>().ok().filter(|v| (0.0..=100.0).contains(v));
+                match (parse(low), parse(high)) {
+                    (Some(low), Some(high)) if low < high => Ok(Contrast::Percentile { low, high }),
+                    _ => Err(invalid()),
+                }
+            }
+        }
+    }
+}
+
+/// Estimates display limits by clipping a small fraction of the finite values at each
+/// tail, so a few outliers don't flatten the image. See [`percentile`] for the cost.
+pub fn auto_contrast(data: &[f32]) -> (f32, f32) {
+    (percentile(data, AUTO_CONTRAST_TAIL), percentile(data, 1.0 - AUTO_CONTRAST_TAIL))
+}
+
+/// Estimates the `q` quantile (0 to 1) of the finite values of `data`, without sorting
+/// or copying it.
+///
+/// A histogram over the full range finds the bin holding the wanted rank; the next pass
+/// histograms only the values of that bin, and so on until the bin holds a single value
+/// or [`PERCENTILE_PASSES`] are done. That is O(n) time and O(bins) memory, and outliers
+/// stretching the range cost precision only in the first pass. The result is always a
+/// value from the data, within `range / HISTOGRAM_BINS^PERCENTILE_PASSES` of the exact
+/// percentile. NaN when there are no finite values.
+pub fn percentile(data: &[f32], q: f64) -> f32 {
+    let (mut low, mut high) = finite_range(data);
+    let total = data.iter().filter(|v| v.is_finite()).count();
+    if total == 0 {
+        return f32::NAN;
+    }
+    // 0-based rank of the wanted value among the sorted finite values, and among the
+    // values inside the current [low, high] window
+    let mut rank = (q.clamp(0.0, 1.0) * (total - 1) as f64).round() as usize;
+
+    for _ in 0..PERCENTILE_PASSES {
+        if low >= high {
+            break;
+        }
+        let width = (high - low) as f64 / HISTOGRAM_BINS as f64;
+        let mut counts = [0usize; HISTOGRAM_BINS];
+        // Bin edges are rounded, so each bin's actual extent bounds the next window;
+        // binning is monotonic, so the window holds exactly that bin's values
+        let mut extent = [(f32::INFINITY, f32::NEG_INFINITY); HISTOGRAM_BINS];
+        for &v in data.iter().filter(|v| (low..=high).contains(*v)) {
+            let bin = (((v - low) as f64 / width) as usize).min(HISTOGRAM_BINS - 1);
+            counts[bin] += 1;
+            extent[bin] = (extent[bin].0.min(v), extent[bin].1.max(v));
+        }
+        let (bin, below) = locate_rank(&counts, rank);
+        rank -= below;
+        (low, high) = extent[bin];
+    }
+    low
+}
+
+/// The bin holding the value of 0-based `rank`, and the count in the bins before it
+fn locate_rank(histogram: &[usize], rank: usize) -> (usize, usize) {
+    let mut below = 0;
+    for (bin, &count) in histogram.iter().enumerate() {
+        if below + count > rank {
+            return (bin, below);
+        }
+        below += count;
+    }
+    (histogram.len() - 1, below)
+}
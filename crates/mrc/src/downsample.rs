@@ -0,0 +1,46 @@
+use std::str::FromStr;
+
+/// How thumbnails are reduced by their downsampling factor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DownsampleMode {
+    /// Keep every Nth pixel of every Nth row; fast, but aliases noisy data
+    #[default]
+    Nearest,
+    /// Average each NxN block, over the pixels it covers at the right and bottom edges
+    Mean,
+}
+
+impl FromStr for DownsampleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(DownsampleMode::Nearest),
+            "mean" => Ok(DownsampleMode::Mean),
+            _ => Err(format!("Invalid downsampling mode '{}': expected nearest or mean", s)),
+        }
+    }
+}
+
+impl DownsampleMode {
+    /// Reduces a row-major `nx`-wide plane by `factor` along both axes
+    pub(crate) fn apply(self, values: &[f32], nx: usize, factor: usize) -> Vec<f32> {
+        let ny = values.len() / nx.max(1);
+        let (out_x, out_y) = (nx.div_ceil(factor), ny.div_ceil(factor));
+        match self {
+            DownsampleMode::Nearest => (0..out_y)
+                .flat_map(|y| (0..out_x).map(move |x| values[y * factor * nx + x * factor]))
+                .collect(),
+            DownsampleMode::Mean => {
+                let mut sums = vec![0f64; out_x * out_y];
+                let mut counts = vec![0u32; sums.len()];
+                for (i, &v) in values.iter().enumerate() {
+                    let out = (i / nx / factor) * out_x + (i % nx) / factor;
+                    sums[out] += v as f64;
+                    counts[out] += 1;
+                }
+                sums.iter().zip(&counts).map(|(&sum, &count)| (sum / count.max(1) as f64) as f32).collect()
+            }
+        }
+    }
+}
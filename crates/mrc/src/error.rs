@@ -7,4 +7,22 @@ pub enum MrcError {
     Io(#[from] io::Error),
     #[error("Invalid MRC format: {0}")]
     Format(String),
-}
\ No newline at end of file
+    #[error("File not found: {0}")]
+    NotFound(String),
+    #[error("Permission denied reading {0}")]
+    PermissionDenied(String),
+    #[error("File is empty: {0}")]
+    EmptyFile(String),
+    #[error("Needs about {needed} bytes, over the memory limit of {limit} bytes; {hint}")]
+    MemoryLimit { needed: usize, limit: usize, hint: &'static str },
+}
+impl MrcError {
+    /// Maps an error from opening `path` to a friendly variant, if there is one
+    pub(crate) fn from_open(path: &str, err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => MrcError::NotFound(path.to_string()),
+            io::ErrorKind::PermissionDenied => MrcError::PermissionDenied(path.to_string()),
+            _ => MrcError::Io(err),
+        }
+    }
+}
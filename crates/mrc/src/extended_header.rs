@@ -0,0 +1,181 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+use serde::Serialize;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{MrcError, MrcHeader};
+
+/// Bytes of an FEI1 per-section record. FEI2 records append fields, up to
+/// [`FEI2_RECORD_SIZE`]; each record starts with its actual size as an int32.
+pub const FEI1_RECORD_SIZE: usize = 768;
+pub const FEI2_RECORD_SIZE: usize = 888;
+
+/// Per-section acquisition metadata from an FEI extended header, at the offsets of
+/// Thermo Fisher's "MRC2014 FEI extended header" specification
+#[derive(Debug, Clone, Serialize)]
+pub struct FeiFrameMeta {
+    /// Alpha tilt in degrees
+    pub a_tilt: f64,
+    /// Beta tilt in degrees
+    pub b_tilt: f64,
+    /// Stage position in metres
+    pub stage_x: f64,
+    pub stage_y: f64,
+    pub stage_z: f64,
+    /// Image shift in metres
+    pub image_shift_x: f64,
+    pub image_shift_y: f64,
+    /// Defocus in metres
+    pub defocus: f64,
+    /// Dose in electrons per square metre
+    pub dose: f64,
+    /// Exposure (integration) time in seconds
+    pub exposure_time: f64,
+    /// Tilt axis angle in degrees
+    pub tilt_axis: f64,
+    /// Pixel size in metres
+    pub pixel_size_x: f64,
+    pub pixel_size_y: f64,
+    pub magnification: f64,
+    /// Accelerating voltage in volts
+    pub voltage: f64,
+    /// Image rotation in degrees, FEI2 only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_rotation: Option<f64>,
+    /// Tilt step between images in degrees, FEI2 only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tilt_per_image: Option<f64>,
+}
+
+impl FeiFrameMeta {
+    /// Decodes a record holding at least [`FEI1_RECORD_SIZE`] bytes, or
+    /// [`FEI2_RECORD_SIZE`] with `fei2`
+    fn parse<B: ByteOrder>(record: &[u8], fei2: bool) -> Self {
+        let f64_at = |offset: usize| B::read_f64(&record[offset..offset + 8]);
+        let fei2_at = |offset: usize| fei2.then(|| f64_at(offset));
+
+        FeiFrameMeta {
+            a_tilt: f64_at(100),
+            b_tilt: f64_at(108),
+            stage_x: f64_at(116),
+            stage_y: f64_at(124),
+            stage_z: f64_at(132),
+            image_shift_x: f64_at(403),
+            image_shift_y: f64_at(411),
+            defocus: f64_at(220),
+            dose: f64_at(92),
+            exposure_time: f64_at(419),
+            tilt_axis: f64_at(140),
+            pixel_size_x: f64_at(156),
+            pixel_size_y: f64_at(164),
+            magnification: f64_at(289),
+            voltage: f64_at(84),
+            image_rotation: fei2_at(784),
+            tilt_per_image: fei2_at(836),
+        }
+    }
+}
+
+/// Summary of the FEI per-section metadata, for the header JSON
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FeiSummary {
+    pub sections: usize,
+    pub min_tilt: f64,
+    pub max_tilt: f64,
+    pub mean_defocus: f64,
+    pub total_dose: f64,
+    pub total_exposure_time: f64,
+}
+
+impl FeiSummary {
+    pub fn from_frames(frames: &[FeiFrameMeta]) -> Option<Self> {
+        if frames.is_empty() {
+            return None;
+        }
+        let n = frames.len() as f64;
+        Some(FeiSummary {
+            sections: frames.len(),
+            min_tilt: frames.iter().map(|f| f.a_tilt).fold(f64::INFINITY, f64::min),
+            max_tilt: frames.iter().map(|f| f.a_tilt).fold(f64::NEG_INFINITY, f64::max),
+            mean_defocus: frames.iter().map(|f| f.defocus).sum::<f64>() / n,
+            total_dose: frames.iter().map(|f| f.dose).sum(),
+            total_exposure_time: frames.iter().map(|f| f.exposure_time).sum(),
+        })
+    }
+}
+
+/// A parsed extended header. Only the FEI layouts (FEI1, FEI2) are understood: one
+/// record per section, of which the fields in [`FeiFrameMeta`] are read.
+#[derive(Debug, Clone)]
+pub struct ExtendedHeader {
+    exttyp: [u8; 4],
+    frames: Vec<FeiFrameMeta>,
+}
+
+impl ExtendedHeader {
+    /// Parses the extended header that follows the main header, or returns None when
+    /// the file has none or one of a type other than FEI
+    pub fn read<R: Read + Seek>(reader: &mut R, header: &MrcHeader) -> Result<Option<Self>, MrcError> {
+        if !is_fei(header) {
+            return Ok(None);
+        }
+        let frames = read_fei_frames(reader, header)?;
+        Ok(Some(ExtendedHeader { exttyp: header.exttyp, frames }))
+    }
+
+    /// The type code from offset 104 of the main header, e.g. `b"FEI2"`
+    pub fn exttyp(&self) -> &[u8; 4] {
+        &self.exttyp
+    }
+
+    /// One record per section, in section order
+    pub fn frames(&self) -> &[FeiFrameMeta] {
+        &self.frames
+    }
+}
+
+/// Returns true if the header declares an FEI-style extended header
+pub fn is_fei(header: &MrcHeader) -> bool {
+    header.nsymbt > 0 && matches!(&header.exttyp, b"FEI1" | b"FEI2")
+}
+
+/// Reads one FEI record per Z section from the extended header.
+/// Returns an empty list when the header has no FEI extended header.
+pub fn read_fei_frames<R: Read + Seek>(
+    reader: &mut R,
+    header: &MrcHeader,
+) -> Result<Vec<FeiFrameMeta>, MrcError> {
+    if !is_fei(header) {
+        return Ok(Vec::new());
+    }
+
+    let big_endian = header.is_big_endian();
+    reader.seek(SeekFrom::Start(1024))?;
+    let record_size = match big_endian {
+        true => reader.read_i32::<BigEndian>()?,
+        false => reader.read_i32::<LittleEndian>()?,
+    };
+    let fei2 = &header.exttyp == b"FEI2";
+    let layout_size = if fei2 { FEI2_RECORD_SIZE } else { FEI1_RECORD_SIZE };
+    if record_size < layout_size as i32 {
+        return Err(MrcError::Format(format!(
+            "{} extended header records of {} bytes are smaller than the {}-byte layout",
+            String::from_utf8_lossy(&header.exttyp), record_size, layout_size
+        )));
+    }
+
+    let record_size = record_size as usize;
+    let records = (header.nsymbt as usize / record_size).min(header.nz.max(0) as usize);
+    // Only the documented prefix is read, whatever size the records declare
+    let mut record = vec![0u8; layout_size];
+    (0..records)
+        .map(|i| {
+            reader.seek(SeekFrom::Start(1024 + (i * record_size) as u64))?;
+            reader.read_exact(&mut record)?;
+            Ok(match big_endian {
+                true => FeiFrameMeta::parse::<BigEndian>(&record, fei2),
+                false => FeiFrameMeta::parse::<LittleEndian>(&record, fei2),
+            })
+        })
+        .collect()
+}
@@ -1,13 +1,32 @@
+#[cfg(feature = "checksum")]
+mod checksum;
+mod contrast;
+mod downsample;
 mod error;
+mod extended_header;
+mod ome_tiff;
+mod projection;
+mod sample;
+mod writer;
+pub use contrast::{auto_contrast, percentile, Contrast, AUTO_CONTRAST_TAIL, PERCENTILE_PASSES};
+pub use downsample::DownsampleMode;
 pub use error::MrcError;
+pub use extended_header::{ExtendedHeader, FeiFrameMeta, FeiSummary};
+pub use ome_tiff::export_ome_tiff;
+pub use projection::{Progress, ProjectionMode};
+use sample::SampleReader;
 
-use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
 use std::fs::File;
-use std::io::{self, Read, Seek, SeekFrom};
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::sync::{Mutex, MutexGuard};
 use serde::Serialize;
-use image::{ImageBuffer, Rgb};
+use image::{ImageBuffer, ImageOutputFormat, Rgb, RgbImage};
+use ndarray::{s, Array3};
 
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum VoxelType {
     Float32,
     Float64,
@@ -15,9 +34,16 @@ pub enum VoxelType {
     UInt8,
     Int16,
     UInt16,
+    /// Mode 101, unsigned 4-bit packed two to a byte
+    UInt4,
+    /// Mode 3, pairs of 16-bit integers (real, imaginary)
+    Complex16,
+    /// Mode 4, pairs of 32-bit floats (real, imaginary)
+    Complex32,
 }
 
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct ImageData {
     size_x: i32,
     size_y: i32,
@@ -27,29 +53,106 @@ pub struct ImageData {
     voxel_type: VoxelType,
     voxel_spacing_x: f32,
     voxel_spacing_y: f32,
-    voxel_spacing_z: f32,
+    /// None (null in JSON) for image stacks, whose sections are separate images rather
+    /// than slices of a volume; see [`MrcHeader::is_image_stack`]
+    voxel_spacing_z: Option<f32>,
+    density: DensityStats,
+    /// Grid index of the first column, row and section (nxstart, nystart, nzstart)
+    start: [i32; 3],
+    /// Origin in Angstroms along x, y, z
+    origin: [f32; 3],
+    /// Cell angles alpha, beta, gamma in degrees, only for non-orthogonal cells
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cell_angles: Option<[f32; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skew: Option<SkewTransform>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tilt_series: Option<FeiSummary>,
+    /// Text labels from the header, left out when there are none
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    labels: Vec<String>,
 }
 
+/// CCP4 skew transformation from the header: a 3x3 matrix (row-major, S11, S12, ...)
+/// and translation mapping orthogonal to skewed coordinates. Only recorded, never applied
+/// to the data.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SkewTransform {
+    pub matrix: [[f32; 3]; 3],
+    pub translation: [f32; 3],
+}
+
+impl SkewTransform {
+    /// True for the identity matrix with no translation, which leaves the data as stored
+    pub fn is_identity(&self) -> bool {
+        let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        self.matrix == identity && self.translation == [0.0; 3]
+    }
+}
+
+/// Density statistics recorded in the header. A value is `None` when it is not a finite
+/// number or the header marks it as undetermined (`dmax < dmin`, `rms < 0`), so older
+/// files with garbage here serialize as `null` rather than NaN.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DensityStats {
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub mean: Option<f32>,
+    pub rms: Option<f32>,
+}
+
+impl DensityStats {
+    fn from_raw(dmin: f32, dmax: f32, dmean: f32, rms: f32) -> Self {
+        let finite = |v: f32| v.is_finite().then_some(v);
+        let (min, max) = match (finite(dmin), finite(dmax)) {
+            (Some(min), Some(max)) if max >= min => (Some(min), Some(max)),
+            _ => (None, None),
+        };
+        DensityStats { min, max, mean: finite(dmean), rms: finite(rms).filter(|&r| r >= 0.0) }
+    }
+}
+
+/// Unsigned 4-bit mode, two values per byte (low nibble first), with each row starting
+/// on a byte boundary
+pub const MODE_UINT4: i32 = 101;
+
+/// Nonstandard mode used by some writers for 64-bit float data. MRC2014 leaves mode 5
+/// undefined, so there is no conflict with standard files.
+pub const MODE_FLOAT64: i32 = 5;
+
 impl ImageData {
     pub fn from_mrc(header: &MrcHeader) -> Self {
         let voxel_type = match header.mode {
             0 => VoxelType::Int8,
             1 => VoxelType::Int16,
             2 => VoxelType::Float32,
+            3 => VoxelType::Complex16,
+            4 => VoxelType::Complex32,
+            MODE_FLOAT64 => VoxelType::Float64,
             6 => VoxelType::UInt16,
+            MODE_UINT4 => VoxelType::UInt4,
             _ => VoxelType::Float32, // default to Float32 for unknown modes
         };
 
         ImageData {
-            size_x: header.nx,
-            size_y: header.ny,
-            size_z: header.nz,
+            size_x: header.dims()[0],
+            size_y: header.dims()[1],
+            size_z: header.dims()[2],
             size_t: 1,  // MRC files don't have time dimension
             size_c: 1,  // MRC files don't have channel dimension
             voxel_type,
             voxel_spacing_x: header.pixel_size[0],
             voxel_spacing_y: header.pixel_size[1],
-            voxel_spacing_z: header.pixel_size[2],
+            voxel_spacing_z: (!header.is_image_stack()).then_some(header.pixel_size[2]),
+            density: header.density,
+            start: header.start,
+            origin: header.origin,
+            cell_angles: (!header.is_orthogonal()).then_some(header.cell_angles),
+            skew: header.skew,
+            tilt_series: None,
+            labels: header.labels.clone(),
         }
     }
 }
@@ -60,176 +163,1805 @@ pub struct MrcHeader {
     ny: i32,
     nz: i32,
     mode: i32,
+    start: [i32; 3],
     cell_dims: [f32; 3],
     cell_angles: [f32; 3],
     map_axis: [i32; 3],
     pixel_size: [f32; 3],
+    density: DensityStats,
+    ispg: i32,
+    nsymbt: i32,
+    origin: [f32; 3],
+    /// CCP4 skew flag; nonzero means a skew transformation is present
+    lskflg: i32,
+    /// Read from offsets 100-148 only when `lskflg` is set, since MRC2014 reuses them
+    skew: Option<SkewTransform>,
+    exttyp: [u8; 4],
+    map_stamp: [u8; 4],
+    machine_stamp: [u8; 4],
+    /// From the machine stamp: 0x11 0x11 for big-endian, anything else little-endian
+    big_endian: bool,
+    labels: Vec<String>,
+    /// Replaces the computed data offset, see [`MrcFile::set_data_offset_override`]
+    data_offset_override: Option<u64>,
 }
 
+/// Extracts a pixel size in Angstroms from label text such as "Pixel size: 1.35 A".
+/// Values given in nm are converted; a missing unit is taken as Angstroms.
+fn parse_label_pixel_size(label: &str) -> Option<f32> {
+    let lower = label.to_ascii_lowercase();
+    let start = lower.find("pixel size")? + "pixel size".len();
+    let rest = lower[start..].trim_start_matches(|c: char| c == ':' || c == '=' || c.is_whitespace());
+    let number_len = rest
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(rest.len());
+    let value: f32 = rest[..number_len].parse().ok()?;
+    let unit = rest[number_len..].trim_start();
+    let value = if unit.starts_with("nm") { value * 10.0 } else { value };
+    (value.is_finite() && value > 0.0).then_some(value)
+}
+
+/// Bytes per component and components per pixel of an MRC mode
+fn sample_layout(mode: i32) -> Option<(usize, usize)> {
+    match mode {
+        0 => Some((1, 1)),
+        1 | 6 | 12 => Some((2, 1)),
+        2 => Some((4, 1)),
+        3 => Some((2, 2)),
+        4 => Some((4, 2)),
+        MODE_FLOAT64 => Some((8, 1)),
+        _ => None,
+    }
+}
+
+/// Machine stamp of big-endian files; only the first byte is significant
+const BIG_ENDIAN_STAMP: u8 = 0x11;
+
 impl MrcHeader {
+    /// Reads the header from the start of `reader`, in the byte order given by the
+    /// machine stamp at offset 212. Fails unless the "MAP " stamp of MRC2014 is at
+    /// offset 208; see [`MrcHeader::read_permissive`] for older files.
     pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, MrcError> {
+        MrcHeader::read_checked(reader, false)
+    }
+
+    /// Like [`MrcHeader::read`], but only warns when the "MAP " stamp is missing, for
+    /// legacy files written before the stamp was required
+    pub fn read_permissive<R: Read + Seek>(reader: &mut R) -> Result<Self, MrcError> {
+        MrcHeader::read_checked(reader, true)
+    }
+
+    fn read_checked<R: Read + Seek>(reader: &mut R, permissive: bool) -> Result<Self, MrcError> {
+        let mut map_stamp = [0u8; 4];
+        reader.seek(SeekFrom::Start(208))?;
+        reader.read_exact(&mut map_stamp)?;
+        if &map_stamp != b"MAP " {
+            let found = String::from_utf8_lossy(&map_stamp);
+            if !permissive {
+                return Err(MrcError::Format(format!(
+                    "No \"MAP \" stamp at offset 208 (found {:?}); not an MRC2014 file", found
+                )));
+            }
+            eprintln!("No \"MAP \" stamp at offset 208 (found {:?}); reading as a legacy MRC file", found);
+        }
+        let big_endian = reader.read_u8()? == BIG_ENDIAN_STAMP;
+        reader.seek(SeekFrom::Start(0))?;
+        let header = if big_endian {
+            MrcHeader::read_as::<BigEndian, _>(reader)?
+        } else {
+            MrcHeader::read_as::<LittleEndian, _>(reader)?
+        };
+        Ok(MrcHeader { big_endian, ..header })
+    }
+
+    fn read_as<B: ByteOrder, R: Read + Seek>(reader: &mut R) -> Result<Self, MrcError> {
         let mut header = MrcHeader {
-            nx: reader.read_i32::<LittleEndian>()?,
-            ny: reader.read_i32::<LittleEndian>()?,
-            nz: reader.read_i32::<LittleEndian>()?,
-            mode: reader.read_i32::<LittleEndian>()?,
+            nx: reader.read_i32::<B>()?,
+            ny: reader.read_i32::<B>()?,
+            nz: reader.read_i32::<B>()?,
+            mode: reader.read_i32::<B>()?,
+            start: [reader.read_i32::<B>()?, reader.read_i32::<B>()?, reader.read_i32::<B>()?],
             cell_dims: [0.0; 3],
             cell_angles: [0.0; 3],
             map_axis: [0; 3],
             pixel_size: [0.0; 3], // x, y, z in Angstroms
+            density: DensityStats { min: None, max: None, mean: None, rms: None },
+            ispg: 0,
+            nsymbt: 0,
+            origin: [0.0; 3],
+            lskflg: 0,
+            skew: None,
+            exttyp: [0; 4],
+            map_stamp: [0; 4],
+            machine_stamp: [0; 4],
+            big_endian: false,
+            labels: Vec::new(),
+            data_offset_override: None,
         };
 
-        // Read cell dimensions at offset 10
+        // Skip to cell dimensions at offset 40
+        reader.seek(SeekFrom::Start(40))?;
         for dim in &mut header.cell_dims {
-            *dim = reader.read_f32::<LittleEndian>()?;
+            *dim = reader.read_f32::<B>()?;
         }
-
-        // Skip to pixel size at offset 40
-        reader.seek(SeekFrom::Start(40))?;
         
-        // Read pixel sizes and divide by grid dimensions
-        header.pixel_size[0] = reader.read_f32::<LittleEndian>()? / header.nx as f32;
-        header.pixel_size[1] = reader.read_f32::<LittleEndian>()? / header.ny as f32;
-        header.pixel_size[2] = reader.read_f32::<LittleEndian>()? / header.nz as f32;
-
         for angle in &mut header.cell_angles {
-            *angle = reader.read_f32::<LittleEndian>()?;
+            *angle = reader.read_f32::<B>()?;
         }
 
         for axis in &mut header.map_axis {
-            *axis = reader.read_i32::<LittleEndian>()?;
+            *axis = reader.read_i32::<B>()?;
+        }
+        header.set_pixel_size_from_cell();
+
+        // dmin, dmax, dmean at 76; rms is read with the machine stamp below
+        let (dmin, dmax, dmean) = (
+            reader.read_f32::<B>()?,
+            reader.read_f32::<B>()?,
+            reader.read_f32::<B>()?,
+        );
+
+        // Space group at 88, extended header size at 92, skew flag at 96 and the extended
+        // header type code at offset 104
+        reader.seek(SeekFrom::Start(88))?;
+        header.ispg = reader.read_i32::<B>()?;
+        header.nsymbt = reader.read_i32::<B>()?;
+        header.lskflg = reader.read_i32::<B>()?;
+        if header.lskflg != 0 {
+            // Skew matrix at 100, translation at 136
+            let mut skew = SkewTransform { matrix: [[0.0; 3]; 3], translation: [0.0; 3] };
+            for value in skew.matrix.iter_mut().flatten().chain(&mut skew.translation) {
+                *value = reader.read_f32::<B>()?;
+            }
+            header.skew = Some(skew);
+        }
+        reader.seek(SeekFrom::Start(104))?;
+        reader.read_exact(&mut header.exttyp)?;
+
+        // Origin at 196, then the "MAP " signature at 208 and machine stamp at 212
+        reader.seek(SeekFrom::Start(196))?;
+        for value in &mut header.origin {
+            *value = reader.read_f32::<B>()?;
+        }
+        reader.read_exact(&mut header.map_stamp)?;
+        reader.read_exact(&mut header.machine_stamp)?;
+        let rms = reader.read_f32::<B>()?;
+        header.density = DensityStats::from_raw(dmin, dmax, dmean, rms);
+
+        if header.nsymbt < 0 {
+            return Err(MrcError::Format("Negative extended header size".to_string()));
+        }
+
+        // Label count at offset 220, then ten 80-character labels from offset 224
+        reader.seek(SeekFrom::Start(220))?;
+        let nlabl = reader.read_i32::<B>()?.clamp(0, 10) as usize;
+        for _ in 0..nlabl {
+            let mut label = [0u8; 80];
+            reader.read_exact(&mut label)?;
+            let text = String::from_utf8_lossy(&label);
+            header.labels.push(text.trim_end_matches(['\0', ' ']).to_string());
         }
 
-        if header.mode < 0 || header.mode > 6 {
+        // Some writers leave the cell zero and only record the pixel size in a label
+        if header.cell_dims.iter().all(|&d| d == 0.0) {
+            let from_label = header.labels.iter()
+                .find_map(|label| parse_label_pixel_size(label).map(|size| (size, label)));
+            if let Some((size, label)) = from_label {
+                eprintln!("Cell dimensions are zero; using pixel size {} A from label \"{}\"", size, label);
+                header.pixel_size = [size; 3];
+            }
+        }
+
+        if !matches!(header.mode, 0..=6 | MODE_UINT4) {
             return Err(MrcError::Format("Invalid mode value".to_string()));
         }
+        if header.nx <= 0 || header.ny <= 0 {
+            return Err(MrcError::Format(format!("Image size {}x{} has no pixels", header.nx, header.ny)));
+        }
 
         Ok(header)
     }
+
+    /// Reads the header together with the raw 1024 bytes it was parsed from, so tools can
+    /// compare the parsed fields against bytes the parser ignores
+    pub fn read_with_raw<R: Read + Seek>(reader: &mut R) -> Result<(Self, [u8; 1024]), MrcError> {
+        let mut raw = [0u8; 1024];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut raw)?;
+        let header = MrcHeader::read(&mut Cursor::new(&raw[..]))?;
+        Ok((header, raw))
+    }
+
+    /// Header fields that deviate from the canonical values for EM data, as
+    /// `(field, value)` pairs; empty for an unremarkable header
+    pub fn nonstandard_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        if !matches!(self.mode, 0 | 1 | 2 | 6) {
+            fields.push(("mode", self.mode.to_string()));
+        }
+        if !self.is_orthogonal() {
+            fields.push(("cell_angles", format!("{:?}", self.cell_angles)));
+        }
+        if self.map_axis != [1, 2, 3] {
+            fields.push(("map_axis", format!("{:?}", self.map_axis)));
+        }
+        if !matches!(self.ispg, 0 | 1 | 401) {
+            fields.push(("ispg", self.ispg.to_string()));
+        }
+        if self.lskflg != 0 {
+            fields.push(("lskflg", self.lskflg.to_string()));
+        }
+        if let Some(skew) = self.skew.filter(|skew| !skew.is_identity()) {
+            // Listed separately as the data may need this transform, which is never applied
+            fields.push(("skew", format!("{:?} + {:?}", skew.matrix, skew.translation)));
+        }
+        if &self.map_stamp != b"MAP " {
+            fields.push(("map", format!("{:?}", String::from_utf8_lossy(&self.map_stamp))));
+        }
+        if !matches!(self.machine_stamp[..2], [0x44, 0x44] | [0x44, 0x41] | [0x11, 0x11]) {
+            fields.push(("machine_stamp", format!("{:02x?}", self.machine_stamp)));
+        }
+        if self.cell_dims.iter().all(|&d| d == 0.0) {
+            fields.push(("cell_dims", format!("{:?}", self.cell_dims)));
+        }
+        fields
+    }
+
+    /// The X, Y, Z axis (0, 1 or 2) stored along columns, rows and sections, per
+    /// mapc/mapr/maps; `None` when those are not a permutation of 1, 2, 3, as when a
+    /// writer leaves them zero
+    fn axis_order(&self) -> Option<[usize; 3]> {
+        let mut order = [0; 3];
+        for (axis, &map) in order.iter_mut().zip(&self.map_axis) {
+            *axis = usize::try_from(map - 1).ok().filter(|&a| a < 3)?;
+        }
+        (order.contains(&0) && order.contains(&1) && order.contains(&2)).then_some(order)
+    }
+
+    /// True when columns, rows and sections are stored along X, Y and Z. Invalid axis
+    /// mappings are treated as canonical.
+    pub fn is_canonical_axes(&self) -> bool {
+        self.axis_order().is_none_or(|order| order == [0, 1, 2])
+    }
+
+    /// Grid size along X, Y and Z; `nx`, `ny` and `nz` in the header count columns, rows
+    /// and sections, which differ for maps with a non-canonical axis order
+    pub fn dims(&self) -> [i32; 3] {
+        let stored = [self.nx, self.ny, self.nz];
+        let Some(order) = self.axis_order() else { return stored };
+        let mut dims = [0; 3];
+        for (&axis, n) in order.iter().zip(stored) {
+            dims[axis] = n;
+        }
+        dims
+    }
+
+    /// Grid index of the first column, row and section (nxstart, nystart, nzstart), for
+    /// placing a sub-volume within a larger map
+    pub fn start(&self) -> [i32; 3] {
+        self.start
+    }
+
+    /// Origin in Angstroms along x, y, z, as recorded in the header
+    pub fn origin(&self) -> [f32; 3] {
+        self.origin
+    }
+
+    /// Text labels (up to ten) with trailing spaces and nulls trimmed
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Density statistics (dmin, dmax, dmean, rms) as recorded in the header
+    pub fn density(&self) -> DensityStats {
+        self.density
+    }
+
+    /// True for files written in big-endian byte order, per the machine stamp
+    pub fn is_big_endian(&self) -> bool {
+        self.big_endian
+    }
+
+    /// Size in bytes of the extended header between the main header and the data
+    pub fn nsymbt(&self) -> i32 {
+        self.nsymbt
+    }
+
+    /// Extended header type code, e.g. `b"FEI2"`; zeros for files predating MRC2014
+    pub fn exttyp(&self) -> &[u8; 4] {
+        &self.exttyp
+    }
+
+    /// Cell angles alpha, beta, gamma in degrees
+    pub fn cell_angles(&self) -> [f32; 3] {
+        self.cell_angles
+    }
+
+    /// True when every cell angle is 90 degrees, or unset (all zero) as some writers
+    /// leave them. Everything in this crate assumes an orthogonal grid.
+    pub fn is_orthogonal(&self) -> bool {
+        self.cell_angles == [0.0; 3] || self.cell_angles.iter().all(|&a| (a - 90.0).abs() <= 1e-3)
+    }
+
+    /// True for a stack of 2D images, such as a tilt series or a particle stack: space
+    /// group 0 with more than one section. The Z "spacing" of a stack comes from a cell
+    /// depth that is only the section count, not a physical depth. Volumes have space
+    /// group 1, or 401 and up for volume stacks.
+    pub fn is_image_stack(&self) -> bool {
+        self.ispg == 0 && self.dims()[2] > 1
+    }
+
+    /// The skew transformation, when the skew flag is set
+    pub fn skew(&self) -> Option<&SkewTransform> {
+        self.skew.as_ref()
+    }
+
+    /// Bytes per sample component: 1, 2, 4 or 8. Complex modes store two components
+    /// per pixel, see [`MrcHeader::samples_per_pixel`]. None for unknown modes and for
+    /// 4-bit mode 101, whose samples share bytes; [`MrcHeader::section_bytes`] covers it.
+    pub fn bytes_per_sample(&self) -> Option<usize> {
+        sample_layout(self.mode).map(|(bytes, _)| bytes)
+    }
+
+    /// Components per pixel: 2 (real, imaginary) for complex modes 3 and 4, else 1
+    pub fn samples_per_pixel(&self) -> usize {
+        sample_layout(self.mode).map_or(1, |(_, samples)| samples)
+    }
+
+    /// Bytes of data per voxel; None where [`MrcHeader::bytes_per_sample`] is
+    pub fn bytes_per_pixel(&self) -> Option<usize> {
+        Some(self.bytes_per_sample()? * self.samples_per_pixel())
+    }
+
+    /// Bytes in one Z section: rows of `ceil(nx / 2)` bytes for 4-bit data. None for
+    /// unknown modes.
+    pub fn section_bytes(&self) -> Option<u64> {
+        let (nx, ny) = (self.nx.max(0) as u64, self.ny.max(0) as u64);
+        match self.mode {
+            MODE_UINT4 => Some(nx.div_ceil(2) * ny),
+            _ => Some(nx * ny * self.bytes_per_pixel()? as u64),
+        }
+    }
+
+    /// Byte offset of the first voxel: the 1024-byte header plus the extended header,
+    /// unless overridden
+    pub fn data_offset(&self) -> u64 {
+        self.data_offset_override.unwrap_or(1024 + self.nsymbt as u64)
+    }
+
+    /// Pixel sizes are the cell dimensions divided by the grid dimensions along X, Y and
+    /// Z, and 0 (unknown) for an empty axis rather than inf or NaN
+    fn set_pixel_size_from_cell(&mut self) {
+        let dims = self.dims();
+        for ((size, cell), n) in self.pixel_size.iter_mut().zip(self.cell_dims).zip(dims) {
+            *size = if n > 0 { cell / n as f32 } else { 0.0 };
+        }
+    }
+
+    /// Replaces a non-positive nz (a malformed file, or one still being written) with
+    /// the number of whole sections in a file of `file_len` bytes
+    fn infer_nz(&mut self, file_len: u64) -> Result<(), MrcError> {
+        let sections = match self.section_bytes() {
+            None | Some(0) => 0,
+            Some(slice_bytes) => file_len.saturating_sub(self.data_offset()) / slice_bytes,
+        };
+        if sections == 0 || sections > i32::MAX as u64 {
+            return Err(MrcError::Format(format!(
+                "nz is {} and the file holds no complete section to infer it from",
+                self.nz
+            )));
+        }
+        eprintln!("Header nz is {}; inferred {} sections from the file size", self.nz, sections);
+        self.nz = sections as i32;
+        if self.cell_dims.iter().any(|&d| d != 0.0) {
+            self.set_pixel_size_from_cell();
+        }
+        Ok(())
+    }
 }
 
 pub struct MrcFile {
     header: MrcHeader,
     image_data: ImageData,
     path: String,
+    // Kept open so repeated reads reuse one handle; the mutex gives interior
+    // mutability for the seek/read cursor while `MrcFile` stays shareable.
+    reader: Mutex<BufReader<File>>,
+    extended_header: Option<ExtendedHeader>,
+    memory_limit: Option<usize>,
+    /// Opened with [`MrcFile::open_permissive`], kept so [`MrcFile::reopen`] does the same
+    permissive: bool,
 }
 
 impl MrcFile {
     pub fn open(path: &str) -> Result<Self, MrcError> {
-        let mut file = File::open(path)?;
-        let header = MrcHeader::read(&mut file)?;
-        let image_data = ImageData::from_mrc(&header);
+        MrcFile::open_checked(path, false)
+    }
+
+    /// Like [`MrcFile::open`], accepting legacy files without the "MAP " stamp; see
+    /// [`MrcHeader::read_permissive`]
+    pub fn open_permissive(path: &str) -> Result<Self, MrcError> {
+        MrcFile::open_checked(path, true)
+    }
+
+    fn open_checked(path: &str, permissive: bool) -> Result<Self, MrcError> {
+        let file = File::open(path).map_err(|e| MrcError::from_open(path, e))?;
+        let file_len = file.metadata()?.len();
+        if file_len == 0 {
+            return Err(MrcError::EmptyFile(path.to_string()));
+        }
+        let mut reader = BufReader::new(file);
+        let mut header = MrcHeader::read_checked(&mut reader, permissive)?;
+        if header.nz <= 0 {
+            header.infer_nz(file_len)?;
+        }
+        if !header.is_orthogonal() {
+            eprintln!(
+                "Cell angles {:?} describe a non-orthogonal cell; the data is treated as orthogonal",
+                header.cell_angles
+            );
+        }
+        let extended_header = ExtendedHeader::read(&mut reader, &header)?;
+        let mut image_data = ImageData::from_mrc(&header);
+        image_data.tilt_series = extended_header.as_ref().and_then(|ext| FeiSummary::from_frames(ext.frames()));
         
         Ok(MrcFile { 
             header, 
             image_data, 
-            path: path.to_string() 
+            path: path.to_string(),
+            reader: Mutex::new(reader),
+            extended_header,
+            memory_limit: None,
+            permissive,
         })
     }
 
+    /// Per-section FEI metadata (tilt, stage, defocus, ...); empty when the file has none
+    pub fn fei_frames(&self) -> &[FeiFrameMeta] {
+        self.extended_header.as_ref().map_or(&[], |ext| ext.frames())
+    }
+
+    /// The parsed extended header, for files with an FEI one
+    pub fn extended_header(&self) -> Option<&ExtendedHeader> {
+        self.extended_header.as_ref()
+    }
+
+    /// Re-opens the underlying file and re-reads the header, for when the file changed on disk
+    pub fn reopen(&mut self) -> Result<(), MrcError> {
+        let memory_limit = self.memory_limit;
+        let data_offset_override = self.header.data_offset_override;
+        *self = MrcFile::open_checked(&self.path, self.permissive)?;
+        self.memory_limit = memory_limit;
+        self.set_data_offset_override(data_offset_override)?;
+        Ok(())
+    }
+
+    /// Caps the working memory of whole-volume operations, in bytes. Under a limit,
+    /// projections read one section at a time instead of keeping a copy per thread, and
+    /// operations that cannot fit fail with [`MrcError::MemoryLimit`].
+    pub fn set_memory_limit(&mut self, bytes: Option<usize>) {
+        self.memory_limit = bytes;
+    }
+
+    /// Reads voxel data from byte `offset` instead of after the header and extended
+    /// header, for files with prepended bytes or an extended header size the header gets
+    /// wrong; `None` restores the computed offset. Nothing checks that the offset is
+    /// right: a wrong one silently yields shifted or garbage images, so only use it when
+    /// the layout is known. Fails if the offset lies past the end of the file.
+    pub fn set_data_offset_override(&mut self, offset: Option<u64>) -> Result<(), MrcError> {
+        if let Some(offset) = offset {
+            let file_len = self.reader().get_ref().metadata()?.len();
+            if offset >= file_len {
+                return Err(MrcError::Format(format!(
+                    "Data offset {} lies past the end of the {}-byte file",
+                    offset, file_len
+                )));
+            }
+        }
+        self.header.data_offset_override = offset;
+        Ok(())
+    }
+
+    /// Fails when `needed` bytes exceed the memory limit
+    pub(crate) fn check_memory(&self, needed: usize, hint: &'static str) -> Result<(), MrcError> {
+        match self.memory_limit {
+            Some(limit) if needed > limit => Err(MrcError::MemoryLimit { needed, limit, hint }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn header(&self) -> &MrcHeader {
+        &self.header
+    }
+
     pub fn get_image_data(&self) -> &ImageData {
         &self.image_data
     }
 
+    fn reader(&self) -> MutexGuard<'_, BufReader<File>> {
+        // A panic mid-read leaves only the cursor position undefined, and every
+        // read seeks first, so a poisoned lock is safe to recover.
+        self.reader.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Saves a PNG thumbnail of the first slice. A `path` of `-` writes the PNG to stdout.
     pub fn save_thumbnail(&self, path: &str, downsample: u32) -> Result<(), MrcError> {
-        let mut file = File::open(&self.path)?;
-        file.seek(SeekFrom::Start(1024))?; // Skip header
+        self.save_thumbnail_with_contrast(path, downsample, Contrast::MinMax)
+    }
+
+    /// Like [`MrcFile::save_thumbnail`], choosing how grey levels are stretched
+    pub fn save_thumbnail_with_contrast(&self, path: &str, downsample: u32, contrast: Contrast) -> Result<(), MrcError> {
+        self.save_slice_with_options(path, 0, downsample, contrast, DownsampleMode::Nearest)
+    }
+
+    /// Saves a PNG of Z slice `z`, downsampled and stretched like a thumbnail. Fails
+    /// when `z` is past the last slice.
+    pub fn save_slice(&self, path: &str, z: u32, downsample: u32) -> Result<(), MrcError> {
+        self.save_slice_with_options(path, z, downsample, Contrast::MinMax, DownsampleMode::Nearest)
+    }
+
+    /// Like [`MrcFile::save_slice`], choosing how grey levels are stretched and how
+    /// pixels are downsampled
+    pub fn save_slice_with_options(
+        &self,
+        path: &str,
+        z: u32,
+        downsample: u32,
+        contrast: Contrast,
+        sampling: DownsampleMode,
+    ) -> Result<(), MrcError> {
+        let img = self.render_slice(z as usize, downsample, contrast, sampling)?;
+        write_png(&img, path)
+    }
+
+    /// Renders a contrast-stretched thumbnail of the first slice. Complex data is shown
+    /// as its modulus.
+    pub fn render_thumbnail(&self, downsample: u32, contrast: Contrast) -> Result<RgbImage, MrcError> {
+        self.render_slice(0, downsample, contrast, DownsampleMode::Nearest)
+    }
+
+    /// Like [`MrcFile::render_thumbnail`], for Z slice `z`. [`DownsampleMode::Mean`]
+    /// reads the whole slice, so it supports the modes of [`MrcFile::read_slices`].
+    pub fn render_slice(
+        &self,
+        z: usize,
+        downsample: u32,
+        contrast: Contrast,
+        sampling: DownsampleMode,
+    ) -> Result<RgbImage, MrcError> {
+        let [nx, ny, nz] = self.header.dims();
+        if downsample == 0 {
+            return Err(MrcError::Format("Downsampling factor must be at least 1".to_string()));
+        }
+        if z >= nz.max(1) as usize {
+            return Err(MrcError::Format(format!("Slice {} is outside 0..{}", z, nz)));
+        }
+        let thumb_width = (nx as u32).div_ceil(downsample);
+        let thumb_height = (ny as u32).div_ceil(downsample);
 
-        // Calculate thumbnail dimensions
-        let thumb_width = (self.header.nx as u32 + downsample - 1) / downsample;
-        let thumb_height = (self.header.ny as u32 + downsample - 1) / downsample;
+        let downsampled = match self.header.mode {
+            // Samples don't start on byte boundaries, the stored rows are not X, or every
+            // pixel counts, so read the whole slice
+            mode if mode == MODE_UINT4 || !self.header.is_canonical_axes() || sampling == DownsampleMode::Mean => {
+                let slice = self.read_slices(z..z + 1)?;
+                sampling.apply(slice.as_slice().unwrap_or_default(), nx as usize, downsample as usize)
+            }
+            0 => self.sample_thumbnail::<sample::Int8>(z, downsample)?,
+            1 => self.sample_thumbnail::<sample::Int16>(z, downsample)?,
+            2 => self.sample_thumbnail::<sample::Float32>(z, downsample)?,
+            3 => self.sample_thumbnail::<sample::Complex16>(z, downsample)?,
+            4 => self.sample_thumbnail::<sample::Complex32>(z, downsample)?,
+            MODE_FLOAT64 => self.sample_thumbnail::<sample::Float64>(z, downsample)?,
+            6 => self.sample_thumbnail::<sample::UInt16>(z, downsample)?,
+            _ => return Err(MrcError::Format("Unsupported mode for thumbnails".to_string())),
+        };
+
+        let (min_val, max_val) = match (contrast, self.header.density) {
+            (Contrast::Header, DensityStats { min: Some(min), max: Some(max), .. }) => (min, max),
+            _ => contrast.limits(&downsampled),
+        };
+        Ok(grayscale_image(&downsampled, thumb_width, thumb_height, min_val, max_val))
+    }
+
+    /// Reads every `downsample`-th sample of every `downsample`-th row of slice `z`
+    fn sample_thumbnail<S: SampleReader>(&self, z: usize, downsample: u32) -> Result<Vec<f32>, MrcError> {
+        let mut file = self.reader();
+        let (Some(section_bytes), Some(bytes_per_pixel)) = (self.header.section_bytes(), self.header.bytes_per_pixel())
+        else {
+            return Err(MrcError::Format(format!("Unsupported mode {} for thumbnails", self.header.mode)));
+        };
+        let data_offset = self.header.data_offset() + z as u64 * section_bytes;
+        let bytes_per_pixel = bytes_per_pixel as u64;
+        let nx = self.header.nx as u64;
+
+        let mut downsampled = Vec::new();
+        for src_y in (0..self.header.ny as u64).step_by(downsample as usize) {
+            for src_x in (0..nx).step_by(downsample as usize) {
+                let offset = data_offset + bytes_per_pixel * (src_y * nx + src_x);
+                let value = match self.header.big_endian {
+                    true => S::read_at::<BigEndian, _>(&mut *file, offset)?,
+                    false => S::read_at::<LittleEndian, _>(&mut *file, offset)?,
+                };
+                downsampled.push(value);
+            }
+        }
+        Ok(downsampled)
+    }
+
+    /// Reads the Z sections in `z_range` as an array indexed (z, y, x). Sections are
+    /// stored back to back, so the whole block is read with one seek. Fails if the range
+    /// runs past the last section or does not fit under the memory limit.
+    ///
+    /// Maps with a non-canonical axis order are rearranged into X, Y, Z order. When the
+    /// stored sections are not along Z, that means reading the whole volume.
+    pub fn read_slices(&self, z_range: Range<usize>) -> Result<Array3<f32>, MrcError> {
+        self.read_slices_from(&mut *self.reader(), z_range)
+    }
+
+    /// [`MrcFile::read_slices`] reading the samples through `reader`, a handle on the file
+    fn read_slices_from<R: Read + Seek>(&self, reader: &mut R, z_range: Range<usize>) -> Result<Array3<f32>, MrcError> {
+        let [nx, ny, nz] = self.header.dims().map(|n| n.max(0) as usize);
+        if z_range.start > z_range.end || z_range.end > nz {
+            return Err(MrcError::Format(format!("Slice range {:?} is outside 0..{}", z_range, nz)));
+        }
+        // Sections holding the range, and which of them to keep once rearranged
+        let order = self.header.axis_order().unwrap_or([0, 1, 2]);
+        let (stored_range, keep) = match order[2] {
+            2 => (z_range.clone(), 0..z_range.len()),
+            _ => (0..self.header.nz.max(0) as usize, z_range.clone()),
+        };
+        let stored_len = stored_range.len() * self.header.nx.max(0) as usize * self.header.ny.max(0) as usize;
+        // The f32 output (and its rearranged copy) plus one section of stored samples
+        let copies = if order == [0, 1, 2] { 0 } else { stored_len * 4 };
+        self.check_memory(
+            z_range.len() * nx * ny * 4 + copies + self.header.section_bytes().unwrap_or(0) as usize,
+            "read fewer slices at a time",
+        )?;
+
+        let values = MrcFile::read_sections_f32(reader, &self.header, stored_range.start, stored_range.len())?;
+        let shape = (stored_range.len(), self.header.ny.max(0) as usize, self.header.nx.max(0) as usize);
+        let stored = Array3::from_shape_vec(shape, values).map_err(|e| MrcError::Format(e.to_string()))?;
+        if order == [0, 1, 2] {
+            return Ok(stored);
+        }
+        // Stored array axes are (sections, rows, columns); pick the one holding each of Z, Y, X
+        let position = |axis: usize| 2 - order.iter().position(|&a| a == axis).unwrap_or(axis);
+        let logical = stored.permuted_axes([position(2), position(1), position(0)]);
+        Ok(logical.slice(s![keep, .., ..]).as_standard_layout().into_owned())
+    }
+
+    /// Reads the whole volume as an array indexed (z, y, x); see [`MrcFile::read_slices`].
+    /// Modes 0, 1, 2, 5, 6 and 101 (4-bit) are supported.
+    pub fn read_volume(&self) -> Result<Array3<f32>, MrcError> {
+        self.read_slices(0..self.header.dims()[2].max(0) as usize)
+    }
+
+    /// Like [`MrcFile::read_volume`], also returning the BLAKE3 digest of the whole file,
+    /// as `b3sum` prints it. The voxel data is hashed as it is read, so only the header,
+    /// extended header and any trailing bytes are read again.
+    #[cfg(feature = "checksum")]
+    pub fn read_volume_with_digest(&self) -> Result<(Array3<f32>, blake3::Hash), MrcError> {
+        let mut reader = self.reader();
+        let mut hashing = checksum::HashingReader::new(&mut *reader);
+        let volume = self.read_slices_from(&mut hashing, 0..self.header.dims()[2].max(0) as usize)?;
+        Ok((volume, hashing.finish()?))
+    }
+
+    /// Reads one Z section as f32 values in row-major (y, x) order
+    pub(crate) fn read_slice_f32<R: Read + Seek>(
+        reader: &mut R,
+        header: &MrcHeader,
+        z: usize,
+    ) -> Result<Vec<f32>, MrcError> {
+        MrcFile::read_sections_f32(reader, header, z, 1)
+    }
+
+    /// Reads `sections` consecutive Z sections from `z` in one read, as f32 values in
+    /// row-major (z, y, x) order
+    fn read_sections_f32<R: Read + Seek>(
+        reader: &mut R,
+        header: &MrcHeader,
+        z: usize,
+        sections: usize,
+    ) -> Result<Vec<f32>, MrcError> {
+        let section_len = header.nx.max(0) as usize * header.ny.max(0) as usize;
+        let count = section_len * sections;
+        let section_bytes = match header.section_bytes() {
+            Some(bytes) if matches!(header.mode, 0 | 1 | 2 | MODE_FLOAT64 | 6 | MODE_UINT4) => bytes,
+            _ => return Err(MrcError::Format(format!("Unsupported mode {} for slice reads", header.mode))),
+        };
+        reader.seek(SeekFrom::Start(header.data_offset() + z as u64 * section_bytes))?;
+
+        let mut values = vec![0f32; count];
+        if header.mode == MODE_UINT4 {
+            read_nibbles(reader, &mut values, header.nx.max(0) as usize, section_len)?;
+        } else if header.big_endian {
+            read_samples::<BigEndian, _>(reader, header.mode, &mut values, section_len)?;
+        } else {
+            read_samples::<LittleEndian, _>(reader, header.mode, &mut values, section_len)?;
+        }
+        Ok(values)
+    }
+}
+
+/// Fills `values` with samples of `mode` stored in byte order `B`. One section-sized
+/// buffer of stored samples is converted into the output after each read, rather than a
+/// second copy of the whole block.
+fn read_samples<B: ByteOrder, R: Read>(
+    reader: &mut R,
+    mode: i32,
+    values: &mut [f32],
+    section_len: usize,
+) -> io::Result<()> {
+    match mode {
+        0 => read_converted(reader, values, section_len, |r, b| r.read_i8_into(b), |b: i8| b as f32),
+        1 => read_converted(reader, values, section_len, |r, b| r.read_i16_into::<B>(b), |b: i16| b as f32),
+        2 => reader.read_f32_into::<B>(values),
+        MODE_FLOAT64 => read_converted(reader, values, section_len, |r, b| r.read_f64_into::<B>(b), |b: f64| b as f32),
+        _ => read_converted(reader, values, section_len, |r, b| r.read_u16_into::<B>(b), |b: u16| b as f32),
+    }
+}
+
+/// Fills `values` with sections of `section_len` 4-bit samples, unpacking rows of `nx`
+/// values from `ceil(nx / 2)` bytes each, low nibble first
+fn read_nibbles<R: Read>(reader: &mut R, values: &mut [f32], nx: usize, section_len: usize) -> io::Result<()> {
+    if nx == 0 {
+        return Ok(());
+    }
+    let mut buffer = vec![0u8; section_len / nx * nx.div_ceil(2)];
+    for section in values.chunks_mut(section_len.max(1)) {
+        reader.read_exact(&mut buffer)?;
+        for (row, packed) in section.chunks_mut(nx).zip(buffer.chunks(nx.div_ceil(2))) {
+            for (x, value) in row.iter_mut().enumerate() {
+                let byte = packed[x / 2];
+                *value = if x % 2 == 0 { byte & 0x0F } else { byte >> 4 } as f32;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fills `values` with consecutive sections of `section_len` samples of `T`, reading each
+/// into the same buffer and converting it
+fn read_converted<R: Read, T: Copy + Default>(
+    reader: &mut R,
+    values: &mut [f32],
+    section_len: usize,
+    read: impl Fn(&mut R, &mut [T]) -> io::Result<()>,
+    convert: impl Fn(T) -> f32,
+) -> io::Result<()> {
+    let mut buffer = vec![T::default(); section_len];
+    for section in values.chunks_mut(section_len.max(1)) {
+        read(reader, &mut buffer[..section.len()])?;
+        section.iter_mut().zip(&buffer).for_each(|(v, &b)| *v = convert(b));
+    }
+    Ok(())
+}
+
+/// Linearly maps `values` from `[min_val, max_val]` to an 8-bit grayscale image
+/// Min and max over the finite values, so NaN/Inf in masked regions don't wreck the contrast.
+/// Returns (0, 0) when there are no finite values.
+pub(crate) fn finite_range(values: &[f32]) -> (f32, f32) {
+    let (min_val, max_val) = values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    if min_val > max_val {
+        (0.0, 0.0)
+    } else {
+        (min_val, max_val)
+    }
+}
+
+/// Maps values linearly from `min_val..=max_val` to grey levels, saturating outside that
+/// range; non-finite values are black
+pub(crate) fn grayscale_image(values: &[f32], width: u32, height: u32, min_val: f32, max_val: f32) -> RgbImage {
+    let range = max_val - min_val;
+    
+    // Create the thumbnail
+    let mut img = ImageBuffer::new(width, height);
+    
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let idx = (y * width + x) as usize;
+        let normalized = if range != 0.0 && values[idx].is_finite() {
+            ((values[idx] - min_val) / range).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
         
-        // Create buffer for downsampled data
-        let mut downsampled = vec![0.0f32; (thumb_width * thumb_height) as usize];
-        let mut min_val = f32::INFINITY;
-        let mut max_val = f32::NEG_INFINITY;
-
-        match self.header.mode {
-            0 => { // 8-bit signed
-                let mut buffer = [0i8; 1];
-                for y in 0..thumb_height {
-                    let src_y = (y * downsample) as usize;
-                    for x in 0..thumb_width {
-                        let src_x = (x * downsample) as usize;
-                        let offset = 1024 + (src_y * self.header.nx as usize + src_x);
-                        file.seek(SeekFrom::Start(offset as u64))?;
-                        file.read_exact(unsafe { std::slice::from_raw_parts_mut(&mut buffer[0] as *mut i8 as *mut u8, 1) })?;
-                        let value = buffer[0] as f32;
-                        min_val = min_val.min(value);
-                        max_val = max_val.max(value);
-                        downsampled[(y * thumb_width + x) as usize] = value;
-                    }
-                }
-            },
-            1 => { // 16-bit signed
-                let mut buffer = [0i16; 1];
-                for y in 0..thumb_height {
-                    let src_y = (y * downsample) as usize;
-                    for x in 0..thumb_width {
-                        let src_x = (x * downsample) as usize;
-                        let offset = 1024 + 2 * (src_y * self.header.nx as usize + src_x);
-                        file.seek(SeekFrom::Start(offset as u64))?;
-                        file.read_i16_into::<LittleEndian>(&mut buffer)?;
-                        let value = buffer[0] as f32;
-                        min_val = min_val.min(value);
-                        max_val = max_val.max(value);
-                        downsampled[(y * thumb_width + x) as usize] = value;
-                    }
+        let value = (normalized * 255.0) as u8;
+        *pixel = Rgb([value, value, value]);
+    }
+    
+    img
+}
+
+/// Encodes an image as PNG in memory
+pub fn encode_png(img: &RgbImage) -> Result<Vec<u8>, MrcError> {
+    let mut bytes = Vec::new();
+    img.write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)
+        .map_err(|e| MrcError::Io(io::Error::other(e)))?;
+    Ok(bytes)
+}
+
+/// Writes an image as PNG to `path`, or to stdout when `path` is `-`
+pub fn write_png(img: &RgbImage, path: &str) -> Result<(), MrcError> {
+    if path == "-" {
+        // Raw bytes: Rust's stdout does no newline translation, so this is binary-safe on Windows too
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&encode_png(img)?)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+    img.save(path).map_err(|e| MrcError::Io(io::Error::other(e)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a little-endian 1024-byte header with 1 A pixels and canonical axes
+    fn header_bytes(nx: i32, ny: i32, nz: i32, mode: i32) -> Vec<u8> {
+        let mut bytes = vec![0u8; 1024];
+        for (i, v) in [nx, ny, nz, mode].iter().enumerate() {
+            bytes[i * 4..i * 4 + 4].copy_from_slice(&v.to_le_bytes());
+        }
+        for (i, v) in [nx as f32, ny as f32, nz as f32, 90.0, 90.0, 90.0].iter().enumerate() {
+            bytes[40 + i * 4..44 + i * 4].copy_from_slice(&v.to_le_bytes());
+        }
+        for (i, v) in [1i32, 2, 3].iter().enumerate() {
+            bytes[64 + i * 4..68 + i * 4].copy_from_slice(&v.to_le_bytes());
+        }
+        bytes[208..212].copy_from_slice(b"MAP ");
+        bytes[212..214].copy_from_slice(&[0x44, 0x44]);
+        bytes
+    }
+
+    fn write_temp(name: &str, bytes: &[u8]) -> String {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_thumbnail_ignores_non_finite() {
+        let mut bytes = header_bytes(4, 1, 1, 2);
+        for v in [f32::NAN, 10.0, f32::INFINITY, 20.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let path = write_temp("emfir_thumbnail_nan.mrc", &bytes);
+        let mrc = MrcFile::open(&path).unwrap();
+
+        let img = mrc.render_thumbnail(1, Contrast::MinMax).unwrap();
+        let grey: Vec<u8> = img.pixels().map(|p| p[0]).collect();
+        assert_eq!(grey, vec![0, 0, 0, 255]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_thumbnail_modes() {
+        // 3x2 slice; 2x downsampling keeps the samples at (0, 0) and (2, 0)
+        let values = [-2.0, 9.0, 6.0, 2.0, 9.0, 9.0];
+        for mode in [0, 1, 2, MODE_FLOAT64, 6] {
+            let mut bytes = header_bytes(3, 2, 1, mode);
+            for v in values {
+                let v: f32 = if mode == 6 { v + 2.0 } else { v };
+                match mode {
+                    0 => bytes.push(v as i8 as u8),
+                    1 => bytes.extend_from_slice(&(v as i16).to_le_bytes()),
+                    2 => bytes.extend_from_slice(&v.to_le_bytes()),
+                    MODE_FLOAT64 => bytes.extend_from_slice(&(v as f64).to_le_bytes()),
+                    _ => bytes.extend_from_slice(&(v as u16).to_le_bytes()),
                 }
-            },
-            2 => { // 32-bit float
-                let mut buffer = [0.0f32; 1];
-                for y in 0..thumb_height {
-                    let src_y = (y * downsample) as usize;
-                    for x in 0..thumb_width {
-                        let src_x = (x * downsample) as usize;
-                        let offset = 1024 + 4 * (src_y * self.header.nx as usize + src_x);
-                        file.seek(SeekFrom::Start(offset as u64))?;
-                        file.read_f32_into::<LittleEndian>(&mut buffer)?;
-                        let value = buffer[0];
-                        min_val = min_val.min(value);
-                        max_val = max_val.max(value);
-                        downsampled[(y * thumb_width + x) as usize] = value;
-                    }
+            }
+            let path = write_temp("emfir_thumbnail_modes.mrc", &bytes);
+            let mrc = MrcFile::open(&path).unwrap();
+            let img = mrc.render_thumbnail(2, Contrast::MinMax).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(img.dimensions(), (2, 1), "mode {}", mode);
+            let grey: Vec<u8> = img.pixels().map(|p| p[0]).collect();
+            assert_eq!(grey, vec![0, 255], "mode {}", mode);
+        }
+    }
+
+    #[test]
+    fn test_auto_contrast_clips_outliers() {
+        let mut data: Vec<f32> = (0..10_000).map(|i| (i % 100) as f32).collect();
+        data[0] = -1.0e6;
+        data[1] = 1.0e6;
+        data[2] = f32::NAN;
+
+        let (low, high) = auto_contrast(&data);
+        // The outliers are clipped rather than stretching the range
+        assert!((-5.0..=0.0).contains(&low), "low = {}", low);
+        assert!((99.0..=105.0).contains(&high), "high = {}", high);
+        assert_eq!(Contrast::MinMax.limits(&data), (-1.0e6, 1.0e6));
+        assert_eq!(auto_contrast(&[3.0, 3.0]), (3.0, 3.0));
+    }
+
+    #[test]
+    fn test_percentile_estimate() {
+        // Skewed pseudo-random data with extreme outliers stretching the range
+        let mut state = 12345u32;
+        let mut data: Vec<f32> = (0..50_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                ((state >> 8) as f32 / (1 << 24) as f32).powi(3) * 1000.0
+            })
+            .collect();
+        data.extend([-1.0e7, 1.0e7, f32::NAN, f32::INFINITY]);
+
+        let mut sorted: Vec<f32> = data.iter().copied().filter(|v| v.is_finite()).collect();
+        sorted.sort_by(f32::total_cmp);
+        let tolerance = 2.0e7 / (1024.0 * 1024.0 * 1024.0);
+        for q in [0.0, 0.001, 0.01, 0.25, 0.5, 0.99, 0.999, 1.0] {
+            let exact = sorted[(q * (sorted.len() - 1) as f64).round() as usize];
+            let estimate = percentile(&data, q);
+            assert!((estimate - exact).abs() <= tolerance, "q = {}: {} vs {}", q, estimate, exact);
+        }
+        assert!(percentile(&[f32::NAN], 0.5).is_nan());
+
+        let contrast: Contrast = "percentile:1,99".parse().unwrap();
+        assert_eq!(contrast, Contrast::Percentile { low: 1.0, high: 99.0 });
+        assert!("percentile:99,1".parse::<Contrast>().is_err());
+        assert!("percentile:1".parse::<Contrast>().is_err());
+    }
+
+    #[test]
+    fn test_read_with_raw() {
+        let mut bytes = header_bytes(3, 2, 1, 1);
+        // An unparsed field, which only the raw bytes reveal
+        bytes[196..200].copy_from_slice(&42i32.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 12]);
+
+        let (header, raw) = MrcHeader::read_with_raw(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(raw[..], bytes[..1024]);
+        assert_eq!((header.nx, header.ny, header.nz, header.mode), (3, 2, 1, 1));
+        assert!(MrcHeader::read_with_raw(&mut Cursor::new(&bytes[..512])).is_err());
+    }
+
+    #[test]
+    fn test_float64_mode() {
+        let mut bytes = header_bytes(2, 2, 2, MODE_FLOAT64);
+        for v in [0.0f64, 1.0e-3, 2.0e-3, 4.0e-3, 1.0, 1.0, 1.0, 1.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let path = write_temp("emfir_float64.mrc", &bytes);
+        let mrc = MrcFile::open(&path).unwrap();
+        assert!(matches!(mrc.get_image_data().voxel_type, VoxelType::Float64));
+
+        let img = mrc.render_thumbnail(1, Contrast::MinMax).unwrap();
+        let grey: Vec<u8> = img.pixels().map(|p| p[0]).collect();
+        assert_eq!(grey, vec![0, 63, 127, 255]);
+        let sum = mrc.project(ProjectionMode::Sum, None).unwrap();
+        assert!((sum[3] - 1.004).abs() < 1e-6);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_downsampled_roundtrip() {
+        // 4x4x2, value = x + 10y + 100z
+        let mut bytes = header_bytes(4, 4, 2, 2);
+        for z in 0..2 {
+            for y in 0..4 {
+                for x in 0..4 {
+                    bytes.extend_from_slice(&((x + 10 * y + 100 * z) as f32).to_le_bytes());
                 }
-            },
-            6 => { // 16-bit unsigned
-                let mut buffer = [0u16; 1];
-                for y in 0..thumb_height {
-                    let src_y = (y * downsample) as usize;
-                    for x in 0..thumb_width {
-                        let src_x = (x * downsample) as usize;
-                        let offset = 1024 + 2 * (src_y * self.header.nx as usize + src_x);
-                        file.seek(SeekFrom::Start(offset as u64))?;
-                        file.read_u16_into::<LittleEndian>(&mut buffer)?;
-                        let value = buffer[0] as f32;
-                        min_val = min_val.min(value);
-                        max_val = max_val.max(value);
-                        downsampled[(y * thumb_width + x) as usize] = value;
-                    }
+            }
+        }
+        let path = write_temp("emfir_downsample_in.mrc", &bytes);
+        let out = std::env::temp_dir().join("emfir_downsample_out.mrc").to_string_lossy().into_owned();
+        MrcFile::open(&path).unwrap().write_downsampled(&out, 2, 2).unwrap();
+
+        let small = MrcFile::open(&out).unwrap();
+        assert_eq!((small.header.nx, small.header.ny, small.header.nz, small.header.mode), (2, 2, 1, 2));
+        assert_eq!(small.header.pixel_size, [2.0; 3]);
+        let values = MrcFile::read_slice_f32(&mut *small.reader(), &small.header, 0).unwrap();
+        assert_eq!(values, vec![55.5, 57.5, 75.5, 77.5]);
+
+        // mx/my/mz and dmin/dmax/dmean
+        let raw = std::fs::read(&out).unwrap();
+        let word = |i: usize| <[u8; 4]>::try_from(&raw[i * 4..i * 4 + 4]).unwrap();
+        assert_eq!([7, 8, 9].map(|i| i32::from_le_bytes(word(i))), [2, 2, 1]);
+        assert_eq!([19, 20, 21].map(|i| f32::from_le_bytes(word(i))), [55.5, 77.5, 66.5]);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&out).unwrap();
+    }
+
+    #[test]
+    fn test_nan_density_stats() {
+        let mut bytes = header_bytes(2, 2, 1, 2);
+        for (offset, v) in [(76, -1.0f32), (80, 3.0), (84, f32::NAN), (216, f32::NAN)] {
+            bytes[offset..offset + 4].copy_from_slice(&v.to_le_bytes());
+        }
+        let header = MrcHeader::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(header.density, DensityStats { min: Some(-1.0), max: Some(3.0), mean: None, rms: None });
+
+        let json = serde_json::to_string(&ImageData::from_mrc(&header)).unwrap();
+        assert!(!json.contains("NaN"));
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["density"]["max"], 3.0);
+        assert!(value["density"]["mean"].is_null());
+
+        // dmax < dmin marks the range as undetermined
+        bytes[80..84].copy_from_slice(&(-2.0f32).to_le_bytes());
+        let header = MrcHeader::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!((header.density.min, header.density.max), (None, None));
+    }
+
+    #[test]
+    fn test_header_density_contrast() {
+        let mut bytes = header_bytes(4, 1, 1, 2);
+        for (offset, v) in [(76, 0.0f32), (80, 100.0), (84, 40.0), (216, 12.5)] {
+            bytes[offset..offset + 4].copy_from_slice(&v.to_le_bytes());
+        }
+        for v in [25.0f32, 0.0, 75.0, 100.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let path = write_temp("emfir_header_contrast.mrc", &bytes);
+        let mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let density = mrc.header().density();
+        assert_eq!(density, DensityStats { min: Some(0.0), max: Some(100.0), mean: Some(40.0), rms: Some(12.5) });
+        // Every other pixel misses the extremes, which only the header range knows about
+        let gray = |contrast| mrc.render_thumbnail(2, contrast).unwrap().pixels().map(|p| p[0]).collect::<Vec<_>>();
+        assert_eq!(gray(Contrast::MinMax), vec![0, 255]);
+        assert_eq!(gray(Contrast::Header), vec![63, 191]);
+        assert_eq!("header".parse::<Contrast>().unwrap(), Contrast::Header);
+    }
+
+    #[test]
+    fn test_save_slice() {
+        // Slice 0 is flat, 1 a ramp along x and 2 a ramp along y
+        let mut bytes = header_bytes(2, 2, 3, 2);
+        let values = [0.0f32, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0];
+        bytes.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+        let path = write_temp("emfir_save_slice.mrc", &bytes);
+        let mrc = MrcFile::open(&path).unwrap();
+
+        let png = |z| {
+            let out = std::env::temp_dir().join(format!("emfir_save_slice_{}.png", z));
+            mrc.save_slice(&out.to_string_lossy(), z, 1).unwrap();
+            let img = image::open(&out).unwrap().to_luma8();
+            std::fs::remove_file(&out).unwrap();
+            img.into_raw()
+        };
+        assert_eq!(png(1), vec![0, 255, 0, 255]);
+        assert_eq!(png(2), vec![0, 0, 255, 255]);
+        let first = mrc.render_slice(0, 1, Contrast::MinMax, DownsampleMode::Nearest).unwrap();
+        assert_eq!(first, mrc.render_thumbnail(1, Contrast::MinMax).unwrap());
+        assert!(matches!(mrc.save_slice("unused.png", 3, 1), Err(MrcError::Format(_))));
+        assert!(matches!(mrc.save_slice("unused.png", 0, 0), Err(MrcError::Format(_))));
+        assert!(matches!(mrc.save_projection("unused.png", ProjectionMode::Max, 0), Err(MrcError::Format(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mean_downsampling() {
+        // A 4x3 checkerboard of 0 and 100: every 2x2 block averages to 50, as do the
+        // partial blocks along the bottom edge, while every other pixel is always 0
+        let mut bytes = header_bytes(4, 3, 1, 2);
+        let values: Vec<f32> = (0..12).map(|i| if (i % 4 + i / 4) % 2 == 0 { 0.0 } else { 100.0 }).collect();
+        bytes.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+        let path = write_temp("emfir_mean_downsampling.mrc", &bytes);
+        let mrc = MrcFile::open(&path).unwrap();
+
+        assert_eq!(DownsampleMode::Mean.apply(&values, 4, 2), vec![50.0, 50.0, 50.0, 50.0]);
+        assert_eq!(DownsampleMode::Nearest.apply(&values, 4, 2), vec![0.0, 0.0, 0.0, 0.0]);
+        let mean = mrc.render_slice(0, 2, Contrast::MinMax, DownsampleMode::Mean).unwrap();
+        assert_eq!(mean.dimensions(), (2, 2));
+        assert!(mean.pixels().all(|p| p == mean.get_pixel(0, 0)));
+        let projected = mrc.render_projection(ProjectionMode::Max, 2, Contrast::MinMax, DownsampleMode::Mean, None);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(projected.unwrap().dimensions(), (2, 2));
+
+        let mut odd = vec![0.0; 9];
+        odd[8] = 90.0;
+        assert_eq!(DownsampleMode::Mean.apply(&odd, 3, 2), vec![0.0, 0.0, 0.0, 90.0]);
+        assert_eq!("mean".parse::<DownsampleMode>(), Ok(DownsampleMode::Mean));
+        assert_eq!(DownsampleMode::default(), DownsampleMode::Nearest);
+    }
+
+    #[test]
+    fn test_map_stamp_required() {
+        let mut bytes = header_bytes(2, 1, 1, 2);
+        bytes[208..212].copy_from_slice(b"\0\0\0\0");
+        bytes.extend([1.0f32, 2.0].iter().flat_map(|v| v.to_le_bytes()));
+        let path = write_temp("emfir_no_map_stamp.mrc", &bytes);
+        let strict = MrcFile::open(&path);
+        let mut permissive = MrcFile::open_permissive(&path).unwrap();
+        permissive.reopen().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let Err(MrcError::Format(message)) = strict else { panic!("expected a format error") };
+        assert!(message.contains("MAP"), "{}", message);
+        assert_eq!(permissive.read_volume().unwrap().into_raw_vec_and_offset().0, vec![1.0, 2.0]);
+        assert!(MrcHeader::read(&mut Cursor::new(vec![0x5a; 1024])).is_err());
+    }
+
+    #[test]
+    fn test_start_and_origin() {
+        let mut bytes = header_bytes(2, 1, 1, 2);
+        for (i, v) in [-8i32, 16, 4].iter().enumerate() {
+            bytes[16 + i * 4..20 + i * 4].copy_from_slice(&v.to_le_bytes());
+        }
+        for (i, v) in [12.5f32, -3.0, 100.25].iter().enumerate() {
+            bytes[196 + i * 4..200 + i * 4].copy_from_slice(&v.to_le_bytes());
+        }
+        let header = MrcHeader::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(header.start(), [-8, 16, 4]);
+        assert_eq!(header.origin(), [12.5, -3.0, 100.25]);
+        // Neighbouring fields are unaffected
+        assert_eq!((header.mode, header.cell_dims[0], &header.map_stamp), (2, 2.0, b"MAP "));
+
+        let json = serde_json::to_value(ImageData::from_mrc(&header)).unwrap();
+        assert_eq!(json["start"], serde_json::json!([-8, 16, 4]));
+        assert_eq!(json["origin"], serde_json::json!([12.5, -3.0, 100.25]));
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut bytes = header_bytes(2, 1, 1, 2);
+        bytes[220..224].copy_from_slice(&2i32.to_le_bytes());
+        bytes[224..224 + 21].copy_from_slice(b"EMfir 0.1  2024-03-01");
+        let note = "Operator: Zo\u{eb} \u{c5}ngstr\u{f6}m";
+        bytes[304..304 + note.len()].copy_from_slice(note.as_bytes());
+        bytes[304 + note.len()..384].fill(b' ');
+        bytes.extend([1.0f32, 2.0].iter().flat_map(|v| v.to_le_bytes()));
+        let path = write_temp("emfir_labels.mrc", &bytes);
+        let mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mrc.header().labels(), ["EMfir 0.1  2024-03-01", note]);
+        let json = serde_json::to_value(mrc.get_image_data()).unwrap();
+        assert_eq!(json["labels"][1], note);
+
+        let unlabelled = MrcHeader::read(&mut Cursor::new(header_bytes(2, 1, 1, 2))).unwrap();
+        assert!(unlabelled.labels().is_empty());
+        assert!(serde_json::to_value(ImageData::from_mrc(&unlabelled)).unwrap().get("labels").is_none());
+    }
+
+    #[test]
+    fn test_map_axis_order() {
+        // 3 columns along Y, 4 rows along X and 2 sections along Z, in an 8x6x2 A cell
+        let mut bytes = header_bytes(3, 4, 2, 2);
+        for (i, v) in [8.0f32, 6.0, 2.0].iter().enumerate() {
+            bytes[40 + i * 4..44 + i * 4].copy_from_slice(&v.to_le_bytes());
+        }
+        for (i, v) in [2i32, 1, 3].iter().enumerate() {
+            bytes[64 + i * 4..68 + i * 4].copy_from_slice(&v.to_le_bytes());
+        }
+        let stored: Vec<f32> = (0..24).map(|v| v as f32).collect();
+        bytes.extend(stored.iter().flat_map(|v| v.to_le_bytes()));
+        let path = write_temp("emfir_map_axis.mrc", &bytes);
+        let mrc = MrcFile::open(&path).unwrap();
+
+        assert!(!mrc.header.is_canonical_axes());
+        assert_eq!(mrc.header.dims(), [4, 3, 2]);
+        assert_eq!(mrc.header.pixel_size, [2.0, 2.0, 1.0]);
+        assert_eq!(mrc.get_image_data().size_x, 4);
+        let transposed = Array3::from_shape_fn((2, 3, 4), |(z, y, x)| stored[z * 12 + x * 3 + y]);
+        assert_eq!(mrc.read_volume().unwrap(), transposed);
+        assert_eq!(mrc.read_slices(1..2).unwrap(), transposed.slice(s![1..2, .., ..]));
+
+        // The thumbnail matches one of the same slice written in canonical order
+        let mut canonical = header_bytes(4, 3, 2, 2);
+        canonical.extend(transposed.iter().flat_map(|v| v.to_le_bytes()));
+        let canonical_path = write_temp("emfir_map_axis_canonical.mrc", &canonical);
+        let reference = MrcFile::open(&canonical_path).unwrap();
+        std::fs::remove_file(&canonical_path).unwrap();
+        assert!(reference.header.is_canonical_axes());
+        for downsample in [1, 2] {
+            assert_eq!(
+                mrc.render_thumbnail(downsample, Contrast::MinMax).unwrap(),
+                reference.render_thumbnail(downsample, Contrast::MinMax).unwrap(),
+            );
+        }
+
+        // Sections along Y: columns are Z and rows are X
+        for (i, v) in [3i32, 1, 2].iter().enumerate() {
+            bytes[64 + i * 4..68 + i * 4].copy_from_slice(&v.to_le_bytes());
+        }
+        std::fs::write(&path, &bytes).unwrap();
+        let mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mrc.header.dims(), [4, 2, 3]);
+        let permuted = Array3::from_shape_fn((3, 2, 4), |(z, y, x)| stored[y * 12 + x * 3 + z]);
+        assert_eq!(mrc.read_slices(1..3).unwrap(), permuted.slice(s![1..3, .., ..]));
+    }
+
+    #[test]
+    fn test_zero_grid_dimensions() {
+        for (nx, ny) in [(0, 4), (4, 0)] {
+            let err = MrcHeader::read(&mut Cursor::new(header_bytes(nx, ny, 1, 2))).unwrap_err();
+            assert!(matches!(err, MrcError::Format(_)), "{}x{}: {:?}", nx, ny, err);
+        }
+
+        // A cell depth over no sections used to give an infinite spacing
+        let mut bytes = header_bytes(4, 2, 0, 2);
+        bytes[48..52].copy_from_slice(&5.0f32.to_le_bytes());
+        let header = MrcHeader::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(header.pixel_size, [1.0, 1.0, 0.0]);
+        let json = serde_json::to_value(ImageData::from_mrc(&header)).unwrap();
+        assert_eq!(json["voxel_spacing_z"], 0.0);
+    }
+
+    #[test]
+    fn test_bytes_per_sample() {
+        // mode, bytes per sample, samples per pixel
+        let table = [(0, 1, 1), (1, 2, 1), (2, 4, 1), (3, 2, 2), (4, 4, 2), (MODE_FLOAT64, 8, 1), (6, 2, 1)];
+        for (mode, bytes, samples) in table {
+            let header = MrcHeader::read(&mut Cursor::new(header_bytes(2, 2, 1, mode))).unwrap();
+            assert_eq!((header.bytes_per_sample(), header.samples_per_pixel()), (Some(bytes), samples), "mode {}", mode);
+            assert_eq!(header.bytes_per_pixel(), Some(bytes * samples));
+        }
+        // 4-bit samples have no byte size of their own, but sections do
+        let header = MrcHeader::read(&mut Cursor::new(header_bytes(3, 2, 1, MODE_UINT4))).unwrap();
+        assert_eq!((header.bytes_per_sample(), header.section_bytes()), (None, Some(4)));
+        let header = MrcHeader { mode: 42, ..header };
+        assert_eq!((header.bytes_per_pixel(), header.section_bytes()), (None, None));
+        assert_eq!(sample_layout(12), Some((2, 1)));
+        assert_eq!(sample_layout(101), None);
+    }
+
+    #[test]
+    fn test_read_slices() {
+        // 3x2 sections holding 10 * z + i
+        let mut bytes = header_bytes(3, 2, 4, 1);
+        bytes.extend((0..4).flat_map(|z| (0..6).flat_map(move |i| (10 * z + i as i16).to_le_bytes())));
+        let path = write_temp("emfir_read_slices.mrc", &bytes);
+        let mut mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let block = mrc.read_slices(1..3).unwrap();
+        assert_eq!(block.dim(), (2, 2, 3));
+        assert_eq!((block[[0, 0, 0]], block[[1, 1, 2]]), (10.0, 25.0));
+        for z in 1..3 {
+            let single = mrc.read_slices(z..z + 1).unwrap();
+            assert_eq!(single.index_axis(ndarray::Axis(0), 0), block.index_axis(ndarray::Axis(0), z - 1));
+        }
+        assert_eq!(mrc.read_slices(4..4).unwrap().dim(), (0, 2, 3));
+
+        assert!(matches!(mrc.read_slices(3..5), Err(MrcError::Format(_))));
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = mrc.read_slices(2..1);
+        assert!(matches!(reversed, Err(MrcError::Format(_))));
+        mrc.set_memory_limit(Some(6 * 4 + 6 * 2));
+        assert!(mrc.read_slices(0..1).is_ok());
+        assert!(matches!(mrc.read_slices(0..2), Err(MrcError::MemoryLimit { .. })));
+    }
+
+    #[test]
+    fn test_read_volume() {
+        let volume = Array3::from_shape_fn((2, 3, 4), |(z, y, x)| (100 * z + 10 * y + x) as f32 - 50.5);
+        let mut bytes = header_bytes(4, 3, 2, 2);
+        bytes[92..96].copy_from_slice(&8i32.to_le_bytes());
+        bytes.extend([0xEE; 8]);
+        bytes.extend(volume.iter().flat_map(|v| v.to_le_bytes()));
+        let path = write_temp("emfir_read_volume.mrc", &bytes);
+        let mut mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mrc.read_volume().unwrap(), volume);
+        mrc.set_memory_limit(Some(100));
+        assert!(matches!(mrc.read_volume(), Err(MrcError::MemoryLimit { .. })));
+
+        #[cfg(feature = "checksum")]
+        {
+            // Trailing bytes after the data are part of the digest too
+            bytes.extend([0x5A; 5]);
+            let path = write_temp("emfir_read_volume_digest.mrc", &bytes);
+            let mrc = MrcFile::open(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let (read, digest) = mrc.read_volume_with_digest().unwrap();
+            assert_eq!((read, digest), (volume, blake3::hash(&bytes)));
+        }
+
+        let mut complex = header_bytes(1, 1, 1, 3);
+        complex.extend([0; 4]);
+        let path = write_temp("emfir_read_volume_complex.mrc", &complex);
+        let mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(mrc.read_volume(), Err(MrcError::Format(_))));
+    }
+
+    #[test]
+    fn test_complex_thumbnail() {
+        let mut bytes = header_bytes(2, 2, 1, 4);
+        for v in [3.0f32, 4.0, 0.0, -1.0, 6.0, 8.0, 0.0, 0.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let path = write_temp("emfir_complex.mrc", &bytes);
+        let mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(mrc.get_image_data().voxel_type, VoxelType::Complex32));
+        // Moduli 5, 1, 10, 0 stretched over 0..10
+        let img = mrc.render_thumbnail(1, Contrast::MinMax).unwrap();
+        let gray: Vec<u8> = img.pixels().map(|p| p[0]).collect();
+        assert_eq!(gray, vec![127, 25, 255, 0]);
+    }
+
+    #[test]
+    fn test_packed_4bit() {
+        // Rows of 3 values take 2 bytes, the high nibble of the second byte unused
+        let mut bytes = header_bytes(3, 2, 2, MODE_UINT4);
+        bytes.extend([0x21, 0xF3, 0x54, 0x06, 0xA9, 0x0B, 0xDC, 0x0E]);
+        let path = write_temp("emfir_uint4.mrc", &bytes);
+        let mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(mrc.get_image_data().voxel_type, VoxelType::UInt4));
+        assert_eq!(mrc.header.section_bytes(), Some(4));
+        let volume = mrc.read_volume().unwrap();
+        let expected: Vec<f32> = (1..=6).chain(9..=14).map(|v| v as f32).collect();
+        assert_eq!(volume.iter().copied().collect::<Vec<_>>(), expected);
+        assert_eq!(mrc.read_slices(1..2).unwrap()[[0, 1, 2]], 14.0);
+
+        let img = mrc.render_thumbnail(2, Contrast::MinMax).unwrap();
+        assert_eq!(img.dimensions(), (2, 1));
+        // Values 1 and 3 from the first row
+        assert_eq!(img.pixels().map(|p| p[0]).collect::<Vec<_>>(), vec![0, 255]);
+    }
+
+    #[test]
+    fn test_infer_nz() {
+        let mut bytes = header_bytes(2, 2, 0, 2);
+        // Cell z of 6 A over the 3 sections present, plus a partial fourth section
+        bytes[48..52].copy_from_slice(&6.0f32.to_le_bytes());
+        bytes.extend((0..14).flat_map(|i| (i as f32).to_le_bytes()));
+        let path = write_temp("emfir_infer_nz.mrc", &bytes);
+        let mrc = MrcFile::open(&path).unwrap();
+        assert_eq!(mrc.header.nz, 3);
+        assert_eq!(mrc.header.pixel_size[2], 2.0);
+        assert_eq!(mrc.get_image_data().size_z, 3);
+        assert_eq!(MrcFile::read_slice_f32(&mut *mrc.reader(), &mrc.header, 2).unwrap()[3], 11.0);
+
+        let path = write_temp("emfir_infer_nz.mrc", &bytes[..1024 + 8]);
+        assert!(matches!(MrcFile::open(&path), Err(MrcError::Format(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_nonstandard_fields() {
+        let bytes = header_bytes(2, 2, 1, 2);
+        let header = MrcHeader::read(&mut Cursor::new(&bytes)).unwrap();
+        assert!(header.nonstandard_fields().is_empty());
+
+        // Unset (all zero) cell angles are as orthogonal as 90 degrees; others are reported
+        let mut bytes = header_bytes(2, 2, 1, 2);
+        bytes[52..64].fill(0);
+        let header = MrcHeader::read(&mut Cursor::new(&bytes)).unwrap();
+        assert!(header.nonstandard_fields().is_empty());
+        bytes[52..56].copy_from_slice(&60f32.to_le_bytes());
+        let header = MrcHeader::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(header.nonstandard_fields()[0].0, "cell_angles");
+
+        let mut bytes = header_bytes(2, 2, 1, 2);
+        bytes[64..68].copy_from_slice(&2i32.to_le_bytes());
+        bytes[68..72].copy_from_slice(&1i32.to_le_bytes());
+        bytes[96..100].copy_from_slice(&1i32.to_le_bytes());
+        bytes[208..212].copy_from_slice(&[0; 4]);
+        let header = MrcHeader::read_permissive(&mut Cursor::new(&bytes)).unwrap();
+        let names: Vec<&str> = header.nonstandard_fields().iter().map(|f| f.0).collect();
+        assert_eq!(names, vec!["map_axis", "lskflg", "skew", "map"]);
+    }
+
+    #[test]
+    fn test_image_stack_z_spacing() {
+        // Space group 0: a stack of three 2 A images
+        let mut bytes = header_bytes(2, 2, 3, 2);
+        bytes[40..52].copy_from_slice(&[4.0f32, 4.0, 6.0].map(f32::to_le_bytes).concat());
+        let header = MrcHeader::read(&mut Cursor::new(&bytes)).unwrap();
+        assert!(header.is_image_stack());
+        let json = serde_json::to_value(ImageData::from_mrc(&header)).unwrap();
+        assert_eq!((json["voxel_spacing_x"].as_f64(), json["voxel_spacing_y"].as_f64()), (Some(2.0), Some(2.0)));
+        assert!(json["voxel_spacing_z"].is_null());
+
+        // Space group 1: a volume with 2 A sections
+        bytes[88..92].copy_from_slice(&1i32.to_le_bytes());
+        let header = MrcHeader::read(&mut Cursor::new(&bytes)).unwrap();
+        assert!(!header.is_image_stack());
+        assert_eq!(serde_json::to_value(ImageData::from_mrc(&header)).unwrap()["voxel_spacing_z"], 2.0);
+
+        // A single image isn't a stack
+        let header = MrcHeader::read(&mut Cursor::new(header_bytes(2, 2, 1, 2))).unwrap();
+        assert!(!header.is_image_stack());
+    }
+
+    #[test]
+    fn test_non_orthogonal_cell() {
+        let mut bytes = header_bytes(2, 2, 1, 2);
+        bytes[60..64].copy_from_slice(&120.0f32.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 16]);
+        let path = write_temp("emfir_non_orthogonal.mrc", &bytes);
+        let mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(!mrc.header().is_orthogonal());
+        assert_eq!(mrc.header().cell_angles(), [90.0, 90.0, 120.0]);
+        let json = serde_json::to_value(mrc.get_image_data()).unwrap();
+        assert_eq!(json["cell_angles"], serde_json::json!([90.0, 90.0, 120.0]));
+
+        // Orthogonal and unset angles stay out of the JSON
+        let header = MrcHeader::read(&mut Cursor::new(header_bytes(2, 2, 1, 2))).unwrap();
+        assert!(header.is_orthogonal());
+        assert!(serde_json::to_value(ImageData::from_mrc(&header)).unwrap().get("cell_angles").is_none());
+        let mut bytes = header_bytes(2, 2, 1, 2);
+        bytes[52..64].fill(0);
+        assert!(MrcHeader::read(&mut Cursor::new(bytes)).unwrap().is_orthogonal());
+    }
+
+    #[test]
+    fn test_skew_transform() {
+        let mut bytes = header_bytes(2, 2, 1, 2);
+        bytes[96..100].copy_from_slice(&1i32.to_le_bytes());
+        for (i, v) in [1.0f32, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0].iter().enumerate() {
+            bytes[100 + i * 4..104 + i * 4].copy_from_slice(&v.to_le_bytes());
+        }
+        let header = MrcHeader::read(&mut Cursor::new(&bytes)).unwrap();
+        assert!(header.skew().unwrap().is_identity());
+        let names: Vec<&str> = header.nonstandard_fields().iter().map(|f| f.0).collect();
+        assert_eq!(names, vec!["lskflg"]);
+
+        bytes[104..108].copy_from_slice(&0.5f32.to_le_bytes());
+        bytes[140..144].copy_from_slice(&3.0f32.to_le_bytes());
+        let header = MrcHeader::read(&mut Cursor::new(&bytes)).unwrap();
+        let skew = header.skew().unwrap();
+        assert_eq!((skew.matrix[0], skew.translation), ([1.0, 0.5, 0.0], [0.0, 3.0, 0.0]));
+        assert!(header.nonstandard_fields().iter().any(|f| f.0 == "skew"));
+
+        // Without the flag the bytes are ignored
+        bytes[96..100].copy_from_slice(&0i32.to_le_bytes());
+        assert!(MrcHeader::read(&mut Cursor::new(&bytes)).unwrap().skew().is_none());
+    }
+
+    #[test]
+    fn test_projection_reports_progress() {
+        let mut bytes = header_bytes(2, 1, 3, 1);
+        for v in [1i16, 5, 2, 4, 3, 3] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let path = write_temp("emfir_projection_progress.mrc", &bytes);
+        let mrc = MrcFile::open(&path).unwrap();
+
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        let progress = |_done: usize, total: usize| {
+            assert_eq!(total, 3);
+            calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        };
+        let max = mrc.project(ProjectionMode::Max, Some(&progress)).unwrap();
+        let sum = mrc.project(ProjectionMode::Sum, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(calls.into_inner(), 3);
+        assert_eq!(max, vec![3.0, 5.0]);
+        assert_eq!(sum, vec![6.0, 12.0]);
+    }
+
+    #[test]
+    fn test_constant_volume_projections() {
+        // Integer sums past the range of the stored type
+        for (mode, value) in [(1, 30000.0), (6, 60000.0), (2, 2.5)] {
+            let mut bytes = header_bytes(3, 2, 4, mode);
+            for _ in 0..24 {
+                match mode {
+                    1 => bytes.extend_from_slice(&(value as i16).to_le_bytes()),
+                    6 => bytes.extend_from_slice(&(value as u16).to_le_bytes()),
+                    _ => bytes.extend_from_slice(&(value as f32).to_le_bytes()),
                 }
-            },
-            _ => return Err(MrcError::Format("Unsupported mode for thumbnails".to_string())),
+            }
+            let path = write_temp(&format!("emfir_constant_projection_{}.mrc", mode), &bytes);
+            let mrc = MrcFile::open(&path).unwrap();
+            // Parallel projections open their own handles, so the file must outlive them
+            let [mean, max, sum] = [ProjectionMode::Mean, ProjectionMode::Max, ProjectionMode::Sum]
+                .map(|projection| mrc.project(projection, None).unwrap());
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(mean, vec![value; 6], "mode {}", mode);
+            assert_eq!(max, vec![value; 6], "mode {}", mode);
+            assert_eq!(sum, vec![value * 4.0; 6], "mode {}", mode);
         }
+    }
 
-        let range = max_val - min_val;
-        
-        // Create the thumbnail
-        let mut img = ImageBuffer::new(thumb_width, thumb_height);
-        
-        for (x, y, pixel) in img.enumerate_pixels_mut() {
-            let idx = (y * thumb_width + x) as usize;
-            let normalized = if range != 0.0 {
-                (downsampled[idx] - min_val) / range
+    #[test]
+    fn test_open_errors() {
+        let missing = std::env::temp_dir().join("emfir_missing.mrc").to_string_lossy().into_owned();
+        assert!(matches!(MrcFile::open(&missing), Err(MrcError::NotFound(_))));
+
+        let empty = write_temp("emfir_empty.mrc", &[]);
+        let err = MrcFile::open(&empty).err().unwrap();
+        std::fs::remove_file(&empty).unwrap();
+        assert!(matches!(err, MrcError::EmptyFile(_)));
+        assert_eq!(err.to_string(), format!("File is empty: {}", empty));
+
+        // Running as root ignores file modes, so check the mapping directly
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert!(matches!(MrcError::from_open("x.mrc", denied), MrcError::PermissionDenied(_)));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut bytes = header_bytes(2, 1, 3, 1);
+        for v in [1i16, 5, 2, 4, 3, 3] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let path = write_temp("emfir_memory_limit.mrc", &bytes);
+        let mut mrc = MrcFile::open(&path).unwrap();
+
+        // Room for one section and accumulator only, so the projection runs sequentially
+        mrc.set_memory_limit(Some(24));
+        assert_eq!(mrc.project(ProjectionMode::Sum, None).unwrap(), vec![6.0, 12.0]);
+        let out = std::env::temp_dir().join("emfir_memory_limit_out.mrc").to_string_lossy().into_owned();
+        assert!(matches!(mrc.write_downsampled(&out, 1, 2), Err(MrcError::MemoryLimit { .. })));
+
+        mrc.set_memory_limit(Some(16));
+        mrc.reopen().unwrap();
+        let err = mrc.project(ProjectionMode::Sum, None).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, MrcError::MemoryLimit { needed: 24, limit: 16, .. }));
+    }
+
+    #[test]
+    fn test_label_pixel_size_fallback() {
+        let mut bytes = header_bytes(4, 4, 1, 0);
+        bytes[40..52].fill(0);
+        bytes[220..224].copy_from_slice(&2i32.to_le_bytes());
+        bytes[224..237].copy_from_slice(b"Created by X ");
+        bytes[304..326].copy_from_slice(b"Pixel size: 1.35 A    ");
+        bytes.extend_from_slice(&[0u8; 16]);
+        let path = write_temp("emfir_label_pixel_size.mrc", &bytes);
+
+        let mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mrc.header.labels, vec!["Created by X", "Pixel size: 1.35 A"]);
+        assert_eq!(mrc.get_image_data().voxel_spacing_x, 1.35);
+        assert_eq!(mrc.get_image_data().voxel_spacing_y, 1.35);
+
+        assert_eq!(parse_label_pixel_size("pixel size = 0.2 nm"), Some(2.0));
+        assert_eq!(parse_label_pixel_size("no size here"), None);
+    }
+
+    #[test]
+    fn test_data_offset_override() {
+        // 16 undeclared bytes between the header and the data
+        let mut bytes = header_bytes(2, 1, 1, 2);
+        bytes.extend_from_slice(&[0xff; 16]);
+        for v in [1.0f32, 3.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let path = write_temp("emfir_data_offset.mrc", &bytes);
+        let mut mrc = MrcFile::open(&path).unwrap();
+        assert!(mrc.set_data_offset_override(Some(bytes.len() as u64)).is_err());
+        mrc.set_data_offset_override(Some(1040)).unwrap();
+        assert_eq!(MrcFile::read_slice_f32(&mut *mrc.reader(), &mrc.header, 0).unwrap(), vec![1.0, 3.0]);
+        mrc.reopen().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mrc.header.data_offset(), 1040);
+        mrc.set_data_offset_override(None).unwrap();
+        assert_eq!(mrc.header.data_offset(), 1024);
+    }
+
+    #[test]
+    fn test_big_endian() {
+        let mut little = header_bytes(3, 2, 2, 1);
+        little[220..224].copy_from_slice(&1i32.to_le_bytes());
+        little[224..232].copy_from_slice(b"big test");
+        for v in [-300i16, 0, 7, 1, 2, 3, 10, 20, 30, 40, 50, 32000] {
+            little.extend_from_slice(&v.to_le_bytes());
+        }
+        // The same file with every numeric header word and sample byte-swapped
+        let mut big = little.clone();
+        for range in [0..104, 108..208, 216..224] {
+            big[range].chunks_exact_mut(4).for_each(<[u8]>::reverse);
+        }
+        big[212..214].copy_from_slice(&[0x11, 0x11]);
+        big[1024..].chunks_exact_mut(2).for_each(<[u8]>::reverse);
+        let little_path = write_temp("emfir_little_endian.mrc", &little);
+        let big_path = write_temp("emfir_big_endian.mrc", &big);
+
+        let little = MrcFile::open(&little_path).unwrap();
+        let big = MrcFile::open(&big_path).unwrap();
+        std::fs::remove_file(&little_path).unwrap();
+        std::fs::remove_file(&big_path).unwrap();
+
+        assert!(big.header().is_big_endian() && !little.header().is_big_endian());
+        let dims = |mrc: &MrcFile| (mrc.header.nx, mrc.header.ny, mrc.header.nz, mrc.header.mode, mrc.header.pixel_size);
+        assert_eq!(dims(&big), dims(&little));
+        assert_eq!((big.header.map_axis, &big.header.labels), (little.header.map_axis, &little.header.labels));
+        assert!(big.header().nonstandard_fields().is_empty());
+        assert_eq!(big.read_slices(0..2).unwrap(), little.read_slices(0..2).unwrap());
+        assert_eq!(big.read_slices(1..2).unwrap()[[0, 1, 2]], 32000.0);
+        let thumbnail = |mrc: &MrcFile| mrc.render_thumbnail(1, Contrast::MinMax).unwrap();
+        assert_eq!(thumbnail(&big), thumbnail(&little));
+    }
+
+    #[test]
+    fn test_cell_dims_offset() {
+        // Start indices at offset 16 must not be mistaken for the cell at offset 40
+        let mut bytes = header_bytes(4, 2, 1, 0);
+        for (i, v) in [3i32, 5, 7].iter().enumerate() {
+            bytes[16 + i * 4..20 + i * 4].copy_from_slice(&v.to_le_bytes());
+        }
+        bytes[40..44].copy_from_slice(&6.0f32.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 8]);
+        let path = write_temp("emfir_cell_dims.mrc", &bytes);
+
+        let mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mrc.header.cell_dims, [6.0, 2.0, 1.0]);
+        assert_eq!(mrc.get_image_data().voxel_spacing_x, 1.5);
+        assert_eq!(mrc.get_image_data().voxel_spacing_y, 1.0);
+    }
+
+    #[test]
+    fn test_fei_extended_header() {
+        // Two FEI2 records; each starts with its size and holds doubles at fixed offsets
+        let mut bytes = header_bytes(2, 2, 2, 2);
+        bytes[92..96].copy_from_slice(&(2 * 888i32).to_le_bytes());
+        bytes[104..108].copy_from_slice(b"FEI2");
+        for (section, tilt) in [-30.0f64, 30.0].iter().enumerate() {
+            let mut record = [0u8; 888];
+            record[0..4].copy_from_slice(&888i32.to_le_bytes());
+            for (offset, value) in [(84, 300e3), (92, 2e20), (100, *tilt), (220, -2e-6), (419, section as f64 + 1.0), (836, 3.0)] {
+                record[offset..offset + 8].copy_from_slice(&f64::to_le_bytes(value));
+            }
+            bytes.extend_from_slice(&record);
+        }
+        bytes.extend(std::iter::repeat_n(0u8, 2 * 2 * 2 * 4));
+        let path = write_temp("emfir_fei2.mrc", &bytes);
+
+        let mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mrc.header.data_offset(), 1024 + 2 * 888);
+        assert_eq!((mrc.header().nsymbt(), mrc.header().exttyp()), (2 * 888, b"FEI2"));
+        let extended_header = mrc.extended_header().unwrap();
+        assert_eq!(extended_header.exttyp(), b"FEI2");
+        assert_eq!(extended_header.frames().len(), 2);
+        let frames = mrc.fei_frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].a_tilt, frames[1].a_tilt), (-30.0, 30.0));
+        assert_eq!((frames[1].voltage, frames[1].defocus, frames[1].exposure_time), (300e3, -2e-6, 2.0));
+        assert_eq!(frames[1].tilt_per_image, Some(3.0));
+
+        let summary = mrc.get_image_data().tilt_series.as_ref().unwrap();
+        assert_eq!((summary.min_tilt, summary.max_tilt), (-30.0, 30.0));
+        assert_eq!((summary.total_dose, summary.total_exposure_time), (4e20, 3.0));
+
+        // FEI1 records end before the FEI2 fields, and must hold at least the FEI1 layout
+        for (record_size, opens) in [(768i32, true), (128, false)] {
+            let mut bytes = header_bytes(2, 2, 1, 2);
+            bytes[92..96].copy_from_slice(&768i32.to_le_bytes());
+            bytes[104..108].copy_from_slice(b"FEI1");
+            let mut record = [0u8; 768];
+            record[0..4].copy_from_slice(&record_size.to_le_bytes());
+            record[100..108].copy_from_slice(&12.5f64.to_le_bytes());
+            bytes.extend_from_slice(&record);
+            bytes.extend(std::iter::repeat_n(0u8, 2 * 2 * 4));
+            let path = write_temp("emfir_fei1.mrc", &bytes);
+            let result = MrcFile::open(&path);
+            std::fs::remove_file(&path).unwrap();
+            if opens {
+                let mrc = result.unwrap();
+                assert_eq!((mrc.fei_frames()[0].a_tilt, mrc.fei_frames()[0].tilt_per_image), (12.5, None));
             } else {
-                0.0
-            };
-            
-            let value = (normalized * 255.0) as u8;
-            *pixel = Rgb([value, value, value]);
+                assert!(matches!(result, Err(MrcError::Format(_))));
+            }
         }
-        
-        img.save(path).map_err(|e| MrcError::Io(io::Error::new(io::ErrorKind::Other, e)))?;
+    }
+
+    #[test]
+    fn test_export_ome_tiff() {
+        use tiff::decoder::{Decoder, DecodingResult};
+        use tiff::tags::Tag;
+
+        let mut bytes = header_bytes(3, 2, 2, 1);
+        for v in [-300i16, 0, 7, 1, 2, 3, 10, 20, 30, 40, 50, 32000] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let path = write_temp("emfir_ome.mrc", &bytes);
+        let out = std::env::temp_dir().join("emfir_ome.ome.tif").to_string_lossy().into_owned();
+        export_ome_tiff(&path, &out).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&out).unwrap()).unwrap();
+        let xml = decoder.get_tag_ascii_string(Tag::ImageDescription).unwrap();
+        for attribute in [
+            "Name=\"emfir_ome\"",
+            "DimensionOrder=\"XYZCT\"",
+            "Type=\"int16\"",
+            "SizeX=\"3\" SizeY=\"2\" SizeZ=\"2\"",
+            "PhysicalSizeX=\"1\" PhysicalSizeXUnit=\"&#197;\"",
+            "<TiffData IFD=\"0\" PlaneCount=\"2\"/>",
+        ] {
+            assert!(xml.contains(attribute), "{} missing from {}", attribute, xml);
+        }
+
+        let mut sections = Vec::new();
+        loop {
+            assert_eq!(decoder.dimensions().unwrap(), (3, 2));
+            match decoder.read_image().unwrap() {
+                DecodingResult::I16(values) => sections.push(values),
+                other => panic!("expected int16 pages, got {:?}", other),
+            }
+            if !decoder.more_images() {
+                break;
+            }
+            decoder.next_image().unwrap();
+        }
+        std::fs::remove_file(&out).unwrap();
+        assert_eq!(sections, vec![vec![-300, 0, 7, 1, 2, 3], vec![10, 20, 30, 40, 50, 32000]]);
+
+        // Complex data has no OME pixel type
+        let path = write_temp("emfir_ome_complex.mrc", &[header_bytes(1, 1, 1, 4), vec![0; 8]].concat());
+        let result = MrcFile::open(&path).unwrap().write_ome_tiff(&out);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(MrcError::Format(_))));
+
+        // An empty volume has no page to carry the XML, and no file is left behind
+        let path = write_temp("emfir_ome_empty.mrc", &[header_bytes(3, 2, 1, 1), vec![0; 12]].concat());
+        let mut mrc = MrcFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        mrc.header.nz = 0;
+        let result = mrc.write_ome_tiff(&out);
+        assert!(matches!(result, Err(MrcError::Format(_))));
+        assert!(!std::path::Path::new(&out).exists());
+    }
+
+    #[cfg(feature = "schema")]
+    #[test]
+    fn test_image_data_schema() {
+        let schema = serde_json::to_value(schemars::schema_for!(ImageData)).unwrap();
+        let required = schema["required"].as_array().unwrap();
+        assert!(required.contains(&"size_x".into()) && required.contains(&"density".into()));
+        // Skipped when absent, so optional
+        assert!(schema["properties"]["skew"].is_object() && !required.contains(&"skew".into()));
+    }
+
+    /// Runs a buffer through everything `MrcFile::open` does with the header bytes,
+    /// reading permissively so random bytes get past the stamp check
+    fn parse_untrusted(bytes: &[u8]) -> Result<(), MrcError> {
+        let mut cursor = Cursor::new(bytes);
+        let mut header = MrcHeader::read_permissive(&mut cursor)?;
+        if header.nz <= 0 {
+            header.infer_nz(bytes.len() as u64)?;
+        }
+        extended_header::read_fei_frames(&mut cursor, &header)?;
+        ImageData::from_mrc(&header);
+        header.nonstandard_fields();
         Ok(())
     }
+
+    proptest::proptest! {
+        #[test]
+        fn fuzz_header_random_bytes(bytes in proptest::collection::vec(proptest::num::u8::ANY, 0..1500)) {
+            let result = parse_untrusted(&bytes);
+            // Everything up to the label count is read unconditionally
+            if bytes.len() < 224 {
+                proptest::prop_assert!(result.is_err());
+            }
+        }
+
+        #[test]
+        fn fuzz_header_mutations(
+            mode in 0..7i32,
+            nsymbt in 0..2048i32,
+            patches in proptest::collection::vec((0..1024usize, proptest::num::u8::ANY), 0..24),
+            extra in 0..4096usize,
+        ) {
+            // Mostly valid headers get much further into the parser than random bytes
+            let mut bytes = header_bytes(4, 4, 2, mode);
+            bytes[92..96].copy_from_slice(&nsymbt.to_le_bytes());
+            bytes[104..108].copy_from_slice(b"FEI1");
+            for (offset, value) in patches {
+                bytes[offset] = value;
+            }
+            bytes.resize(1024 + extra, 0);
+            let _ = parse_untrusted(&bytes);
+        }
+    }
 }
@@ -0,0 +1,184 @@
+use byteorder::{ByteOrder, LittleEndian};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use tiff::encoder::colortype::{self, ColorType};
+use tiff::encoder::{TiffEncoder, TiffKind, TiffValue};
+use tiff::tags::Tag;
+use tiff::TiffError;
+
+use crate::{MrcError, MrcFile, MODE_FLOAT64};
+
+/// Volumes larger than this are written as BigTIFF, leaving room under the 4 GB
+/// offset limit of classic TIFF for the directories and the OME-XML
+const BIGTIFF_THRESHOLD: u64 = u32::MAX as u64 - (64 << 20);
+
+/// OME pixel type storing an MRC mode without conversion
+fn ome_pixel_type(mode: i32) -> Option<&'static str> {
+    match mode {
+        0 => Some("int8"),
+        1 => Some("int16"),
+        2 => Some("float"),
+        MODE_FLOAT64 => Some("double"),
+        6 => Some("uint16"),
+        _ => None,
+    }
+}
+
+fn tiff_error(err: TiffError) -> MrcError {
+    match err {
+        TiffError::IoError(e) => MrcError::Io(e),
+        e => MrcError::Io(io::Error::other(e)),
+    }
+}
+
+/// Escapes text for an XML attribute; non-ASCII becomes character references, as the
+/// TIFF ImageDescription tag only holds ASCII
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c if c.is_ascii() => escaped.push(c),
+            c => escaped.push_str(&format!("&#{};", c as u32)),
+        }
+    }
+    escaped
+}
+
+impl MrcFile {
+    /// OME-XML for the volume as written by [`MrcFile::write_ome_tiff`]
+    fn ome_xml(&self, pixel_type: &str) -> String {
+        let header = &self.header;
+        let name = Path::new(&self.path).file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+        // Pixel sizes of 0 mean unknown, which OME expresses by leaving them out, as is
+        // the Z size of an image stack
+        let physical_sizes: String = ["X", "Y", "Z"]
+            .iter()
+            .zip(header.pixel_size)
+            .filter(|(axis, size)| *size > 0.0 && !(**axis == "Z" && header.is_image_stack()))
+            .map(|(axis, size)| format!(" PhysicalSize{0}=\"{1}\" PhysicalSize{0}Unit=\"&#197;\"", axis, size))
+            .collect();
+        format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>",
+                "<OME xmlns=\"http://www.openmicroscopy.org/Schemas/OME/2016-06\"",
+                " xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\"",
+                " xsi:schemaLocation=\"http://www.openmicroscopy.org/Schemas/OME/2016-06",
+                " http://www.openmicroscopy.org/Schemas/OME/2016-06/ome.xsd\">",
+                "<Image ID=\"Image:0\" Name=\"{name}\">",
+                "<Pixels ID=\"Pixels:0\" DimensionOrder=\"XYZCT\" Type=\"{pixel_type}\"",
+                " SizeX=\"{nx}\" SizeY=\"{ny}\" SizeZ=\"{nz}\" SizeC=\"1\" SizeT=\"1\"{physical_sizes}>",
+                "<Channel ID=\"Channel:0:0\" SamplesPerPixel=\"1\"/>",
+                "<TiffData IFD=\"0\" PlaneCount=\"{nz}\"/>",
+                "</Pixels></Image></OME>",
+            ),
+            name = xml_escape(&name),
+            pixel_type = pixel_type,
+            nx = header.nx,
+            ny = header.ny,
+            nz = header.nz.max(0),
+            physical_sizes = physical_sizes,
+        )
+    }
+
+    /// Writes the volume as a single-channel OME-TIFF: one page per Z section in the
+    /// stored pixel type, with OME-XML on the first page giving the dimensions, pixel
+    /// type and pixel size. Volumes over 4 GB are written as BigTIFF. Modes without an
+    /// OME equivalent (complex, float16) are rejected, as are maps not stored in X, Y, Z
+    /// axis order and empty volumes, which would leave no page to hold the XML.
+    pub fn write_ome_tiff(&self, out: &str) -> Result<(), MrcError> {
+        let (Some(pixel_type), Some(sample_bytes)) = (ome_pixel_type(self.header.mode), self.header.bytes_per_sample())
+        else {
+            return Err(MrcError::Format(format!("Cannot write mode {} as OME-TIFF", self.header.mode)));
+        };
+        if !self.header.is_canonical_axes() {
+            return Err(MrcError::Format(format!(
+                "Cannot write axis order {:?} as OME-TIFF",
+                self.header.map_axis
+            )));
+        }
+        let (nx, ny, nz) = (self.header.nx, self.header.ny, self.header.nz);
+        if nx <= 0 || ny <= 0 || nz <= 0 {
+            return Err(MrcError::Format(format!("Cannot write an empty {}x{}x{} volume as OME-TIFF", nx, ny, nz)));
+        }
+        let xml = self.ome_xml(pixel_type);
+        let volume_bytes = nx as u64 * ny as u64 * nz as u64 * sample_bytes as u64;
+
+        let mut writer = BufWriter::new(File::create(out)?);
+        if volume_bytes > BIGTIFF_THRESHOLD {
+            self.write_ome_pages(TiffEncoder::new_big(&mut writer).map_err(tiff_error)?, &xml, sample_bytes)?;
+        } else {
+            self.write_ome_pages(TiffEncoder::new(&mut writer).map_err(tiff_error)?, &xml, sample_bytes)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn write_ome_pages<W: Write + Seek, K: TiffKind>(
+        &self,
+        mut encoder: TiffEncoder<W, K>,
+        xml: &str,
+        sample_bytes: usize,
+    ) -> Result<(), MrcError> {
+        let encoder = &mut encoder;
+        match self.header.mode {
+            0 => self.write_sections::<colortype::GrayI8, _, _>(encoder, xml, sample_bytes, |bytes, out| {
+                out.iter_mut().zip(bytes).for_each(|(v, &b)| *v = b as i8)
+            }),
+            1 => self.write_sections::<colortype::GrayI16, _, _>(encoder, xml, sample_bytes, LittleEndian::read_i16_into),
+            2 => self.write_sections::<colortype::Gray32Float, _, _>(encoder, xml, sample_bytes, LittleEndian::read_f32_into),
+            MODE_FLOAT64 => {
+                self.write_sections::<colortype::Gray64Float, _, _>(encoder, xml, sample_bytes, LittleEndian::read_f64_into)
+            }
+            _ => self.write_sections::<colortype::Gray16, _, _>(encoder, xml, sample_bytes, LittleEndian::read_u16_into),
+        }
+    }
+
+    /// Writes every Z section as a page of `C`, decoding the stored `sample_bytes`-byte
+    /// samples with `decode`
+    fn write_sections<C, W, K>(
+        &self,
+        encoder: &mut TiffEncoder<W, K>,
+        xml: &str,
+        sample_bytes: usize,
+        decode: impl Fn(&[u8], &mut [C::Inner]),
+    ) -> Result<(), MrcError>
+    where
+        C: ColorType,
+        C::Inner: Default + Clone,
+        [C::Inner]: TiffValue,
+        W: Write + Seek,
+        K: TiffKind,
+    {
+        let (nx, ny) = (self.header.nx.max(0) as u32, self.header.ny.max(0) as u32);
+        let count = nx as usize * ny as usize;
+        let mut bytes = vec![0u8; count * sample_bytes];
+        let mut section = vec![C::Inner::default(); count];
+
+        let mut reader = self.reader();
+        reader.seek(SeekFrom::Start(self.header.data_offset()))?;
+        for z in 0..self.header.nz.max(0) {
+            reader.read_exact(&mut bytes)?;
+            if self.header.is_big_endian() {
+                // Swap to the little-endian order `decode` reads
+                bytes.chunks_exact_mut(sample_bytes).for_each(<[u8]>::reverse);
+            }
+            decode(&bytes, &mut section);
+            let mut image = encoder.new_image::<C>(nx, ny).map_err(tiff_error)?;
+            if z == 0 {
+                image.encoder().write_tag(Tag::ImageDescription, xml).map_err(tiff_error)?;
+            }
+            image.write_data(&section).map_err(tiff_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the MRC file at `path` as an OME-TIFF at `out`; see [`MrcFile::write_ome_tiff`]
+pub fn export_ome_tiff(path: &str, out: &str) -> Result<(), MrcError> {
+    MrcFile::open(path)?.write_ome_tiff(out)
+}
@@ -0,0 +1,187 @@
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::BufReader;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{grayscale_image, write_png, Contrast, DownsampleMode, MrcError, MrcFile};
+use image::RgbImage;
+
+/// Progress callback, called with (slices processed, total slices).
+/// May be called from several threads at once.
+pub type Progress<'a> = &'a (dyn Fn(usize, usize) + Sync);
+
+/// Per-pixel reduction applied across all Z sections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectionMode {
+    Mean,
+    Max,
+    Sum,
+}
+
+impl FromStr for ProjectionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mean" => Ok(ProjectionMode::Mean),
+            "max" => Ok(ProjectionMode::Max),
+            "sum" => Ok(ProjectionMode::Sum),
+            _ => Err(format!("Invalid projection '{}': expected mean, max or sum", s)),
+        }
+    }
+}
+
+impl ProjectionMode {
+    fn identity(self) -> f64 {
+        match self {
+            ProjectionMode::Max => f64::NEG_INFINITY,
+            ProjectionMode::Mean | ProjectionMode::Sum => 0.0,
+        }
+    }
+
+    fn combine(self, acc: &mut [f64], values: impl Iterator<Item = f64>) {
+        match self {
+            ProjectionMode::Max => acc.iter_mut().zip(values).for_each(|(a, v)| *a = a.max(v)),
+            ProjectionMode::Mean | ProjectionMode::Sum => acc.iter_mut().zip(values).for_each(|(a, v)| *a += v),
+        }
+    }
+}
+
+impl MrcFile {
+    /// Computes the projection of all Z sections at full resolution, row-major (y, x).
+    ///
+    /// Sections are read and reduced in parallel, each rayon thread taking a contiguous
+    /// run of them with its own file handle and accumulating in f64 so integer sums
+    /// cannot overflow. `progress`
+    /// is called after every section; nothing is reported when it is `None`.
+    ///
+    /// When the per-thread copies would exceed the memory limit, sections are instead
+    /// read one at a time into a single accumulator. Maps with a non-canonical axis order
+    /// are read whole with [`MrcFile::read_volume`] and projected along Z.
+    pub fn project(&self, mode: ProjectionMode, progress: Option<Progress>) -> Result<Vec<f64>, MrcError> {
+        if !self.header.is_canonical_axes() {
+            return self.project_volume(mode, progress);
+        }
+        let nz = self.header.nz.max(0) as usize;
+        let count = self.header.nx as usize * self.header.ny as usize;
+        // One f32 section and one f64 accumulator per worker, plus the reduced result
+        let per_worker = count * (4 + 8);
+        self.check_memory(per_worker, "the section size alone exceeds the limit")?;
+        let parallel = self.memory_limit.is_none_or(|limit| {
+            per_worker * (rayon::current_num_threads() + 1) <= limit
+        });
+
+        let projection = if parallel {
+            self.project_parallel(mode, nz, count, progress)?
+        } else {
+            let mut acc = vec![mode.identity(); count];
+            let mut reader = self.reader();
+            for z in 0..nz {
+                let slice = MrcFile::read_slice_f32(&mut *reader, &self.header, z)?;
+                mode.combine(&mut acc, slice.into_iter().map(f64::from));
+                if let Some(progress) = progress {
+                    progress(z + 1, nz);
+                }
+            }
+            acc
+        };
+
+        Ok(match mode {
+            ProjectionMode::Mean if nz > 0 => projection.into_iter().map(|v| v / nz as f64).collect(),
+            _ => projection,
+        })
+    }
+
+    fn project_volume(&self, mode: ProjectionMode, progress: Option<Progress>) -> Result<Vec<f64>, MrcError> {
+        let volume = self.read_volume()?;
+        let (nz, ny, nx) = volume.dim();
+        let mut acc = vec![mode.identity(); ny * nx];
+        for (z, slice) in volume.outer_iter().enumerate() {
+            mode.combine(&mut acc, slice.iter().map(|&v| f64::from(v)));
+            if let Some(progress) = progress {
+                progress(z + 1, nz);
+            }
+        }
+        Ok(match mode {
+            ProjectionMode::Mean if nz > 0 => acc.into_iter().map(|v| v / nz as f64).collect(),
+            _ => acc,
+        })
+    }
+
+    fn project_parallel(
+        &self,
+        mode: ProjectionMode,
+        nz: usize,
+        count: usize,
+        progress: Option<Progress>,
+    ) -> Result<Vec<f64>, MrcError> {
+        let done = AtomicUsize::new(0);
+        // One contiguous run of sections per thread, so there are only as many
+        // accumulators as the memory check allowed for, however rayon splits the work
+        let workers = rayon::current_num_threads().clamp(1, nz.max(1));
+        (0..workers)
+            .into_par_iter()
+            .map(|worker| {
+                let mut reader = BufReader::new(File::open(&self.path)?);
+                let mut acc = vec![mode.identity(); count];
+                for z in worker * nz / workers..(worker + 1) * nz / workers {
+                    let slice = MrcFile::read_slice_f32(&mut reader, &self.header, z)?;
+                    mode.combine(&mut acc, slice.into_iter().map(f64::from));
+
+                    let processed = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    if let Some(progress) = progress {
+                        progress(processed, nz);
+                    }
+                }
+                Ok::<_, MrcError>(acc)
+            })
+            .try_reduce_with(|mut a, b| {
+                mode.combine(&mut a, b.into_iter());
+                Ok(a)
+            })
+            .unwrap_or_else(|| Ok(vec![mode.identity(); count]))
+    }
+
+    /// Renders a contrast-stretched, downsampled projection
+    pub fn render_projection(
+        &self,
+        mode: ProjectionMode,
+        downsample: u32,
+        contrast: Contrast,
+        sampling: DownsampleMode,
+        progress: Option<Progress>,
+    ) -> Result<RgbImage, MrcError> {
+        if downsample == 0 {
+            return Err(MrcError::Format("Downsampling factor must be at least 1".to_string()));
+        }
+        let projection: Vec<f32> = self.project(mode, progress)?.into_iter().map(|v| v as f32).collect();
+        let [nx, ny, _] = self.header.dims().map(|n| n as u32);
+        let thumb_width = nx.div_ceil(downsample);
+        let thumb_height = ny.div_ceil(downsample);
+
+        let downsampled = sampling.apply(&projection, nx as usize, downsample as usize);
+        let (min_val, max_val) = contrast.limits(&downsampled);
+        Ok(grayscale_image(&downsampled, thumb_width, thumb_height, min_val, max_val))
+    }
+
+    /// Saves a PNG of the Z projection. A `path` of `-` writes the PNG to stdout.
+    pub fn save_projection(&self, path: &str, mode: ProjectionMode, downsample: u32) -> Result<(), MrcError> {
+        self.save_projection_with_progress(path, mode, downsample, Contrast::MinMax, DownsampleMode::Nearest, None)
+    }
+
+    /// Like [`MrcFile::save_projection`], with contrast and downsampling modes and
+    /// reporting sections processed to `progress`
+    pub fn save_projection_with_progress(
+        &self,
+        path: &str,
+        mode: ProjectionMode,
+        downsample: u32,
+        contrast: Contrast,
+        sampling: DownsampleMode,
+        progress: Option<Progress>,
+    ) -> Result<(), MrcError> {
+        let img = self.render_projection(mode, downsample, contrast, sampling, progress)?;
+        write_png(&img, path)
+    }
+}
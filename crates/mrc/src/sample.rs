@@ -0,0 +1,73 @@
+use byteorder::{ByteOrder, ReadBytesExt};
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// One stored sample type, read as f32 in byte order `B`. Adding a mode means adding an impl here and
+/// an arm in [`crate::MrcFile::render_thumbnail`].
+pub(crate) trait SampleReader {
+    fn read<B: ByteOrder, R: Read>(reader: &mut R) -> io::Result<f32>;
+
+    /// Reads the sample at byte `offset`
+    fn read_at<B: ByteOrder, R: Read + Seek>(reader: &mut R, offset: u64) -> io::Result<f32> {
+        reader.seek(SeekFrom::Start(offset))?;
+        Self::read::<B, R>(reader)
+    }
+}
+
+/// Mode 0, signed 8-bit
+pub(crate) struct Int8;
+/// Mode 1, signed 16-bit
+pub(crate) struct Int16;
+/// Mode 2, 32-bit float
+pub(crate) struct Float32;
+/// Mode 5, 64-bit float (nonstandard)
+pub(crate) struct Float64;
+/// Mode 6, unsigned 16-bit
+pub(crate) struct UInt16;
+/// Mode 3, complex 16-bit integer, read as the modulus
+pub(crate) struct Complex16;
+/// Mode 4, complex 32-bit float, read as the modulus
+pub(crate) struct Complex32;
+
+impl SampleReader for Int8 {
+    fn read<B: ByteOrder, R: Read>(reader: &mut R) -> io::Result<f32> {
+        Ok(reader.read_i8()? as f32)
+    }
+}
+
+impl SampleReader for Int16 {
+    fn read<B: ByteOrder, R: Read>(reader: &mut R) -> io::Result<f32> {
+        Ok(reader.read_i16::<B>()? as f32)
+    }
+}
+
+impl SampleReader for Float32 {
+    fn read<B: ByteOrder, R: Read>(reader: &mut R) -> io::Result<f32> {
+        reader.read_f32::<B>()
+    }
+}
+
+impl SampleReader for Float64 {
+    fn read<B: ByteOrder, R: Read>(reader: &mut R) -> io::Result<f32> {
+        Ok(reader.read_f64::<B>()? as f32)
+    }
+}
+
+impl SampleReader for UInt16 {
+    fn read<B: ByteOrder, R: Read>(reader: &mut R) -> io::Result<f32> {
+        Ok(reader.read_u16::<B>()? as f32)
+    }
+}
+
+impl SampleReader for Complex16 {
+    fn read<B: ByteOrder, R: Read>(reader: &mut R) -> io::Result<f32> {
+        let (re, im) = (reader.read_i16::<B>()? as f32, reader.read_i16::<B>()? as f32);
+        Ok(re.hypot(im))
+    }
+}
+
+impl SampleReader for Complex32 {
+    fn read<B: ByteOrder, R: Read>(reader: &mut R) -> io::Result<f32> {
+        let (re, im) = (reader.read_f32::<B>()?, reader.read_f32::<B>()?);
+        Ok(re.hypot(im))
+    }
+}
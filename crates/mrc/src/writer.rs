@@ -0,0 +1,181 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use ndarray::Axis;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::{MrcError, MrcFile};
+
+/// Everything needed to write a standard MRC2014 header
+pub(crate) struct HeaderFields {
+    pub dims: [usize; 3],
+    pub mode: i32,
+    /// Pixel size in Angstroms along x, y, z
+    pub pixel_size: [f32; 3],
+    /// Space group: 0 for images and image stacks, 1 for a volume
+    pub ispg: i32,
+    pub label: String,
+}
+
+/// Density statistics for the header
+struct Stats {
+    min: f32,
+    max: f32,
+    mean: f32,
+    rms: f32,
+}
+
+impl Stats {
+    fn of(values: &[f32]) -> Stats {
+        let n = values.len().max(1) as f64;
+        let mean = values.iter().map(|&v| v as f64).sum::<f64>() / n;
+        let variance = values.iter().map(|&v| (v as f64 - mean).powi(2)).sum::<f64>() / n;
+        Stats {
+            min: values.iter().copied().fold(f32::INFINITY, f32::min),
+            max: values.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            mean: mean as f32,
+            rms: variance.sqrt() as f32,
+        }
+    }
+}
+
+/// Encodes a little-endian 1024-byte header with no extended header
+fn encode_header(fields: &HeaderFields, stats: &Stats) -> Result<Vec<u8>, MrcError> {
+    let mut out = Vec::with_capacity(1024);
+    for n in fields.dims {
+        out.write_i32::<LittleEndian>(n as i32)?;
+    }
+    out.write_i32::<LittleEndian>(fields.mode)?;
+    // nxstart, nystart, nzstart
+    for _ in 0..3 {
+        out.write_i32::<LittleEndian>(0)?;
+    }
+    // mx, my, mz: one grid step per voxel
+    for n in fields.dims {
+        out.write_i32::<LittleEndian>(n as i32)?;
+    }
+    for (n, size) in fields.dims.iter().zip(fields.pixel_size) {
+        out.write_f32::<LittleEndian>(*n as f32 * size)?;
+    }
+    for _ in 0..3 {
+        out.write_f32::<LittleEndian>(90.0)?;
+    }
+    for axis in [1, 2, 3] {
+        out.write_i32::<LittleEndian>(axis)?;
+    }
+    out.write_f32::<LittleEndian>(stats.min)?;
+    out.write_f32::<LittleEndian>(stats.max)?;
+    out.write_f32::<LittleEndian>(stats.mean)?;
+    out.write_i32::<LittleEndian>(fields.ispg)?;
+    // nsymbt
+    out.write_i32::<LittleEndian>(0)?;
+    // extra: 8 unused bytes, exttyp, nversion, then padding up to the origin at 196
+    out.resize(108, 0);
+    out.write_i32::<LittleEndian>(20140)?;
+    out.resize(208, 0);
+    out.extend_from_slice(b"MAP ");
+    // Little-endian machine stamp
+    out.extend_from_slice(&[0x44, 0x44, 0, 0]);
+    out.write_f32::<LittleEndian>(stats.rms)?;
+    out.write_i32::<LittleEndian>(1)?;
+
+    let mut label = [b' '; 80];
+    let text = fields.label.as_bytes();
+    label[..text.len().min(80)].copy_from_slice(&text[..text.len().min(80)]);
+    out.extend_from_slice(&label);
+    out.resize(1024, 0);
+    Ok(out)
+}
+
+/// Converts values to the output mode, rounding and clamping integer modes
+fn encode_values(values: &[f32], mode: i32) -> Result<Vec<u8>, MrcError> {
+    let mut out = Vec::with_capacity(values.len() * 4);
+    for &v in values {
+        match mode {
+            0 => out.write_i8(v.round().clamp(i8::MIN as f32, i8::MAX as f32) as i8)?,
+            1 => out.write_i16::<LittleEndian>(v.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16)?,
+            2 => out.write_f32::<LittleEndian>(v)?,
+            6 => out.write_u16::<LittleEndian>(v.round().clamp(0.0, u16::MAX as f32) as u16)?,
+            _ => return Err(MrcError::Format(format!("Cannot write mode {}", mode))),
+        }
+    }
+    Ok(out)
+}
+
+/// Stored value of `v` in `mode`, so the header stats match the data
+fn quantize(v: f32, mode: i32) -> f32 {
+    match mode {
+        0 => v.round().clamp(i8::MIN as f32, i8::MAX as f32),
+        1 => v.round().clamp(i16::MIN as f32, i16::MAX as f32),
+        6 => v.round().clamp(0.0, u16::MAX as f32),
+        _ => v,
+    }
+}
+
+/// Writes a header and row-major voxel data as an MRC file
+pub(crate) fn write_mrc(path: &str, fields: &HeaderFields, values: &[f32]) -> Result<(), MrcError> {
+    let values: Vec<f32> = values.iter().map(|&v| quantize(v, fields.mode)).collect();
+    let data = encode_values(&values, fields.mode)?;
+    let header = encode_header(fields, &Stats::of(&values))?;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&header)?;
+    writer.write_all(&data)?;
+    writer.flush()?;
+    Ok(())
+}
+
+impl MrcFile {
+    /// Writes a copy of the volume binned by `factor` along x, y and z, averaging each
+    /// block (edge blocks average the voxels they cover). The output is stored in `mode`
+    /// (0, 1, 2 or 6) with the pixel size scaled by `factor` and fresh density statistics.
+    pub fn write_downsampled(&self, path: &str, factor: usize, mode: i32) -> Result<(), MrcError> {
+        if factor == 0 {
+            return Err(MrcError::Format("Downsampling factor must be at least 1".to_string()));
+        }
+        let [nx, ny, nz] = self.header.dims().map(|n| n.max(0) as usize);
+        let (out_x, out_y, out_z) = (nx.div_ceil(factor), ny.div_ceil(factor), nz.div_ceil(factor));
+        // f64 sums, u32 counts and the f32 output, plus one input section
+        self.check_memory(
+            out_x * out_y * out_z * (8 + 4 + 4) + nx * ny * 4,
+            "use a larger downsampling factor",
+        )?;
+
+        // Maps with a non-canonical axis order are rearranged into X, Y, Z order up front
+        let volume = match self.header.is_canonical_axes() {
+            true => None,
+            false => Some(self.read_volume()?),
+        };
+
+        let mut sums = vec![0f64; out_x * out_y * out_z];
+        let mut counts = vec![0u32; sums.len()];
+        {
+            let mut reader = self.reader();
+            for z in 0..nz {
+                let slice = match &volume {
+                    Some(volume) => volume.index_axis(Axis(0), z).iter().copied().collect(),
+                    None => MrcFile::read_slice_f32(&mut *reader, &self.header, z)?,
+                };
+                let plane = (z / factor) * out_x * out_y;
+                for (i, &v) in slice.iter().enumerate() {
+                    let out = plane + (i / nx / factor) * out_x + (i % nx) / factor;
+                    sums[out] += v as f64;
+                    counts[out] += 1;
+                }
+            }
+        }
+        let values: Vec<f32> = sums
+            .iter()
+            .zip(&counts)
+            .map(|(&sum, &count)| (sum / count.max(1) as f64) as f32)
+            .collect();
+
+        let fields = HeaderFields {
+            dims: [out_x, out_y, out_z],
+            mode,
+            pixel_size: self.header.pixel_size.map(|size| size * factor as f32),
+            ispg: self.header.ispg,
+            label: format!("emfir: downsampled by {}", factor),
+        };
+        write_mrc(path, &fields, &values)
+    }
+}